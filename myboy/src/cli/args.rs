@@ -1,9 +1,13 @@
-use std::path::PathBuf;
+use std::{ops::RangeInclusive, path::PathBuf};
 
 use clap::{Parser, Subcommand};
 use clap_num::maybe_hex;
 
-use crate::logging::log::LogOutput;
+use crate::{debugger::history::DEFAULT_HISTORY_LENGTH, logging::log::LogOutput};
+
+/// Two minutes, long enough for the blargg suites
+const DEFAULT_TEST_FRAMES: u64 = 7200;
+const DEFAULT_TEST_TIMEOUT: u64 = 120;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -16,23 +20,232 @@ pub struct Cli {
 
     #[arg(long)]
     pub disable_logtypes: Option<Vec<LogOutput>>,
+
+    /// Write a Gameboy Doctor compatible CPU trace to this file
+    #[arg(long, global = true)]
+    pub trace: Option<PathBuf>,
+
+    /// Only trace instructions in this address range, as START-END (hex)
+    #[arg(long, global = true, value_parser = parse_address_range, requires = "trace")]
+    pub trace_range: Option<RangeInclusive<u16>>,
+
+    /// Only trace instructions running from this ROM bank
+    #[arg(long, global = true, value_parser = maybe_hex::<u16>, requires = "trace")]
+    pub trace_bank: Option<u16>,
+
+    /// Start tracing once PC reaches this address
+    #[arg(long, global = true, value_parser = maybe_hex::<u16>, requires = "trace")]
+    pub trace_start_pc: Option<u16>,
+
+    /// Stop tracing once PC reaches this address
+    #[arg(long, global = true, value_parser = maybe_hex::<u16>, requires = "trace")]
+    pub trace_stop_pc: Option<u16>,
+
+    /// Write a "Label:" line before every traced instruction at a label
+    #[arg(long, global = true, requires = "trace")]
+    pub trace_labels: bool,
+
+    /// An RGBDS symbol file with the labels of the ROM, defaults to the .sym file next to it
+    #[arg(long, global = true)]
+    pub symbols: Option<PathBuf>,
+
+    /// Where the last executed instructions are written to if the emulation aborts
+    #[arg(long, global = true, default_value = "bricoboy-history.log")]
+    pub history_file: PathBuf,
+
+    /// The number of executed instructions kept in the history
+    #[arg(long, global = true, default_value_t = DEFAULT_HISTORY_LENGTH)]
+    pub history_length: usize,
 }
 
 #[derive(Subcommand, Debug, Clone)]
 pub enum Commands {
+    /// Play a ROM. Headless runs end with exit code 0 when an --until condition is met
+    /// or a limit is reached without one, 2 when a limit is reached before it and 3 on timeout
     Play {
         #[arg(long)]
         headless: bool,
 
+        /// Stop after this many frames of emulated time
+        #[arg(long, requires = "headless")]
+        frames: Option<u64>,
+
+        /// Stop after this many T-cycles
+        #[arg(long, requires = "headless")]
+        cycles: Option<u64>,
+
+        /// Stop when PC reaches ADDR (hex) or a label
+        #[arg(long, requires = "headless")]
+        until_pc: Option<String>,
+
+        /// Stop when the serial output contains this text, e.g. "Passed"
+        #[arg(long, requires = "headless")]
+        until_serial: Option<String>,
+
+        /// Stop after this many seconds of wall-clock time
+        #[arg(long, requires = "headless")]
+        timeout: Option<u64>,
+
+        /// Run as fast as possible instead of at the speed of the real hardware
+        #[arg(long, requires = "headless")]
+        unthrottled: bool,
+
+        /// Write the LCD to this PNG file when the run ends
+        #[arg(long, requires = "headless")]
+        screenshot: Option<PathBuf>,
+
+        /// Write hashes of the screen, audio and memory every --hash-interval frames to this file,
+        /// - for stdout
+        #[arg(long, requires = "headless")]
+        frame_hashes: Option<PathBuf>,
+
+        /// Check the hashes against a list written before, stopping at the first difference.
+        /// Frames of the list the run does not reach are an error as well
+        #[arg(long, requires = "headless")]
+        check_hashes: Option<PathBuf>,
+
+        /// The frames between two hashes
+        #[arg(long, default_value_t = 1)]
+        hash_interval: u64,
+
+        /// Record the joypad input to this movie file, from power-on
+        #[arg(long, conflicts_with = "movie")]
+        record: Option<PathBuf>,
+
+        /// Start the game from this save state, a recording is branched off it
+        #[arg(long)]
+        from_state: Option<PathBuf>,
+
+        /// Play the input of this movie file
+        #[arg(long)]
+        movie: Option<PathBuf>,
+
+        /// Let loading a save state during playback branch the movie, recording from there
+        #[arg(long, requires = "movie")]
+        read_write: bool,
+
         #[arg(action = clap::ArgAction::Append)]
         file: PathBuf,
     },
 
     Debug {
-        #[arg(short, long, value_parser = maybe_hex::<u16>)]
-        breakpoint: Option<u16>,
+        /// Break at ADDR, BANK:ADDR or a label, or watch memory with r:/w:/rw:START[-END] (hex),
+        /// optionally followed by a condition, e.g. "0150 if A == 0x3F && [HL] > 2"
+        #[arg(short, long)]
+        breakpoint: Vec<String>,
+
+        /// Debug on the terminal instead of in the debugger window
+        #[arg(long)]
+        repl: bool,
+
+        #[arg(action = clap::ArgAction::Append)]
+        file: PathBuf,
+    },
+
+    /// Print the disassembly of a ROM in RGBDS syntax
+    Disasm {
+        /// The ROM bank mapped to 0x4000-0x7FFF, only this bank is printed if no range is given
+        #[arg(long, value_parser = maybe_hex::<u16>)]
+        bank: Option<u16>,
+
+        #[arg(long, value_parser = maybe_hex::<u16>)]
+        start: Option<u16>,
+
+        #[arg(long, value_parser = maybe_hex::<u16>)]
+        end: Option<u16>,
+
+        #[arg(action = clap::ArgAction::Append)]
+        file: PathBuf,
+    },
+
+    /// Export a ROM as RGBDS sources that rebuild it byte for byte
+    Export {
+        /// The directory the project is written to
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Additional code entry point, as BANK:ADDRESS (hex)
+        #[arg(long, value_parser = parse_bank_address)]
+        entry: Vec<(u16, u16)>,
+
+        /// A table of jump targets, as BANK:ADDRESS:COUNT (hex)
+        #[arg(long, value_parser = parse_jump_table)]
+        jump_table: Vec<(u16, u16, u16)>,
 
         #[arg(action = clap::ArgAction::Append)]
         file: PathBuf,
     },
+
+    /// Run the test ROMs in a directory in parallel and report which pass
+    Test {
+        /// A file with a "ROM CHECK..." line per ROM, e.g. "div_timing.gb mooneye",
+        /// with checks serial=TEXT, fail=TEXT, mooneye, screenshot=PNG, frames=N, timeout=N,
+        /// tolerance=N and skip
+        #[arg(short, long)]
+        expectations: Option<PathBuf>,
+
+        /// Write a JUnit XML report to this file
+        #[arg(long)]
+        junit: Option<PathBuf>,
+
+        /// The number of ROMs run at the same time, the number of CPUs by default
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// The frames of emulated time a ROM runs at most, screenshots are compared after them
+        #[arg(long, default_value_t = DEFAULT_TEST_FRAMES)]
+        frames: u64,
+
+        /// The seconds of wall-clock time a ROM runs at most
+        #[arg(long, default_value_t = DEFAULT_TEST_TIMEOUT)]
+        timeout: u64,
+
+        /// How much a color channel may differ from a reference screenshot, 0 for an exact match.
+        /// The differing pixels are shown in red in a .diff.png next to the reference
+        #[arg(long, default_value_t = 0)]
+        tolerance: u8,
+
+        #[arg(action = clap::ArgAction::Append)]
+        directory: PathBuf,
+    },
+
+    /// Run a ROM headless and compare its CPU trace with a reference trace
+    TraceDiff {
+        /// A Gameboy Doctor style trace of a known-good emulator
+        #[arg(short, long)]
+        reference: PathBuf,
+
+        /// The number of matching lines printed before the mismatch
+        #[arg(long, default_value_t = 10)]
+        context: usize,
+
+        #[arg(action = clap::ArgAction::Append)]
+        file: PathBuf,
+    },
+}
+
+fn parse_hex(value: &str) -> Result<u16, String> {
+    let digits = value.trim_start_matches("0x").trim_start_matches('$');
+    u16::from_str_radix(digits, 16).map_err(|e| format!("Invalid hex value '{}': {}", value, e))
+}
+
+fn parse_address_range(value: &str) -> Result<RangeInclusive<u16>, String> {
+    match value.split('-').collect::<Vec<&str>>()[..] {
+        [start, end] => Ok(parse_hex(start)?..=parse_hex(end)?),
+        _ => Err(format!("Expected START-END, got '{}'", value)),
+    }
+}
+
+fn parse_bank_address(value: &str) -> Result<(u16, u16), String> {
+    match value.split(':').collect::<Vec<&str>>()[..] {
+        [bank, address] => Ok((parse_hex(bank)?, parse_hex(address)?)),
+        _ => Err(format!("Expected BANK:ADDRESS, got '{}'", value)),
+    }
+}
+
+fn parse_jump_table(value: &str) -> Result<(u16, u16, u16), String> {
+    match value.split(':').collect::<Vec<&str>>()[..] {
+        [bank, address, count] => Ok((parse_hex(bank)?, parse_hex(address)?, parse_hex(count)?)),
+        _ => Err(format!("Expected BANK:ADDRESS:COUNT, got '{}'", value)),
+    }
 }
@@ -0,0 +1,504 @@
+use std::{
+    path::Path,
+    process,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use mygbcartridge::cartridge::Cartridge;
+
+use crate::{
+    debugger::{
+        breakpoints::{Breakpoint, parse_address},
+        history::ExecutionHistory,
+        repl::Repl,
+        reverse::checkpoints,
+    },
+    device::{
+        device::Device,
+        frame_hashes::FrameHashes,
+        headless::{StopConditions, StopReason},
+        movie::Movie,
+    },
+    disasm::{
+        disassembler::Disassembler, project::write_project, rgbds::format_listing_line,
+        symbols::SymbolTable, tracer::CodeTracer,
+    },
+    logging::{
+        log::{ConsoleLogger, Log, Logger},
+        trace::{TraceFilter, TraceLogger},
+        trace_diff::{TraceComparison, TraceDiffLogger, TraceDiffResult},
+    },
+    screen::open_gamescreen,
+    testing::{
+        expectations::Expectations,
+        report::{Totals, format_summary, write_junit},
+        runner::{Limits, find_roms, run_tests},
+    },
+    ui::app::AppTemplate,
+};
+
+use super::args::{Cli, Commands};
+
+fn create_default_logger(cli: &Cli) -> Box<dyn Logger> {
+    let disabled_logtypes = &cli.disable_logtypes.clone();
+    let mut logger = Box::new(ConsoleLogger::default());
+
+    if let Some(disabled_logtypes) = disabled_logtypes {
+        logger.set_disabled_outputs(disabled_logtypes.clone());
+    }
+
+    logger
+}
+
+/**
+ * The symbols given on the command line, or else the ones next to the ROM, if there are any.
+ */
+fn load_symbols(cli: &Cli, file: &Path) -> Result<Arc<SymbolTable>, String> {
+    let path = match &cli.symbols {
+        Some(path) => path.clone(),
+        None => SymbolTable::path_for_rom(file),
+    };
+    if cli.symbols.is_none() && !path.exists() {
+        return Ok(Arc::default());
+    }
+
+    let symbols = SymbolTable::load(&path)?;
+    create_default_logger(cli).info(Log::Msg(format!(
+        "Loaded {} symbols from {}",
+        symbols.len(),
+        path.display()
+    )));
+
+    Ok(Arc::new(symbols))
+}
+
+/**
+ * The logger used by the device, which sends the CPU states to the trace file, if requested.
+ */
+fn create_device_logger(cli: &Cli, symbols: &Arc<SymbolTable>) -> Result<Box<dyn Logger>, String> {
+    let logger = create_default_logger(cli);
+
+    match &cli.trace {
+        Some(path) => {
+            let filter = TraceFilter {
+                address_range: cli.trace_range.clone(),
+                bank: cli.trace_bank,
+                start_pc: cli.trace_start_pc,
+                stop_pc: cli.trace_stop_pc,
+            };
+
+            let labels = cli.trace_labels.then(|| symbols.clone());
+
+            Ok(Box::new(TraceLogger::new(
+                path.as_path(),
+                filter,
+                labels,
+                logger,
+            )?))
+        }
+        None => Ok(logger),
+    }
+}
+
+fn create_device(cli: &Cli, file: &Path) -> Result<Device, String> {
+    let symbols = load_symbols(cli, file)?;
+    let cartridge = Cartridge::new(file);
+    let mut device = Device::new(cartridge);
+    device.logger = create_device_logger(cli, &symbols)?;
+    device.history = ExecutionHistory::new(cli.history_length);
+    device.history_file = Some(cli.history_file.clone());
+    device.rom_file = Some(file.to_path_buf());
+    device.symbols = symbols;
+
+    Ok(device)
+}
+
+fn create_default_device(cli: Cli) -> Result<Device, String> {
+    let command = &cli.command.as_ref().ok_or_else(|| "No command provided")?;
+    match command {
+        Commands::Play {
+            file,
+            record,
+            from_state,
+            movie,
+            read_write,
+            ..
+        } => {
+            let mut device = create_device(&cli, file.as_path())?;
+
+            if let Some(state) = from_state {
+                device.load_state_from(state)?;
+            }
+            if let Some(path) = record {
+                device.record_movie(path, from_state.is_none())?;
+            }
+            if let Some(path) = movie {
+                device.play_movie(Movie::load(path)?, !read_write)?;
+            }
+
+            Ok(device)
+        }
+        Commands::Debug {
+            file, breakpoint, ..
+        } => {
+            let mut device = create_device(&cli, file.as_path())?;
+
+            for spec in breakpoint {
+                let breakpoint = Breakpoint::parse(spec, &device.symbols)
+                    .map_err(|e| format!("Invalid breakpoint '{}': {}", spec, e))?;
+                device.add_breakpoint(breakpoint);
+            }
+
+            Ok(device)
+        }
+        Commands::Disasm { .. }
+        | Commands::Export { .. }
+        | Commands::Test { .. }
+        | Commands::TraceDiff { .. } => Err("The command does not run a device".to_string()),
+    }
+}
+
+/**
+ * Logs the start of the emulator and creates the device for the commands running one.
+ */
+fn start_device(cli: Cli) -> Result<(Box<dyn Logger>, Device), String> {
+    let mut logger = create_default_logger(&cli);
+
+    logger.info(Log::Msg(
+        "Starting MyBoy Gameboy Emulator".to_string(),
+    ));
+
+    let device = create_default_device(cli)?;
+
+    // // As this is in ogni case eternal
+    // let device = Box::new(device);
+
+    Ok((logger, device))
+}
+
+/// Runs the command given on the command line
+pub fn run(cli: Cli) -> Result<(), String> {
+    let command = cli.command.clone().ok_or("No command provided")?;
+
+    match command {
+        // the disassembly goes to stdout, so nothing else should be printed
+        Commands::Disasm {
+            file,
+            bank,
+            start,
+            end,
+        } => run_disassembler(file.as_path(), bank, start, end),
+        Commands::Export {
+            output,
+            entry,
+            jump_table,
+            file,
+        } => run_export(file.as_path(), output.as_path(), &entry, &jump_table),
+        Commands::Test {
+            expectations,
+            junit,
+            jobs,
+            frames,
+            timeout,
+            tolerance,
+            directory,
+        } => {
+            let limits = Limits {
+                frames,
+                timeout: Duration::from_secs(timeout),
+                tolerance,
+            };
+            run_test_suite(
+                directory.as_path(),
+                expectations.as_deref(),
+                junit.as_deref(),
+                jobs,
+                limits,
+            )
+        }
+        Commands::TraceDiff {
+            reference,
+            context,
+            file,
+        } => run_trace_diff(&cli, file.as_path(), reference.as_path(), context),
+        Commands::Play {
+            headless,
+            frames,
+            cycles,
+            until_pc,
+            until_serial,
+            timeout,
+            unthrottled,
+            screenshot,
+            frame_hashes,
+            check_hashes,
+            hash_interval,
+            ..
+        } => {
+            let (mut logger, device) = start_device(cli)?;
+
+            if headless {
+                logger.info(Log::Msg(
+                    "Running device in headless mode".to_string(),
+                ));
+                let mut device = device;
+                device.throttle = !unthrottled;
+                if frame_hashes.is_some() || check_hashes.is_some() {
+                    device.frame_hashes = Some(FrameHashes::new(
+                        hash_interval,
+                        frame_hashes.as_deref(),
+                        check_hashes.as_deref(),
+                    )?);
+                }
+                let conditions = StopConditions {
+                    frames,
+                    cycles,
+                    until_pc: until_pc
+                        .map(|spec| parse_address(&spec, &device.symbols))
+                        .transpose()?,
+                    until_serial,
+                    timeout: timeout.map(Duration::from_secs),
+                };
+                let reason = run_device_headless(device, &conditions, screenshot.as_deref())
+                    .map_err(|e| format!("Failed to run device in headless mode: {}", e))?;
+
+                if let Some(reason) = reason {
+                    logger.info(Log::Msg(format!("Stopped, {}", reason)));
+                    let code = reason.exit_code(&conditions);
+                    if code != 0 {
+                        process::exit(code);
+                    }
+                }
+                Ok(())
+            } else {
+                let _ = open_gamescreen(device)
+                    .map_err(|e| format!("Failed to open game screen: {}", e))?;
+
+                Ok(())
+            }
+        }
+        Commands::Debug { repl, .. } => {
+            let (_, mut device) = start_device(cli)?;
+            device.rewind = Some(checkpoints());
+            if repl {
+                return Repl::new(&mut device).run();
+            }
+
+            open_native_app(device).map_err(|e| format!("Failed to open native app: {}", e))
+        }
+    }
+}
+
+fn run_disassembler(
+    file: &Path,
+    bank: Option<u16>,
+    start: Option<u16>,
+    end: Option<u16>,
+) -> Result<(), String> {
+    let cartridge = Cartridge::new(file);
+    let bank_count = cartridge.get_rom_bank_count();
+
+    if let Some(bank) = bank
+        && bank >= bank_count
+    {
+        return Err(format!(
+            "Invalid bank {}, the cartridge only has {} banks",
+            bank, bank_count
+        ));
+    }
+
+    let (default_start, default_end) = match bank {
+        Some(0) => (0x0000, 0x3FFF),
+        Some(_) => (0x4000, 0x7FFF),
+        None => (0x0000, 0x7FFF),
+    };
+    let start = start.unwrap_or(default_start);
+    let end = end.unwrap_or(default_end);
+
+    if start > end || end > 0x7FFF {
+        return Err(format!(
+            "Invalid address range 0x{:04X}-0x{:04X}, must be within ROM (0x0000-0x7FFF)",
+            start, end
+        ));
+    }
+
+    let disassembler = Disassembler::new(&cartridge, bank.unwrap_or(1));
+    for disassembled in disassembler.disassemble(start..=end) {
+        println!("{}", format_listing_line(&disassembled));
+    }
+
+    Ok(())
+}
+
+/**
+ * Runs the device on this thread until one of the `conditions` is met or the frame hashes
+ * differ, then writes the screenshot, if requested.
+ */
+fn run_device_headless(
+    mut device: Device,
+    conditions: &StopConditions,
+    screenshot: Option<&Path>,
+) -> Result<Option<StopReason>, String> {
+    let reason = device.run_headless(conditions);
+    device.stop_movie()?;
+    if let Some(path) = screenshot {
+        device.save_screenshot(path)?;
+    }
+    if let Some(frame_hashes) = device.frame_hashes.take() {
+        let checked = frame_hashes.checked;
+        frame_hashes.finish()?;
+        if checked > 0 {
+            device.logger.info(Log::Msg(format!(
+                "The hashes of {} frames matched",
+                checked
+            )));
+        }
+    }
+    Ok(reason)
+}
+
+fn run_export(
+    file: &Path,
+    output: &Path,
+    entry_points: &[(u16, u16)],
+    jump_tables: &[(u16, u16, u16)],
+) -> Result<(), String> {
+    let cartridge = Cartridge::new(file);
+
+    let mut tracer = CodeTracer::new(&cartridge);
+    for (bank, address) in entry_points {
+        if *bank >= tracer.bank_count() {
+            return Err(format!("Invalid bank {} for entry point", bank));
+        }
+        tracer.add_entry_point(*bank, *address, None);
+    }
+    for (bank, address, count) in jump_tables {
+        if *bank >= tracer.bank_count() {
+            return Err(format!("Invalid bank {} for jump table", bank));
+        }
+        tracer.add_jump_table(*bank, *address, *count);
+    }
+
+    let analysis = tracer.run();
+    write_project(&cartridge, &analysis, output)?;
+
+    println!("Wrote RGBDS project to {}", output.display());
+
+    Ok(())
+}
+
+fn run_test_suite(
+    directory: &Path,
+    expectations: Option<&Path>,
+    junit: Option<&Path>,
+    jobs: Option<usize>,
+    limits: Limits,
+) -> Result<(), String> {
+    let expectations = match expectations {
+        Some(path) => Expectations::load(path)?,
+        None => Expectations::default(),
+    };
+    let roms = find_roms(directory)?;
+    if let Some(rom) = expectations.missing(&roms).first() {
+        return Err(format!(
+            "The expected ROM {} is not in {}",
+            rom.display(),
+            directory.display()
+        ));
+    }
+    if roms.is_empty() {
+        return Err(format!("No ROMs in {}", directory.display()));
+    }
+
+    let jobs = jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, usize::from));
+    let started = Instant::now();
+    let results = run_tests(
+        directory,
+        &roms,
+        |rom| expectations.for_rom(directory, rom),
+        limits,
+        jobs,
+    );
+    let duration = started.elapsed();
+
+    print!("{}", format_summary(&results, duration));
+    if let Some(path) = junit {
+        let suite = directory
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| directory.display().to_string());
+        write_junit(path, &suite, &results, duration)?;
+    }
+
+    let totals = Totals::of(&results);
+    match totals.failed + totals.errors {
+        0 => Ok(()),
+        failed => Err(format!(
+            "{} of {} test ROMs did not pass",
+            failed,
+            results.len()
+        )),
+    }
+}
+
+fn run_trace_diff(cli: &Cli, file: &Path, reference: &Path, context: usize) -> Result<(), String> {
+    let comparison = Arc::new(Mutex::new(TraceComparison::new(reference, context)?));
+    let symbols = load_symbols(cli, file)?;
+
+    let cartridge = Cartridge::new(file);
+    let mut device = Device::new(cartridge);
+    device.logger = Box::new(TraceDiffLogger::new(
+        comparison.clone(),
+        create_default_logger(cli),
+    ));
+    device.history = ExecutionHistory::new(cli.history_length);
+    device.history_file = Some(cli.history_file.clone());
+
+    device.run_guarded(|device| {
+        while !comparison.lock().unwrap().is_finished() {
+            device.step();
+        }
+    });
+
+    let result = comparison.lock().unwrap().result.take();
+    match result {
+        Some(TraceDiffResult::Matched(lines)) => {
+            println!("No difference in {} lines of the reference trace", lines);
+            Ok(())
+        }
+        Some(TraceDiffResult::Mismatch(mismatch)) => {
+            print!("{}", mismatch.report(&symbols));
+            Err(format!(
+                "The trace differs from the reference at line {}",
+                mismatch.line_number
+            ))
+        }
+        Some(TraceDiffResult::Failed(e)) => Err(e),
+        None => unreachable!(),
+    }
+}
+
+fn open_native_app(device: Device) -> Result<(), String> {
+    let native_options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_inner_size([1280.0, 800.0])
+            .with_min_inner_size([800.0, 500.0]),
+        ..eframe::NativeOptions::default()
+    };
+    // .with_icon(
+    //     // NOTE: Adding an icon is optional
+    //     eframe::icon_data::from_png_bytes(&include_bytes!("../assets/icon-256.png")[..])
+    //         .expect("Failed to load icon"),
+    // ),
+
+    let app = AppTemplate::new(device);
+
+    eframe::run_native(
+        "MyBoy Gameboy Emulator",
+        native_options,
+        Box::new(|_cc| Ok(Box::new(app))),
+    )
+    .map_err(|e| format!("Failed to run native app: {}", e))
+}
@@ -1,10 +1,13 @@
 use super::{instruction::Instruction, register_set::RegisterSet};
 use crate::{
-    Logger,
     cpu::register_set::{Flag, WordRegister},
-    device::mem_map::MemMap,
+    debugger::call_stack::{CallKind, CallStack, StackFrame},
+    device::{
+        mem_map::MemMap,
+        save_state::{SaveState, StateReader, StateWriter},
+    },
     io::if_register::{InterruptType, get_handler_address},
-    logging::log::Log,
+    logging::log::{Log, Logger},
 };
 use std::{
     fmt::{Debug, Display},
@@ -30,8 +33,11 @@ pub struct CPU {
     pub interrupt_master_enable: InterruptMasterEnableStatus,
     pub halted: bool,
     pub logger: Option<Box<dyn Logger>>,
+    pub call_stack: CallStack,
+    /// The state before the instruction started last, until the device takes it for the history
+    pub last_state: Option<super::CPUState>,
 
-    stopped: bool,
+    pub(crate) stopped: bool,
     cycle_counter: Wrapping<u8>,
     occupied_cycles: u32,
 }
@@ -49,6 +55,8 @@ impl CPU {
             stopped: false,
             halted: false,
             logger: Option::None,
+            call_stack: CallStack::default(),
+            last_state: None,
         }
     }
 
@@ -71,20 +79,27 @@ impl CPU {
         }
         let halted = self.halted;
         if !halted {
-            logger.info(Log::CPUState(super::CPUState::new(self, mem_map)));
+            self.capture_state(mem_map, logger);
         }
         self.check_interrupts(mem_map);
         if halted && !self.halted {
-            logger.info(Log::CPUState(super::CPUState::new(self, mem_map)));
+            self.capture_state(mem_map, logger);
         }
 
         let next_instruction_address = *self.register_set.pc();
         let instruction = Instruction::create(next_instruction_address, mem_map).unwrap();
         if !self.halted {
             self.occupied_cycles = self.run(mem_map, &instruction) - 1;
+            self.call_stack.update(*self.register_set.sp());
         }
     }
 
+    fn capture_state(&mut self, mem_map: &MemMap, logger: &mut dyn Logger) {
+        let state = super::CPUState::new(self, mem_map);
+        self.last_state = Some(state);
+        logger.info(Log::CPUState(state));
+    }
+
     #[inline]
     pub fn is_halted(&self) -> bool {
         self.halted
@@ -142,14 +157,59 @@ impl CPU {
         interrupt_type: InterruptType,
     ) {
         self.interrupt_master_enable = InterruptMasterEnableStatus::Disabled;
-        self.push_to_stack(mem_map, *self.register_set.pc());
+        let pc = *self.register_set.pc();
+        self.push_to_stack(mem_map, pc);
         self.register_set
             .set_w(WordRegister::PC, get_handler_address(interrupt_type));
+        self.call_stack.push(StackFrame {
+            kind: CallKind::Interrupt(interrupt_type),
+            call_site: pc,
+            call_site_bank: mem_map.rom_bank_for_address(pc),
+            target: get_handler_address(interrupt_type),
+            target_bank: mem_map.rom_bank_for_address(get_handler_address(interrupt_type)),
+            return_address: pc,
+            stack_pointer: *self.register_set.sp(),
+        });
 
         self.occupied_cycles += 5;
     }
 }
 
+impl SaveState for CPU {
+    fn save(&self, writer: &mut StateWriter) {
+        self.register_set.save(writer);
+        writer.u8(match self.interrupt_master_enable {
+            InterruptMasterEnableStatus::Disabled => 0,
+            InterruptMasterEnableStatus::Enabling => 1,
+            InterruptMasterEnableStatus::Enabled => 2,
+        });
+        writer.bool(self.halted);
+        writer.bool(self.stopped);
+        writer.u8(self.cycle_counter.0);
+        writer.u32(self.occupied_cycles);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.register_set.load(reader)?;
+        self.interrupt_master_enable = match reader.u8()? {
+            0 => InterruptMasterEnableStatus::Disabled,
+            1 => InterruptMasterEnableStatus::Enabling,
+            2 => InterruptMasterEnableStatus::Enabled,
+            value => return Err(format!("Invalid IME status {} in the save state", value)),
+        };
+        self.halted = reader.bool()?;
+        self.stopped = reader.bool()?;
+        self.cycle_counter = Wrapping(reader.u8()?);
+        self.occupied_cycles = reader.u32()?;
+
+        // the calls and the instruction before the state are not known anymore
+        self.current_instruction = None;
+        self.last_state = None;
+        self.call_stack = CallStack::default();
+        Ok(())
+    }
+}
+
 impl Display for CPU {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut flags = String::new();
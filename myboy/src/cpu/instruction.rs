@@ -5,7 +5,7 @@ use super::{
 };
 use crate::{
     cpu::register_set::{ByteRegister, WordRegister},
-    device::mem_map::MemMap,
+    memory::generic_memory::AddressSpace,
 };
 
 #[derive(Clone, Copy, Debug)]
@@ -83,7 +83,7 @@ impl Default for Instruction {
 
 impl Instruction {
     // TODO: There is no reason for create to be here
-    pub(crate) fn create(address: u16, data: &MemMap) -> Result<Instruction, String> {
+    pub(crate) fn create(address: u16, data: &impl AddressSpace) -> Result<Instruction, String> {
         let opcode = data.read_byte(address);
         match InstructionType::create_instruction_type(address, data) {
             Ok((instruction_type, (target, source), condition)) => Ok(Instruction {
@@ -119,7 +119,7 @@ impl Instruction {
 impl InstructionType {
     fn create_instruction_type(
         address: u16,
-        data: &MemMap,
+        data: &impl AddressSpace,
     ) -> Result<
         (
             InstructionType,
@@ -946,9 +946,9 @@ impl Display for InstructionType {
             InstructionType::Or => write!(f, "OR"),
             InstructionType::Cp => write!(f, "CP"),
             InstructionType::RotateLeftCircular => write!(f, "RLC"),
-            InstructionType::RotateRight => write!(f, "RRC"),
+            InstructionType::RotateRight => write!(f, "RR"),
             InstructionType::RotateLeft => write!(f, "RL"),
-            InstructionType::RotateRightCircular => write!(f, "RR"),
+            InstructionType::RotateRightCircular => write!(f, "RRC"),
             InstructionType::ShiftLeftArithmetically => write!(f, "SLA"),
             InstructionType::ShiftRightArithmetically => write!(f, "SRA"),
             InstructionType::Swap => write!(f, "SWAP"),
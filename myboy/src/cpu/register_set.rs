@@ -1,5 +1,7 @@
 use std::{fmt::Display, u8};
 
+use crate::device::save_state::{SaveState, StateReader, StateWriter};
+
 #[derive(Clone, Copy, Debug)]
 pub(crate) enum ByteRegister {
     A,
@@ -51,6 +53,42 @@ pub(crate) struct RegisterSet {
     sp: u16,
 }
 
+impl ByteRegister {
+    /// Parses a register name like `A` or `h`
+    pub(crate) fn from_name(name: &str) -> Option<ByteRegister> {
+        let register = match name.to_ascii_uppercase().as_str() {
+            "A" => ByteRegister::A,
+            "F" => ByteRegister::F,
+            "B" => ByteRegister::B,
+            "C" => ByteRegister::C,
+            "D" => ByteRegister::D,
+            "E" => ByteRegister::E,
+            "H" => ByteRegister::H,
+            "L" => ByteRegister::L,
+            _ => return None,
+        };
+
+        Some(register)
+    }
+}
+
+impl WordRegister {
+    /// Parses a register pair name like `HL` or `sp`
+    pub(crate) fn from_name(name: &str) -> Option<WordRegister> {
+        let register = match name.to_ascii_uppercase().as_str() {
+            "AF" => WordRegister::AF,
+            "BC" => WordRegister::BC,
+            "DE" => WordRegister::DE,
+            "HL" => WordRegister::HL,
+            "SP" => WordRegister::SP,
+            "PC" => WordRegister::PC,
+            _ => return None,
+        };
+
+        Some(register)
+    }
+}
+
 impl Default for RegisterSet {
     fn default() -> Self {
         RegisterSet {
@@ -211,6 +249,35 @@ impl RegisterSet {
     }
 }
 
+impl SaveState for RegisterSet {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&[
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l,
+        ]);
+        writer.u16(self.pc);
+        writer.u16(self.sp);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        let mut bytes = [0u8; 8];
+        reader.bytes(&mut bytes)?;
+        let [a, f, b, c, d, e, h, l] = bytes;
+        *self = RegisterSet {
+            a,
+            f,
+            b,
+            c,
+            d,
+            e,
+            h,
+            l,
+            pc: reader.u16()?,
+            sp: reader.u16()?,
+        };
+        Ok(())
+    }
+}
+
 impl Display for RegisterSet {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -1,4 +1,8 @@
-use crate::{cpu::InterruptMasterEnableStatus, device::mem_map::MemMap};
+use crate::{
+    cpu::InterruptMasterEnableStatus,
+    debugger::call_stack::{CallKind, StackFrame},
+    device::mem_map::MemMap,
+};
 
 use super::{
     addressing_mode::AddressingMode,
@@ -618,6 +622,15 @@ impl CPU {
                 let address = self.get_target_word(mem_map, instruction);
                 self.push_to_stack(mem_map, instruction.address + 1);
                 self.register_set.set_w(WordRegister::PC, address);
+                self.call_stack.push(StackFrame {
+                    kind: CallKind::Reset,
+                    call_site: instruction.address,
+                    call_site_bank: mem_map.rom_bank_for_address(instruction.address),
+                    target: address,
+                    target_bank: mem_map.rom_bank_for_address(address),
+                    return_address: instruction.address + 1,
+                    stack_pointer: *self.register_set.sp(),
+                });
                 return 4;
             }
             InstructionType::DisableInterrupts => {
@@ -899,6 +912,7 @@ impl CPU {
                     self.register_set.set_w(WordRegister::SP, stack_pointer);
 
                     self.register_set.set_w(WordRegister::PC, return_address);
+                    self.call_stack.returned(stack_pointer - 2);
                     return 4 + instruction.condition.is_some() as u32;
                 }
                 self.register_set.set_w(
@@ -918,6 +932,7 @@ impl CPU {
                 self.register_set.set_w(WordRegister::SP, stack_pointer);
 
                 self.register_set.set_w(WordRegister::PC, return_address);
+                self.call_stack.returned(stack_pointer - 2);
                 return 4;
             }
             InstructionType::Call => {
@@ -934,6 +949,15 @@ impl CPU {
                         instruction.address + (instruction.size() as u16),
                     );
                     self.register_set.set_w(WordRegister::PC, address);
+                    self.call_stack.push(StackFrame {
+                        kind: CallKind::Call,
+                        call_site: instruction.address,
+                        call_site_bank: mem_map.rom_bank_for_address(instruction.address),
+                        target: address,
+                        target_bank: mem_map.rom_bank_for_address(address),
+                        return_address: instruction.address + (instruction.size() as u16),
+                        stack_pointer,
+                    });
                     return 6;
                 }
                 self.register_set.set_w(
@@ -7,6 +7,8 @@ use super::{CPU, RegisterSet};
 #[derive(Clone, Copy)]
 pub struct CPUState {
     pub register_set: RegisterSet,
+    /// The ROM bank the instruction is read from, `None` if it runs from RAM
+    pub bank: Option<u16>,
     pub current_instruction_bytes: [u8; 4],
 }
 
@@ -44,18 +46,16 @@ impl CPUState {
         let register_set = cpu.register_set.clone();
 
         let pc = (*cpu.register_set.pc()).clone();
-        let current_bytes = mem_map.read_word(pc).to_le_bytes();
-        let next_bytes = mem_map.read_word(pc + 2).to_le_bytes();
-
         let current_instruction_bytes = [
-            current_bytes[0],
-            current_bytes[1],
-            next_bytes[0],
-            next_bytes[1],
+            mem_map.peek_byte(pc),
+            mem_map.peek_byte(pc.wrapping_add(1)),
+            mem_map.peek_byte(pc.wrapping_add(2)),
+            mem_map.peek_byte(pc.wrapping_add(3)),
         ];
 
         CPUState {
             register_set,
+            bank: mem_map.rom_bank_for_address(pc),
             current_instruction_bytes,
         }
     }
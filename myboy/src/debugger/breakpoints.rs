@@ -0,0 +1,330 @@
+use std::{fmt::Display, ops::RangeInclusive};
+
+use crate::{
+    cpu::register_set::RegisterSet, disasm::symbols::SymbolTable,
+    memory::generic_memory::AddressSpace,
+};
+
+use super::{
+    expression::Expression,
+    watch::{AccessKind, MemoryAccess, WatchKind},
+};
+
+#[derive(Clone, Debug)]
+pub(crate) enum BreakpointKind {
+    /// Stops before the instruction at `address` runs, optionally only if it runs from `bank`
+    Execute { address: u16, bank: Option<u16> },
+    /// Stops after an instruction accessed memory in `range`
+    Watch {
+        range: RangeInclusive<u16>,
+        kind: WatchKind,
+    },
+}
+
+impl Display for BreakpointKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BreakpointKind::Execute {
+                address,
+                bank: Some(bank),
+            } => write!(f, "{:02x}:{:04x}", bank, address),
+            BreakpointKind::Execute {
+                address,
+                bank: None,
+            } => write!(f, "{:04x}", address),
+            BreakpointKind::Watch { range, kind } if range.start() == range.end() => {
+                write!(f, "{} {:04x}", kind, range.start())
+            }
+            BreakpointKind::Watch { range, kind } => {
+                write!(f, "{} {:04x}-{:04x}", kind, range.start(), range.end())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Breakpoint {
+    /// Assigned by the `BreakpointManager`, 0 until the breakpoint is added
+    pub(crate) id: u32,
+    pub(crate) kind: BreakpointKind,
+    /// Only stop if this evaluates to something other than 0
+    pub(crate) condition: Option<Expression>,
+    pub(crate) enabled: bool,
+    pub(crate) hit_count: u32,
+}
+
+impl Breakpoint {
+    pub(crate) fn new(kind: BreakpointKind, condition: Option<Expression>) -> Breakpoint {
+        Breakpoint {
+            id: 0,
+            kind,
+            condition,
+            enabled: true,
+            hit_count: 0,
+        }
+    }
+
+    /**
+     * Parses a breakpoint from the command line or the debugger, addresses are hex:
+     *
+     * - `0150` or `$0150` or `0x0150`: break at an address
+     * - `2:4000`: break at an address, but only in ROM bank 2
+     * - `r:c000`, `w:c000-c0ff`, `rw:ff40`: break on reads, writes or both
+     *
+     * followed by an optional condition: `0150 if A == 0x3F && [HL] > 2`.
+     * Labels from `symbols` can be used instead of addresses: `Main.loop`, `w:wScore`.
+     */
+    pub(crate) fn parse(spec: &str, symbols: &SymbolTable) -> Result<Breakpoint, String> {
+        let (target, condition) = match spec.split_once(" if ") {
+            Some((target, condition)) => (target.trim(), Some(Expression::parse(condition)?)),
+            None => (spec.trim(), None),
+        };
+
+        let kind = match target.split_once(':') {
+            Some(("r", range)) => BreakpointKind::Watch {
+                range: parse_range(range, symbols)?,
+                kind: WatchKind::Read,
+            },
+            Some(("w", range)) => BreakpointKind::Watch {
+                range: parse_range(range, symbols)?,
+                kind: WatchKind::Write,
+            },
+            Some(("rw", range)) => BreakpointKind::Watch {
+                range: parse_range(range, symbols)?,
+                kind: WatchKind::Access,
+            },
+            Some((bank, address)) => BreakpointKind::Execute {
+                address: parse_address(address, symbols)?,
+                bank: Some(parse_hex(bank)?),
+            },
+            // a label in the switchable ROM area only exists in its own bank
+            None => match symbols.address_of(target) {
+                Some((bank, address @ 0x4000..=0x7FFF)) => BreakpointKind::Execute {
+                    address,
+                    bank: Some(bank),
+                },
+                _ => BreakpointKind::Execute {
+                    address: parse_address(target, symbols)?,
+                    bank: None,
+                },
+            },
+        };
+
+        Ok(Breakpoint::new(kind, condition))
+    }
+
+    fn condition_holds(&self, registers: &RegisterSet, memory: &impl AddressSpace) -> bool {
+        match &self.condition {
+            Some(condition) => condition.is_true(registers, memory),
+            None => true,
+        }
+    }
+}
+
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{} {}", self.id, self.kind)?;
+        if let Some(condition) = &self.condition {
+            write!(f, " if {}", condition)?;
+        }
+        if !self.enabled {
+            write!(f, " (disabled)")?;
+        }
+        write!(f, ", hit {} times", self.hit_count)
+    }
+}
+
+/// A label or a hex address
+pub(crate) fn parse_address(value: &str, symbols: &SymbolTable) -> Result<u16, String> {
+    match symbols.address_of(value.trim()) {
+        Some((_, address)) => Ok(address),
+        None => parse_hex(value)
+            .map_err(|_| format!("'{}' is neither a label nor a hex address", value.trim())),
+    }
+}
+
+fn parse_hex(value: &str) -> Result<u16, String> {
+    let digits = value
+        .trim()
+        .trim_start_matches("0x")
+        .trim_start_matches('$');
+    u16::from_str_radix(digits, 16).map_err(|e| format!("Invalid address '{}': {}", value, e))
+}
+
+fn parse_range(value: &str, symbols: &SymbolTable) -> Result<RangeInclusive<u16>, String> {
+    match value.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse_address(start, symbols)?, parse_address(end, symbols)?);
+            match start <= end {
+                true => Ok(start..=end),
+                false => Err(format!("Invalid address range '{}'", value)),
+            }
+        }
+        None => {
+            let address = parse_address(value, symbols)?;
+            Ok(address..=address)
+        }
+    }
+}
+
+/// Why execution stopped
+pub(crate) struct BreakpointHit {
+    pub(crate) id: u32,
+    pub(crate) access: Option<MemoryAccess>,
+}
+
+impl Display for BreakpointHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.access {
+            Some(MemoryAccess {
+                address,
+                value,
+                kind: AccessKind::Read,
+            }) => write!(
+                f,
+                "Watchpoint #{} hit: read ${:02x} from 0x{:04x}",
+                self.id, value, address
+            ),
+            Some(MemoryAccess {
+                address,
+                value,
+                kind: AccessKind::Write,
+            }) => write!(
+                f,
+                "Watchpoint #{} hit: wrote ${:02x} to 0x{:04x}",
+                self.id, value, address
+            ),
+            None => write!(f, "Breakpoint #{} hit", self.id),
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub(crate) struct BreakpointManager {
+    breakpoints: Vec<Breakpoint>,
+    next_id: u32,
+}
+
+impl BreakpointManager {
+    pub(crate) fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub(crate) fn add(&mut self, mut breakpoint: Breakpoint) -> u32 {
+        self.next_id += 1;
+        breakpoint.id = self.next_id;
+        self.breakpoints.push(breakpoint);
+        self.next_id
+    }
+
+    pub(crate) fn remove(&mut self, id: u32) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        self.breakpoints.len() != count
+    }
+
+    pub(crate) fn set_enabled(&mut self, id: u32, enabled: bool) -> bool {
+        match self
+            .breakpoints
+            .iter_mut()
+            .find(|breakpoint| breakpoint.id == id)
+        {
+            Some(breakpoint) => {
+                breakpoint.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The first execution breakpoint at `address`, in any bank
+    pub(crate) fn execute_breakpoint_at(&self, address: u16) -> Option<&Breakpoint> {
+        self.breakpoints.iter().find(|breakpoint| {
+            matches!(breakpoint.kind, BreakpointKind::Execute { address: a, .. } if a == address)
+        })
+    }
+
+    /// The ranges the memory map has to record accesses for
+    pub(crate) fn watch_ranges(&self) -> Vec<(RangeInclusive<u16>, WatchKind)> {
+        self.breakpoints
+            .iter()
+            .filter(|breakpoint| breakpoint.enabled)
+            .filter_map(|breakpoint| match &breakpoint.kind {
+                BreakpointKind::Watch { range, kind } => Some((range.clone(), *kind)),
+                BreakpointKind::Execute { .. } => None,
+            })
+            .collect()
+    }
+
+    /**
+     * Checks the execution breakpoints before the instruction at PC runs.
+     * Every matching breakpoint counts a hit, the first one is returned.
+     */
+    pub(crate) fn check_execute(
+        &mut self,
+        registers: &RegisterSet,
+        bank: Option<u16>,
+        memory: &impl AddressSpace,
+    ) -> Option<BreakpointHit> {
+        let pc = *registers.pc();
+        let mut hit = None;
+
+        for breakpoint in self.breakpoints.iter_mut() {
+            let BreakpointKind::Execute {
+                address,
+                bank: breakpoint_bank,
+            } = breakpoint.kind
+            else {
+                continue;
+            };
+            let matches = breakpoint.enabled
+                && address == pc
+                && (breakpoint_bank.is_none() || breakpoint_bank == bank)
+                && breakpoint.condition_holds(registers, memory);
+
+            if matches {
+                breakpoint.hit_count += 1;
+                hit.get_or_insert(BreakpointHit {
+                    id: breakpoint.id,
+                    access: None,
+                });
+            }
+        }
+
+        hit
+    }
+
+    /**
+     * Checks the watchpoints against the memory accesses of the last instruction.
+     */
+    pub(crate) fn check_accesses(
+        &mut self,
+        accesses: &[MemoryAccess],
+        registers: &RegisterSet,
+        memory: &impl AddressSpace,
+    ) -> Option<BreakpointHit> {
+        let mut hit = None;
+
+        for breakpoint in self.breakpoints.iter_mut() {
+            let BreakpointKind::Watch { range, kind } = &breakpoint.kind else {
+                continue;
+            };
+            if !breakpoint.enabled || !breakpoint.condition_holds(registers, memory) {
+                continue;
+            }
+
+            let access = accesses
+                .iter()
+                .find(|access| range.contains(&access.address) && kind.matches(access.kind));
+            if let Some(access) = access {
+                breakpoint.hit_count += 1;
+                hit.get_or_insert(BreakpointHit {
+                    id: breakpoint.id,
+                    access: Some(*access),
+                });
+            }
+        }
+
+        hit
+    }
+}
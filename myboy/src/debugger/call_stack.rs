@@ -0,0 +1,128 @@
+use std::fmt::{Display, Write as _};
+
+use crate::{disasm::symbols::SymbolTable, io::if_register::InterruptType};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CallKind {
+    Call,
+    Reset,
+    Interrupt(InterruptType),
+}
+
+impl Display for CallKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallKind::Call => write!(f, "call"),
+            CallKind::Reset => write!(f, "rst"),
+            CallKind::Interrupt(InterruptType::VBlank) => write!(f, "VBlank interrupt"),
+            CallKind::Interrupt(InterruptType::LCDStat) => write!(f, "LCD STAT interrupt"),
+            CallKind::Interrupt(InterruptType::Timer) => write!(f, "timer interrupt"),
+            CallKind::Interrupt(InterruptType::Serial) => write!(f, "serial interrupt"),
+            CallKind::Interrupt(InterruptType::Joypad) => write!(f, "joypad interrupt"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct StackFrame {
+    pub(crate) kind: CallKind,
+    /// The address of the call or rst, or the PC the interrupt happened at
+    pub(crate) call_site: u16,
+    /// The ROM bank the call site and return address were mapped from
+    pub(crate) call_site_bank: Option<u16>,
+    /// The routine that was called
+    pub(crate) target: u16,
+    /// The ROM bank the routine was mapped from
+    pub(crate) target_bank: Option<u16>,
+    pub(crate) return_address: u16,
+    /// Where the return address was pushed to
+    pub(crate) stack_pointer: u16,
+}
+
+impl StackFrame {
+    /// `0x0200 (Routine) from 0x0153 (Main+3) (call), returns to 0x0156 (Main+6)`
+    pub(crate) fn describe(&self, symbols: &SymbolTable) -> String {
+        format!(
+            "{} from {} ({}), returns to {}",
+            symbols.format_address(self.target_bank, self.target),
+            symbols.format_address(self.call_site_bank, self.call_site),
+            self.kind,
+            symbols.format_address(self.call_site_bank, self.return_address)
+        )
+    }
+}
+
+/**
+ * A shadow of the call stack, kept next to the real stack to build backtraces.
+ *
+ * A frame is popped when a ret/reti pops its return address. Frames whose return address
+ * lies above SP without having been returned through were dropped by manipulating SP
+ * directly (`pop` of the return address, `ld sp`, `add sp`), they are discarded and counted.
+ */
+#[derive(Default, Clone)]
+pub(crate) struct CallStack {
+    frames: Vec<StackFrame>,
+    discarded_frames: u64,
+}
+
+impl CallStack {
+    /// The frames, innermost last
+    pub(crate) fn frames(&self) -> &[StackFrame] {
+        &self.frames
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub(crate) fn discarded_frames(&self) -> u64 {
+        self.discarded_frames
+    }
+
+    pub(crate) fn push(&mut self, frame: StackFrame) {
+        self.frames.push(frame);
+    }
+
+    /**
+     * A ret or reti popped the word at `stack_pointer`, which ends the innermost frame
+     * if that is where its return address was pushed to.
+     * Returning through an address pushed by hand (`push hl` / `ret`) keeps the frames.
+     */
+    pub(crate) fn returned(&mut self, stack_pointer: u16) {
+        if self
+            .frames
+            .last()
+            .is_some_and(|frame| frame.stack_pointer == stack_pointer)
+        {
+            self.frames.pop();
+        }
+    }
+
+    /// Discards the frames SP was moved past, to be called after every instruction.
+    pub(crate) fn update(&mut self, stack_pointer: u16) {
+        while self
+            .frames
+            .last()
+            .is_some_and(|frame| frame.stack_pointer < stack_pointer)
+        {
+            self.frames.pop();
+            self.discarded_frames += 1;
+        }
+    }
+
+    /// One `#n frame` line per frame, innermost first
+    pub(crate) fn backtrace(&self, symbols: &SymbolTable) -> String {
+        let mut backtrace = String::new();
+        for (index, frame) in self.frames.iter().rev().enumerate() {
+            let _ = writeln!(backtrace, "#{:<3} {}", index, frame.describe(symbols));
+        }
+        if self.discarded_frames > 0 {
+            let _ = writeln!(
+                backtrace,
+                "({} frames were discarded by moving SP)",
+                self.discarded_frames
+            );
+        }
+        backtrace
+    }
+}
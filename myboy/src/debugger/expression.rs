@@ -0,0 +1,374 @@
+use std::fmt::Display;
+
+use crate::{
+    cpu::register_set::{ByteRegister, RegisterSet, WordRegister},
+    memory::generic_memory::AddressSpace,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum UnaryOperator {
+    Not,
+    Negate,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Number(i64),
+    ByteRegister(ByteRegister),
+    WordRegister(WordRegister),
+    /// The byte at the address the inner expression evaluates to
+    Memory(Box<Node>),
+    Unary(UnaryOperator, Box<Node>),
+    Binary(BinaryOperator, Box<Node>, Box<Node>),
+}
+
+/**
+ * A small expression over registers and memory, used as breakpoint condition,
+ * e.g. `A == 0x3F && [HL] > 2`.
+ *
+ * Numbers are decimal unless prefixed with `0x` or `$` (hex) or `%` (binary),
+ * `[...]` reads a byte from memory. Comparisons and logic operators evaluate to 0 or 1.
+ */
+#[derive(Clone, Debug)]
+pub(crate) struct Expression {
+    source: String,
+    root: Node,
+}
+
+impl Expression {
+    pub(crate) fn parse(source: &str) -> Result<Expression, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+
+        let root = parser.parse_binary(0)?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected '{}' in '{}'", token, source));
+        }
+
+        Ok(Expression {
+            source: source.trim().to_string(),
+            root,
+        })
+    }
+
+    pub(crate) fn evaluate(&self, registers: &RegisterSet, memory: &impl AddressSpace) -> i64 {
+        evaluate(&self.root, registers, memory)
+    }
+
+    pub(crate) fn is_true(&self, registers: &RegisterSet, memory: &impl AddressSpace) -> bool {
+        self.evaluate(registers, memory) != 0
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn evaluate(node: &Node, registers: &RegisterSet, memory: &impl AddressSpace) -> i64 {
+    match node {
+        Node::Number(value) => *value,
+        Node::ByteRegister(register) => *registers.get_b(*register) as i64,
+        Node::WordRegister(register) => registers.get_w(*register) as i64,
+        Node::Memory(address) => {
+            memory.read_byte(evaluate(address, registers, memory) as u16) as i64
+        }
+        Node::Unary(operator, operand) => {
+            let value = evaluate(operand, registers, memory);
+            match operator {
+                UnaryOperator::Not => (value == 0) as i64,
+                UnaryOperator::Negate => value.wrapping_neg(),
+            }
+        }
+        Node::Binary(BinaryOperator::And, left, right) => {
+            (evaluate(left, registers, memory) != 0 && evaluate(right, registers, memory) != 0)
+                as i64
+        }
+        Node::Binary(BinaryOperator::Or, left, right) => {
+            (evaluate(left, registers, memory) != 0 || evaluate(right, registers, memory) != 0)
+                as i64
+        }
+        Node::Binary(operator, left, right) => {
+            let left = evaluate(left, registers, memory);
+            let right = evaluate(right, registers, memory);
+            match operator {
+                BinaryOperator::Equal => (left == right) as i64,
+                BinaryOperator::NotEqual => (left != right) as i64,
+                BinaryOperator::Less => (left < right) as i64,
+                BinaryOperator::LessOrEqual => (left <= right) as i64,
+                BinaryOperator::Greater => (left > right) as i64,
+                BinaryOperator::GreaterOrEqual => (left >= right) as i64,
+                BinaryOperator::BitOr => left | right,
+                BinaryOperator::BitXor => left ^ right,
+                BinaryOperator::BitAnd => left & right,
+                BinaryOperator::Add => left.wrapping_add(right),
+                BinaryOperator::Sub => left.wrapping_sub(right),
+                BinaryOperator::And | BinaryOperator::Or => unreachable!(),
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Operator(&'static str),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Identifier(name) => write!(f, "{}", name),
+            Token::Operator(operator) => write!(f, "{}", operator),
+        }
+    }
+}
+
+/// Longer operators first, so `<=` is not read as `<` followed by `=`.
+const OPERATORS: [&str; 18] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "|", "^", "&", "+", "-", "!", "[", "]", "(", ")",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+
+    while !rest.is_empty() {
+        if let Some(operator) = OPERATORS
+            .iter()
+            .find(|operator| rest.starts_with(**operator))
+        {
+            tokens.push(Token::Operator(operator));
+            rest = &rest[operator.len()..];
+        } else {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '$' || c == '%' || c == '_'))
+                .unwrap_or(rest.len());
+            if length == 0 {
+                return Err(format!("Unexpected character in '{}'", rest));
+            }
+
+            let word = &rest[..length];
+            tokens.push(match word.chars().next() {
+                Some('0'..='9' | '$' | '%') => Token::Number(parse_number(word)?),
+                _ => Token::Identifier(word.to_string()),
+            });
+            rest = &rest[length..];
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(word: &str) -> Result<i64, String> {
+    let (digits, radix) = if let Some(digits) = word
+        .strip_prefix("0x")
+        .or_else(|| word.strip_prefix("0X"))
+        .or_else(|| word.strip_prefix('$'))
+    {
+        (digits, 16)
+    } else if let Some(digits) = word.strip_prefix('%') {
+        (digits, 2)
+    } else {
+        (word, 10)
+    };
+
+    i64::from_str_radix(digits, radix).map_err(|e| format!("Invalid number '{}': {}", word, e))
+}
+
+fn register(name: &str) -> Option<Node> {
+    ByteRegister::from_name(name)
+        .map(Node::ByteRegister)
+        .or_else(|| WordRegister::from_name(name).map(Node::WordRegister))
+}
+
+/// Binding strength of the binary operators, higher binds tighter.
+fn precedence(operator: &str) -> Option<(u8, BinaryOperator)> {
+    let entry = match operator {
+        "||" => (1, BinaryOperator::Or),
+        "&&" => (2, BinaryOperator::And),
+        "==" => (3, BinaryOperator::Equal),
+        "!=" => (3, BinaryOperator::NotEqual),
+        "<" => (4, BinaryOperator::Less),
+        "<=" => (4, BinaryOperator::LessOrEqual),
+        ">" => (4, BinaryOperator::Greater),
+        ">=" => (4, BinaryOperator::GreaterOrEqual),
+        "|" => (5, BinaryOperator::BitOr),
+        "^" => (6, BinaryOperator::BitXor),
+        "&" => (7, BinaryOperator::BitAnd),
+        "+" => (8, BinaryOperator::Add),
+        "-" => (8, BinaryOperator::Sub),
+        _ => return None,
+    };
+
+    Some(entry)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &'static str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Operator(operator)) if operator == expected => Ok(()),
+            Some(token) => Err(format!("Expected '{}', got '{}'", expected, token)),
+            None => Err(format!("Expected '{}' at the end", expected)),
+        }
+    }
+
+    /// Precedence climbing over all binary operators binding tighter than `min_precedence`.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Node, String> {
+        let mut left = self.parse_unary()?;
+
+        while let Some(Token::Operator(operator)) = self.peek() {
+            let Some((precedence, operator)) = precedence(operator) else {
+                break;
+            };
+            if precedence <= min_precedence {
+                break;
+            }
+
+            self.next();
+            let right = self.parse_binary(precedence)?;
+            left = Node::Binary(operator, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Node::Number(value)),
+            Some(Token::Identifier(name)) => {
+                register(&name).ok_or_else(|| format!("Unknown register '{}'", name))
+            }
+            Some(Token::Operator("!")) => Ok(Node::Unary(
+                UnaryOperator::Not,
+                Box::new(self.parse_unary()?),
+            )),
+            Some(Token::Operator("-")) => Ok(Node::Unary(
+                UnaryOperator::Negate,
+                Box::new(self.parse_unary()?),
+            )),
+            Some(Token::Operator("[")) => {
+                let address = self.parse_binary(0)?;
+                self.expect("]")?;
+                Ok(Node::Memory(Box::new(address)))
+            }
+            Some(Token::Operator("(")) => {
+                let inner = self.parse_binary(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            Some(token) => Err(format!("Unexpected '{}'", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cpu::{ByteRegister, RegisterSet, WordRegister},
+        memory::generic_memory::AddressSpace,
+    };
+
+    use super::Expression;
+
+    struct Memory(Vec<u8>);
+
+    impl AddressSpace for Memory {
+        fn read_byte(&self, address: u16) -> u8 {
+            self.0[address as usize]
+        }
+    }
+
+    /// Evaluates `source` with A = 0x3F, HL = 0xC000 and `[0xC000]` = 3.
+    fn evaluate(source: &str) -> i64 {
+        let mut registers = RegisterSet::default();
+        registers.set_b(ByteRegister::A, 0x3f);
+        registers.set_w(WordRegister::HL, 0xc000);
+        let mut memory = Memory(vec![0x00; 0x10000]);
+        memory.0[0xc000] = 3;
+
+        Expression::parse(source)
+            .unwrap()
+            .evaluate(&registers, &memory)
+    }
+
+    #[test]
+    fn comparisons_bind_tighter_than_logic_operators() {
+        assert_eq!(evaluate("A == 0x3F && [HL] > 2"), 1);
+        assert_eq!(evaluate("A == 0x3F && [HL] > 3"), 0);
+        assert_eq!(evaluate("a == $3f || 0"), 1);
+        assert_eq!(evaluate("0 && 1 || 1"), 1);
+        assert_eq!(evaluate("1 | 2 == 3"), 1);
+        assert_eq!(evaluate("%1010 & 2 + 1"), 2);
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        assert_eq!(evaluate("1 | (2 == 3)"), 1);
+        assert_eq!(evaluate("2 | (2 == 3)"), 2);
+        assert_eq!(evaluate("(%1010 & 2) + 1"), 3);
+        assert_eq!(evaluate("10 - (3 - 2)"), 9);
+        assert_eq!(evaluate("10 - 3 - 2"), 5);
+        assert_eq!(evaluate("[HL + (1 - 1)]"), 3);
+    }
+
+    #[test]
+    fn unary_operators() {
+        assert_eq!(evaluate("!0"), 1);
+        assert_eq!(evaluate("!A"), 0);
+        assert_eq!(evaluate("!!A"), 1);
+        assert_eq!(evaluate("-[HL]"), -3);
+        assert_eq!(evaluate("--A"), 0x3f);
+        assert_eq!(evaluate("A + -1"), 0x3e);
+        assert_eq!(evaluate("-(0 - 0x7FFFFFFFFFFFFFFF - 1)"), i64::MIN);
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for source in [
+            "", "A ==", "(A == 1", "[HL", "A B", "A == 1)", "X == 1", "0xZZ", "A # 1",
+        ] {
+            assert!(Expression::parse(source).is_err(), "'{}' parsed", source);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::{collections::VecDeque, fs, path::Path};
+
+use crate::{
+    cpu::CPUState,
+    disasm::{rgbds::format_state_instruction, symbols::SymbolTable},
+};
+
+pub(crate) const DEFAULT_HISTORY_LENGTH: usize = 1024;
+
+/**
+ * The CPU states before the last executed instructions, oldest first.
+ * Once full, every new instruction replaces the oldest one.
+ */
+#[derive(Clone)]
+pub(crate) struct ExecutionHistory {
+    entries: VecDeque<CPUState>,
+    capacity: usize,
+    /// The instructions pushed so far, including those dropped again
+    recorded: u64,
+}
+
+impl ExecutionHistory {
+    pub(crate) fn new(capacity: usize) -> ExecutionHistory {
+        ExecutionHistory {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            recorded: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, state: CPUState) {
+        self.recorded += 1;
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(state);
+    }
+
+    pub(crate) fn entries(&self) -> &VecDeque<CPUState> {
+        &self.entries
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn recorded(&self) -> u64 {
+        self.recorded
+    }
+
+    /// Forgets the newest `count` instructions, after going back in time
+    pub(crate) fn drop_newest(&mut self, count: u64) {
+        let count = (count as usize).min(self.entries.len());
+        self.entries.truncate(self.entries.len() - count);
+    }
+
+    /**
+     * One line per instruction, the disassembly followed by the registers before it ran.
+     */
+    pub(crate) fn format_entry(state: &CPUState, symbols: &SymbolTable) -> String {
+        format!(
+            "{:<36} {:?}",
+            format_state_instruction(state, symbols),
+            state
+        )
+    }
+
+    /// Writes the entries, with a line for every label execution passed through.
+    pub(crate) fn dump(&self, path: &Path, symbols: &SymbolTable) -> Result<(), String> {
+        let mut lines = Vec::new();
+        for state in &self.entries {
+            if let Some(label) = symbols.label(state.bank, *state.register_set.pc()) {
+                lines.push(format!("{}:", label));
+            }
+            lines.push(ExecutionHistory::format_entry(state, symbols));
+        }
+
+        fs::write(path, lines.join("\n") + "\n")
+            .map_err(|e| format!("Failed to write history to {}: {}", path.display(), e))
+    }
+}
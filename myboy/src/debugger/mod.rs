@@ -0,0 +1,8 @@
+pub(crate) mod breakpoints;
+pub(crate) mod call_stack;
+pub(crate) mod expression;
+pub(crate) mod history;
+pub(crate) mod repl;
+pub(crate) mod reverse;
+pub(crate) mod stepping;
+pub(crate) mod watch;
@@ -0,0 +1,387 @@
+use std::sync::atomic::Ordering;
+
+use rustyline::{DefaultEditor, error::ReadlineError};
+
+use crate::{
+    cpu::register_set::{ByteRegister, Flag, WordRegister},
+    device::{
+        device::Device,
+        mem_map::{is_editable, region_name},
+    },
+    disasm::{disassembler::disassemble, rgbds::format_instruction},
+};
+
+use super::{
+    breakpoints::{Breakpoint, BreakpointKind, parse_address},
+    stepping::RunTarget,
+};
+
+const HELP: &str = "Commands, addresses are hex or labels, values are hex:
+  break [SPEC]        (b) add a breakpoint like --breakpoint, or list them
+  delete ID           (d) remove a breakpoint
+  step [COUNT]        (s) run single instructions
+  next                (n) step over calls
+  finish                  run until the current routine returns
+  continue            (c) run until a breakpoint is hit
+  reverse-step       (rs) go back to before the last instruction
+  reverse-continue   (rc) go back to the last breakpoint hit
+  regs                (r) show the registers
+  x[/COUNT] ADDR          show COUNT bytes of memory, 16 by default
+  disasm [ADDR] [COUNT]   disassemble COUNT instructions at ADDR, PC by default
+  set REG VALUE           set a register, e.g. set a 3f, set pc Main
+  write ADDR VALUE    (w) write a byte to memory
+  bt                      show the call stack
+  history                 list the commands entered so far
+  !!, !N                  repeat the last or the Nth command, an empty line repeats too
+  quit                (q) leave the debugger
+Ctrl-C stops a running command, arrow keys go through the history.";
+
+const DEFAULT_DUMP_LENGTH: u16 = 16;
+const DEFAULT_DISASSEMBLY_LENGTH: u16 = 10;
+
+/**
+ * A gdb-like debugger on the terminal, used by `bricoboy debug`.
+ * Runs on the current thread, `continue` only returns at a breakpoint or on Ctrl-C.
+ */
+pub(crate) struct Repl<'d> {
+    device: &'d mut Device,
+    /// The commands entered so far, oldest first
+    history: Vec<String>,
+}
+
+impl<'d> Repl<'d> {
+    pub(crate) fn new(device: &'d mut Device) -> Repl<'d> {
+        Repl {
+            device,
+            history: Vec::new(),
+        }
+    }
+
+    pub(crate) fn run(&mut self) -> Result<(), String> {
+        println!("Type 'help' for the list of commands");
+        self.print_location();
+
+        let mut editor =
+            DefaultEditor::new().map_err(|e| format!("Failed to set up the line editor: {}", e))?;
+        let interrupted = self.device.interrupted.clone();
+        ctrlc::set_handler(move || interrupted.store(true, Ordering::Relaxed))
+            .map_err(|e| format!("Failed to set up the Ctrl-C handler: {}", e))?;
+
+        loop {
+            let line = match editor.readline("(bricoboy) ") {
+                Ok(line) => line,
+                // at the prompt Ctrl-C only discards the line
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => {
+                    println!();
+                    return Ok(());
+                }
+                Err(e) => return Err(format!("Failed to read the command: {}", e)),
+            };
+
+            let command = match self.expand_history(line.trim()) {
+                Ok(Some(command)) => command,
+                Ok(None) => continue,
+                Err(e) => {
+                    println!("{}", e);
+                    continue;
+                }
+            };
+            if !line.trim().is_empty() {
+                let _ = editor.add_history_entry(command.as_str());
+            }
+
+            match self.execute(&command) {
+                Ok(true) => return Ok(()),
+                Ok(false) => {}
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+
+    /**
+     * Resolves `!!`, `!N` and empty lines to a command from the history,
+     * and records new commands. `None` if there is nothing to run.
+     */
+    fn expand_history(&mut self, line: &str) -> Result<Option<String>, String> {
+        let command = match line {
+            "" => return Ok(self.history.last().cloned()),
+            "!!" => self.history.last().cloned(),
+            _ => match line.strip_prefix('!') {
+                Some(number) => {
+                    let number = number
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid history reference '{}'", line))?;
+                    number
+                        .checked_sub(1)
+                        .and_then(|index| self.history.get(index))
+                        .cloned()
+                }
+                None => Some(line.to_string()),
+            },
+        };
+
+        let Some(command) = command else {
+            return Err(format!("No command {} in the history", line));
+        };
+        if line.starts_with('!') {
+            println!("{}", command);
+        }
+        self.history.push(command.clone());
+
+        Ok(Some(command))
+    }
+
+    /// Runs a single command, `true` if the debugger should quit
+    fn execute(&mut self, line: &str) -> Result<bool, String> {
+        let (command, args) = line
+            .split_once(char::is_whitespace)
+            .map(|(command, args)| (command, args.trim()))
+            .unwrap_or((line, ""));
+
+        match command {
+            "break" | "b" if args.is_empty() => self.list_breakpoints(),
+            "break" | "b" => {
+                let breakpoint = Breakpoint::parse(args, &self.device.symbols)?;
+                let description = match breakpoint.kind {
+                    BreakpointKind::Execute { address, bank } => {
+                        self.device.symbols.format_address(bank, address)
+                    }
+                    BreakpointKind::Watch { .. } => breakpoint.kind.to_string(),
+                };
+                let id = self.device.add_breakpoint(breakpoint);
+                println!("Breakpoint #{} at {}", id, description);
+            }
+            "delete" | "d" => {
+                let id = args
+                    .parse::<u32>()
+                    .map_err(|_| format!("Invalid breakpoint id '{}'", args))?;
+                if !self.device.remove_breakpoint(id) {
+                    return Err(format!("No breakpoint #{}", id));
+                }
+            }
+            "step" | "s" => {
+                let count = match args {
+                    "" => 1,
+                    count => count
+                        .parse::<u32>()
+                        .map_err(|_| format!("Invalid count '{}'", count))?,
+                };
+                self.device.interrupted.store(false, Ordering::Relaxed);
+                self.device.run_guarded(|device| {
+                    for _ in 0..count {
+                        if device.interrupted.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        device.step();
+                    }
+                });
+                self.print_location();
+            }
+            "next" | "n" => {
+                match RunTarget::step_over(self.device) {
+                    Some(target) => self.run_until(Some(target)),
+                    None => self.device.run_guarded(Device::step),
+                }
+                self.print_location();
+            }
+            "finish" => {
+                if self.device.cpu.call_stack.depth() == 0 {
+                    return Err("Not inside a call".to_string());
+                }
+                self.run_until(Some(RunTarget::step_out(self.device)));
+                self.print_location();
+            }
+            "continue" | "c" => {
+                self.run_until(None);
+                self.print_location();
+            }
+            "reverse-step" | "rs" => {
+                self.device.run_guarded(Device::step_back)?;
+                self.print_location();
+            }
+            "reverse-continue" | "rc" => {
+                self.device.run_guarded(Device::reverse_continue)?;
+                self.print_location();
+            }
+            "regs" | "r" => self.print_registers(),
+            "x" => self.dump_memory(DEFAULT_DUMP_LENGTH, args)?,
+            "disasm" => {
+                let mut args = args.split_whitespace();
+                let address = match args.next() {
+                    Some(address) => parse_address(address, &self.device.symbols)?,
+                    None => *self.device.cpu.register_set.pc(),
+                };
+                let count = match args.next() {
+                    Some(count) => count
+                        .parse::<u16>()
+                        .map_err(|_| format!("Invalid count '{}'", count))?,
+                    None => DEFAULT_DISASSEMBLY_LENGTH,
+                };
+                self.print_disassembly(address, count);
+            }
+            "set" => {
+                let (register, value) = args
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| "Usage: set REG VALUE".to_string())?;
+                self.set_register(register, value.trim())?;
+            }
+            "write" | "w" => {
+                let (address, value) = args
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| "Usage: write ADDR VALUE".to_string())?;
+                let address = parse_address(address, &self.device.symbols)?;
+                let value = parse_byte(value.trim())?;
+                if !is_editable(address) {
+                    return Err(format!(
+                        "0x{:04x} ({}) is not writable",
+                        address,
+                        region_name(address)
+                    ));
+                }
+                self.device.mem_map.write_byte(address, value);
+            }
+            "bt" | "backtrace" => {
+                println!("At {}", self.pc_location());
+                print!(
+                    "{}",
+                    self.device.cpu.call_stack.backtrace(&self.device.symbols)
+                );
+            }
+            "history" => {
+                for (index, command) in self.history.iter().enumerate() {
+                    println!("{:>4}  {}", index + 1, command);
+                }
+            }
+            "help" | "h" => println!("{}", HELP),
+            "quit" | "q" => return Ok(true),
+            // x/COUNT ADDR
+            _ if command.starts_with("x/") => {
+                let count = command[2..]
+                    .parse::<u16>()
+                    .map_err(|_| format!("Invalid count in '{}'", command))?;
+                self.dump_memory(count, args)?;
+            }
+            _ => return Err(format!("Unknown command '{}', try 'help'", command)),
+        }
+
+        Ok(false)
+    }
+
+    fn run_until(&mut self, target: Option<RunTarget>) {
+        self.device.run_target = target;
+        self.device.run();
+    }
+
+    fn list_breakpoints(&self) {
+        let breakpoints = self.device.breakpoints.breakpoints();
+        if breakpoints.is_empty() {
+            println!("No breakpoints");
+        }
+        for breakpoint in breakpoints {
+            println!("{}", breakpoint);
+        }
+    }
+
+    fn pc_location(&self) -> String {
+        let pc = *self.device.cpu.register_set.pc();
+        let bank = self.device.mem_map.rom_bank_for_address(pc);
+        self.device.symbols.format_address(bank, pc)
+    }
+
+    fn print_location(&self) {
+        self.print_disassembly(*self.device.cpu.register_set.pc(), 1);
+    }
+
+    /// Prints `count` instructions from `address` on, the one at PC marked with `=>`
+    fn print_disassembly(&self, address: u16, count: u16) {
+        let pc = *self.device.cpu.register_set.pc();
+        let symbols = &self.device.symbols;
+        let mapped_bank = self.device.mem_map.rom_bank_for_address(0x4000);
+
+        let mut address = address;
+        for _ in 0..count {
+            let bank = self.device.mem_map.rom_bank_for_address(address);
+            if let Some(label) = symbols.label(mapped_bank, address) {
+                println!("{}:", label);
+            }
+
+            let disassembled = disassemble(&self.device.mem_map, bank.unwrap_or(0), address);
+            let text = format_instruction(&disassembled, &|target| {
+                symbols.label(mapped_bank, target).map(str::to_string)
+            });
+            let marker = if address == pc { "=>" } else { "  " };
+            println!("{} {:04x}  {}", marker, address, text);
+
+            address = address.wrapping_add(disassembled.size());
+        }
+    }
+
+    fn print_registers(&self) {
+        let registers = &self.device.cpu.register_set;
+        let flag = |flag: Flag, name: char| match registers.get_flag(flag) {
+            true => name,
+            false => '-',
+        };
+
+        println!(
+            "AF {:04x}  BC {:04x}  DE {:04x}  HL {:04x}",
+            registers.get_w(WordRegister::AF),
+            registers.get_w(WordRegister::BC),
+            registers.get_w(WordRegister::DE),
+            registers.get_w(WordRegister::HL)
+        );
+        println!(
+            "SP {:04x}  PC {}",
+            registers.get_w(WordRegister::SP),
+            self.pc_location()
+        );
+        println!(
+            "Flags {}{}{}{}",
+            flag(Flag::Zero, 'Z'),
+            flag(Flag::Subtract, 'N'),
+            flag(Flag::HalfCarry, 'H'),
+            flag(Flag::Carry, 'C')
+        );
+    }
+
+    /// Prints `count` bytes from the address in `args` on, 16 per line
+    fn dump_memory(&self, count: u16, args: &str) -> Result<(), String> {
+        if args.is_empty() {
+            return Err("Usage: x[/COUNT] ADDR".to_string());
+        }
+        let start = parse_address(args, &self.device.symbols)?;
+
+        for line_start in (0..count).step_by(16) {
+            let address = start.wrapping_add(line_start);
+            let bytes = (0..(count - line_start).min(16))
+                .map(|offset| {
+                    let byte = self.device.mem_map.peek_byte(address.wrapping_add(offset));
+                    format!("{:02x}", byte)
+                })
+                .collect::<Vec<String>>();
+            println!("{:04x}  {}", address, bytes.join(" "));
+        }
+
+        Ok(())
+    }
+
+    fn set_register(&mut self, register: &str, value: &str) -> Result<(), String> {
+        let registers = &mut self.device.cpu.register_set;
+
+        if let Some(register) = ByteRegister::from_name(register) {
+            registers.set_b(register, parse_byte(value)?);
+        } else if let Some(register) = WordRegister::from_name(register) {
+            registers.set_w(register, parse_address(value, &self.device.symbols)?);
+        } else {
+            return Err(format!("Unknown register '{}'", register));
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_byte(value: &str) -> Result<u8, String> {
+    let digits = value.trim_start_matches("0x").trim_start_matches('$');
+    u8::from_str_radix(digits, 16).map_err(|e| format!("Invalid byte '{}': {}", value, e))
+}
@@ -0,0 +1,235 @@
+use std::mem;
+
+use crate::{
+    device::{device::Device, movie::MovieMode, rewind::RewindBuffer},
+    logging::log::{Log, Logger, SilentLogger},
+};
+
+use super::{
+    breakpoints::{BreakpointHit, BreakpointManager},
+    history::ExecutionHistory,
+    stepping::FRAME_CYCLES,
+};
+
+/// A checkpoint every frame, so going back one instruction runs at most a frame again
+const CHECKPOINT_INTERVAL: u64 = FRAME_CYCLES;
+/// About 10 seconds back
+const CHECKPOINT_CAPACITY: usize = 600;
+const NO_HISTORY: &str = "No more reverse-execution history";
+
+/// The checkpoints to keep in `Device::rewind` while debugging
+pub(crate) fn checkpoints() -> RewindBuffer {
+    RewindBuffer::new(CHECKPOINT_CAPACITY, CHECKPOINT_INTERVAL)
+}
+
+/// What running again must not change, put aside while going back
+struct Aside {
+    logger: Box<dyn Logger>,
+    serial_buffer: Vec<u8>,
+    history: ExecutionHistory,
+    /// With the hit counts from before
+    breakpoints: BreakpointManager,
+    throttle: bool,
+    /// Whether the movie was read-only, it plays what was recorded while going back
+    movie_read_only: Option<bool>,
+}
+
+/**
+ * Reverse execution: the device goes back to the checkpoint before the point to reach and
+ * runs forward again up to it, unthrottled and without logging. The emulation is deterministic,
+ * so this reproduces the past exactly, except for changes made by hand while paused,
+ * which are lost when going back past them. The checkpoints after that point are dropped.
+ *
+ * A movie is played read-only meanwhile, so the input recorded is run again and kept.
+ * A recording then plays what was recorded after the point reached and records again at its end.
+ */
+impl Device {
+    /// Goes back to before the last instruction
+    pub(crate) fn step_back(&mut self) -> Result<(), String> {
+        let now = self.cycle_count;
+        let aside = self.set_aside();
+
+        match self.find_previous_instruction(now) {
+            Ok((cycle_count, undone)) => {
+                self.go_to(cycle_count);
+                self.put_back(aside, undone);
+                Ok(())
+            }
+            Err(e) => {
+                self.put_back(aside, 0);
+                Err(e)
+            }
+        }
+    }
+
+    /**
+     * Goes back to the last breakpoint or watchpoint hit before now,
+     * or to the oldest checkpoint if there was none since.
+     */
+    pub(crate) fn reverse_continue(&mut self) -> Result<(), String> {
+        let now = self.cycle_count;
+        let aside = self.set_aside();
+
+        match self.find_previous_hit(now) {
+            Ok((cycle_count, undone, hit)) => {
+                self.go_to(cycle_count);
+                self.put_back(aside, undone);
+                match hit {
+                    Some(hit) => self.break_at(hit),
+                    None => self.logger.info(Log::Msg(format!(
+                        "{}, stopped at the oldest checkpoint",
+                        NO_HISTORY
+                    ))),
+                }
+                Ok(())
+            }
+            Err(e) => {
+                self.put_back(aside, 0);
+                Err(e)
+            }
+        }
+    }
+
+    /// Where the instruction before `now` started, and how many instructions going there undoes
+    fn find_previous_instruction(&mut self, now: u64) -> Result<(u64, u64), String> {
+        self.restore_checkpoint(now.checked_sub(1).ok_or(NO_HISTORY)?)?;
+
+        let mut previous = (self.cycle_count, self.history.recorded());
+        self.replay(now, |device| {
+            if device.cycle_count < now {
+                previous = (device.cycle_count, device.history.recorded());
+            }
+        });
+
+        Ok((previous.0, self.history.recorded() - previous.1))
+    }
+
+    /**
+     * Searches the checkpoint intervals from the newest to the oldest for the last hit before
+     * `now`. Returns where it happened, the instructions undone and the hit, which is `None`
+     * if there was no hit since the oldest checkpoint.
+     */
+    fn find_previous_hit(&mut self, now: u64) -> Result<(u64, u64, Option<BreakpointHit>), String> {
+        let mut end = now;
+        // the instructions from `end` to now
+        let mut undone = 0;
+
+        loop {
+            let restored = end
+                .checked_sub(1)
+                .map(|cycle_count| self.restore_checkpoint(cycle_count));
+            if !matches!(restored, Some(Ok(()))) {
+                return match end == now {
+                    true => Err(NO_HISTORY.to_string()),
+                    false => Ok((end, undone, None)),
+                };
+            }
+
+            let start = self.cycle_count;
+            let recorded_at_start = self.history.recorded();
+            let mut last_hit = None;
+            self.replay(end, |device| {
+                let accesses = device.mem_map.watch.take_accesses();
+                let mut hit = device.breakpoints.check_accesses(
+                    &accesses,
+                    &device.cpu.register_set,
+                    &device.mem_map,
+                );
+                // an instruction at `end` belongs to the interval searched before
+                if device.cycle_count < end {
+                    let bank = device
+                        .mem_map
+                        .rom_bank_for_address(*device.cpu.register_set.pc());
+                    hit = device
+                        .breakpoints
+                        .check_execute(&device.cpu.register_set, bank, &device.mem_map)
+                        .or(hit);
+                }
+                if let Some(hit) = hit.filter(|_| device.cycle_count < now) {
+                    last_hit = Some((device.cycle_count, device.history.recorded(), hit));
+                }
+            });
+            let recorded_at_end = self.history.recorded();
+
+            if let Some((cycle_count, recorded, hit)) = last_hit {
+                return Ok((cycle_count, recorded_at_end - recorded + undone, Some(hit)));
+            }
+            undone += recorded_at_end - recorded_at_start;
+            end = start;
+        }
+    }
+
+    /// Runs again from the checkpoint before `cycle_count` up to it
+    fn go_to(&mut self, cycle_count: u64) {
+        // it was found running from this checkpoint
+        self.restore_checkpoint(cycle_count).unwrap();
+        self.replay(cycle_count, |_| {});
+        self.mem_map.watch.take_accesses();
+    }
+
+    /// Goes back to the newest checkpoint at or before `cycle_count`, dropping the ones after it
+    fn restore_checkpoint(&mut self, cycle_count: u64) -> Result<(), String> {
+        let mut checkpoints = self
+            .rewind
+            .take()
+            .ok_or_else(|| "Reverse execution is not enabled".to_string())?;
+        let result = match checkpoints.newest_at(cycle_count) {
+            Some(checkpoint) => {
+                self.restore_snapshot(checkpoint);
+                Ok(())
+            }
+            None => Err(NO_HISTORY.to_string()),
+        };
+        self.rewind = Some(checkpoints);
+        result
+    }
+
+    /**
+     * Runs whole instructions until `cycle_count` is reached, without taking checkpoints.
+     * `inspect` sees the device at the start and after every instruction.
+     */
+    fn replay(&mut self, cycle_count: u64, mut inspect: impl FnMut(&mut Self)) {
+        let rewind = self.rewind.take();
+        self.mem_map.watch.take_accesses();
+
+        inspect(self);
+        while self.cycle_count < cycle_count {
+            self.step();
+            inspect(self);
+        }
+
+        self.rewind = rewind;
+    }
+
+    fn set_aside(&mut self) -> Aside {
+        Aside {
+            logger: mem::replace(&mut self.logger, Box::new(SilentLogger)),
+            serial_buffer: mem::take(&mut self.serial_buffer),
+            // only counts the instructions
+            history: mem::replace(&mut self.history, ExecutionHistory::new(0)),
+            breakpoints: self.breakpoints.clone(),
+            throttle: mem::replace(&mut self.throttle, false),
+            movie_read_only: self
+                .movie
+                .as_mut()
+                .map(|movie| mem::replace(&mut movie.read_only, true)),
+        }
+    }
+
+    /// Restores what was set aside, without the `undone` newest instructions in the history
+    fn put_back(&mut self, aside: Aside, undone: u64) {
+        self.logger = aside.logger;
+        self.serial_buffer = aside.serial_buffer;
+        self.history = aside.history;
+        self.history.drop_newest(undone);
+        self.breakpoints = aside.breakpoints;
+        self.throttle = aside.throttle;
+        if let (Some(movie), Some(read_only)) = (&mut self.movie, aside.movie_read_only) {
+            movie.read_only = read_only;
+            // a read-write movie does not finish, it records at its end
+            if !read_only && movie.mode == MovieMode::Finished {
+                movie.mode = MovieMode::Recording;
+            }
+        }
+    }
+}
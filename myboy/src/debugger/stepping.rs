@@ -0,0 +1,81 @@
+use crate::{
+    cpu::instruction::{Instruction, InstructionType},
+    device::device::Device,
+};
+
+/// The T-cycles of one frame, used to end a frame advance while the LCD is off
+pub(crate) const FRAME_CYCLES: u64 = 70224;
+
+/**
+ * Where a run started by the debugger stops again, besides breakpoints.
+ */
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum RunTarget {
+    /// Back at `return_address` after the call, without being deeper in the stack
+    StepOver {
+        return_address: u16,
+        call_depth: usize,
+    },
+    /// Returned from the routine running at `call_depth`
+    StepOut {
+        call_depth: usize,
+    },
+    Address(u16),
+    /// The PPU finished the frame it was drawing when the run started
+    NextFrame {
+        frame_count: u64,
+        cycle_count: u64,
+    },
+}
+
+impl RunTarget {
+    /**
+     * The target for stepping over the instruction at PC,
+     * `None` if it is no call or rst and a single step does the same.
+     */
+    pub(crate) fn step_over(device: &Device) -> Option<RunTarget> {
+        let pc = *device.cpu.register_set.pc();
+        let instruction = Instruction::create(pc, &device.mem_map).ok()?;
+
+        match instruction.instruction_type {
+            InstructionType::Call | InstructionType::Reset => Some(RunTarget::StepOver {
+                return_address: pc.wrapping_add(instruction.size() as u16),
+                call_depth: device.cpu.call_stack.depth(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn step_out(device: &Device) -> RunTarget {
+        RunTarget::StepOut {
+            call_depth: device.cpu.call_stack.depth(),
+        }
+    }
+
+    pub(crate) fn next_frame(device: &Device) -> RunTarget {
+        RunTarget::NextFrame {
+            frame_count: device.ppu.frame_count,
+            cycle_count: device.cycle_count,
+        }
+    }
+
+    pub(crate) fn is_reached(&self, device: &Device) -> bool {
+        let pc = *device.cpu.register_set.pc();
+
+        match *self {
+            RunTarget::StepOver {
+                return_address,
+                call_depth,
+            } => pc == return_address && device.cpu.call_stack.depth() <= call_depth,
+            RunTarget::StepOut { call_depth } => device.cpu.call_stack.depth() < call_depth,
+            RunTarget::Address(address) => pc == address,
+            RunTarget::NextFrame {
+                frame_count,
+                cycle_count,
+            } => {
+                device.ppu.frame_count > frame_count
+                    || (!device.ppu_enabled() && device.cycle_count - cycle_count >= FRAME_CYCLES)
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::{cell::RefCell, fmt::Display, ops::RangeInclusive};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AccessKind {
+    Read,
+    Write,
+}
+
+/// Which accesses a watchpoint reacts to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+impl WatchKind {
+    pub(crate) fn matches(&self, access: AccessKind) -> bool {
+        matches!(
+            (self, access),
+            (WatchKind::Access, _)
+                | (WatchKind::Read, AccessKind::Read)
+                | (WatchKind::Write, AccessKind::Write)
+        )
+    }
+}
+
+impl Display for WatchKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchKind::Read => write!(f, "read"),
+            WatchKind::Write => write!(f, "write"),
+            WatchKind::Access => write!(f, "access"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MemoryAccess {
+    pub(crate) address: u16,
+    pub(crate) value: u8,
+    pub(crate) kind: AccessKind,
+}
+
+/**
+ * Records the memory accesses hitting a watched range, for the breakpoints to check after each step.
+ *
+ * Accesses are only recorded while `armed`, which the device only is while the CPU runs,
+ * so the PPU and the debugger reading memory do not trigger watchpoints.
+ */
+#[derive(Default)]
+pub(crate) struct MemoryWatch {
+    ranges: Vec<(RangeInclusive<u16>, WatchKind)>,
+    pub(crate) armed: bool,
+    accesses: RefCell<Vec<MemoryAccess>>,
+}
+
+impl MemoryWatch {
+    pub(crate) fn set_ranges(&mut self, ranges: Vec<(RangeInclusive<u16>, WatchKind)>) {
+        self.ranges = ranges;
+        self.accesses.get_mut().clear();
+    }
+
+    pub(crate) fn record(&self, address: u16, value: u8, kind: AccessKind) {
+        if !self.armed || self.ranges.is_empty() {
+            return;
+        }
+
+        let watched = self
+            .ranges
+            .iter()
+            .any(|(range, watch_kind)| range.contains(&address) && watch_kind.matches(kind));
+        if watched {
+            self.accesses.borrow_mut().push(MemoryAccess {
+                address,
+                value,
+                kind,
+            });
+        }
+    }
+
+    pub(crate) fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        std::mem::take(self.accesses.get_mut())
+    }
+}
+
+/**
+ * The T-cycle every address was last written at, for the memory view to highlight recent writes.
+ * The device advances `now` on every cycle.
+ */
+#[derive(Clone)]
+pub(crate) struct WriteHistory {
+    /// One past the cycle of the last write, 0 if there was none
+    written_at: Box<[u64]>,
+    pub(crate) now: u64,
+}
+
+impl Default for WriteHistory {
+    fn default() -> Self {
+        WriteHistory {
+            written_at: vec![0; 0x10000].into_boxed_slice(),
+            now: 0,
+        }
+    }
+}
+
+impl WriteHistory {
+    pub(crate) fn record(&mut self, address: u16) {
+        self.written_at[address as usize] = self.now + 1;
+    }
+
+    /// The cycles since the last write to `address`, `None` if it was never written
+    pub(crate) fn age(&self, address: u16) -> Option<u64> {
+        match self.written_at[address as usize] {
+            0 => None,
+            written_at => Some((self.now + 1).saturating_sub(written_at)),
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use crate::{
+    cpu::{
+        cpu::InterruptMasterEnableStatus,
+        register_set::{ByteRegister, WordRegister},
+    },
+    ppu::ppu::PPU,
+};
+
+use super::{
+    device::Device,
+    save_state::{StateReader, StateWriter},
+};
+
+const FOOTER_MAGIC: &[u8; 4] = b"BESS";
+const FOOTER_LENGTH: usize = 8;
+const MAJOR_VERSION: u16 = 1;
+const MINOR_VERSION: u16 = 1;
+/// The model identifier of the original Game Boy
+const MODEL_DMG: &[u8; 4] = b"GD  ";
+const CORE_LENGTH: u32 = 0xd0;
+const INFO_LENGTH: u32 = 0x20;
+const RTC_LENGTH: usize = 0x30;
+/// The registers in the order of the CORE block
+const CORE_REGISTERS: [WordRegister; 6] = [
+    WordRegister::PC,
+    WordRegister::AF,
+    WordRegister::BC,
+    WordRegister::DE,
+    WordRegister::HL,
+    WordRegister::SP,
+];
+
+/**
+ * The Best Effort Save State format of SameBoy, which other emulators understand as well.
+ * It is appended to our own state: the memories follow the native state, then come the blocks
+ * describing the hardware, and the file ends with the offset of the first block and `BESS`.
+ * See https://github.com/LIJI32/SameBoy/blob/master/BESS.md
+ *
+ * Only what the emulator has is exchanged: there is no mapper, cartridge RAM or RTC yet,
+ * so those blocks are not written and ignored when read. The PPU starts over at the
+ * beginning of the current line, as BESS has no PPU internals.
+ */
+impl Device {
+    /// The native state with the BESS blocks appended
+    pub(crate) fn save_bess(&self) -> Vec<u8> {
+        let mut writer = StateWriter::default();
+        writer.bytes(&self.save_state());
+
+        let mem_map = &self.mem_map;
+        let buffers: [&[u8]; 4] = [
+            &mem_map.working_ram.data,
+            &mem_map.video_ram.data,
+            &mem_map.object_attribute_memory.data,
+            &mem_map.hram.data,
+        ];
+        let mut locations = Vec::new();
+        for buffer in buffers {
+            locations.push((buffer.len() as u32, writer.len() as u32));
+            writer.bytes(buffer);
+        }
+        let [ram, vram, oam, hram] = locations[..] else {
+            unreachable!()
+        };
+
+        let first_block = writer.len() as u32;
+
+        let name = format!("bricoboy {}", env!("CARGO_PKG_VERSION"));
+        block_header(&mut writer, b"NAME", name.len() as u32);
+        writer.bytes(name.as_bytes());
+
+        block_header(&mut writer, b"INFO", INFO_LENGTH);
+        for address in 0x0134..0x0144 {
+            writer.u8(self.cartridge.read_byte(address));
+        }
+        writer.u8(self.cartridge.read_byte(0x014e));
+        writer.u8(self.cartridge.read_byte(0x014f));
+        writer.bytes(&[0; 14]);
+
+        block_header(&mut writer, b"CORE", CORE_LENGTH);
+        writer.u16(MAJOR_VERSION);
+        writer.u16(MINOR_VERSION);
+        writer.bytes(MODEL_DMG);
+
+        let registers = &self.cpu.register_set;
+        for register in CORE_REGISTERS {
+            writer.u16(registers.get_w(register));
+        }
+
+        writer.bool(!matches!(
+            self.cpu.interrupt_master_enable,
+            InterruptMasterEnableStatus::Disabled
+        ));
+        writer.u8(mem_map.io_registers.ie_register.0);
+        writer.u8(match (self.cpu.halted, self.cpu.stopped) {
+            (_, true) => 2,
+            (true, false) => 1,
+            (false, false) => 0,
+        });
+        writer.u8(0);
+
+        for address in 0xff00..=0xff7f {
+            writer.u8(mem_map.io_registers.read_byte(address));
+        }
+
+        // RAM, VRAM, MBC RAM, OAM, HRAM, and the CGB palettes
+        for (size, offset) in [ram, vram, (0, 0), oam, hram, (0, 0), (0, 0)] {
+            writer.u32(size);
+            writer.u32(offset);
+        }
+
+        block_header(&mut writer, b"END ", 0);
+
+        writer.u32(first_block);
+        writer.bytes(FOOTER_MAGIC);
+        writer.into_bytes()
+    }
+
+    /**
+     * Loads the BESS blocks of a state, whatever emulator wrote it.
+     * The state must be from the same ROM if it tells which one it is.
+     * All blocks are read before the device is touched.
+     */
+    pub(crate) fn load_bess(&mut self, state: &[u8]) -> Result<(), String> {
+        let blocks = find_blocks(state)?;
+        let mut reader = StateReader::new(blocks);
+        let mut core = None;
+
+        loop {
+            let mut id = [0u8; 4];
+            reader.bytes(&mut id)?;
+            let length = reader.u32()? as usize;
+            let content = reader.byte_vec(length)?;
+
+            match &id {
+                b"END " => break,
+                b"INFO" => self.check_info(&content)?,
+                b"CORE" => core = Some(read_core(&content, state)?),
+                // no mapper yet, the writes setting it up have nowhere to go
+                b"MBC " if !length.is_multiple_of(3) => {
+                    return Err("Invalid MBC block in the BESS state".to_string());
+                }
+                // and no real time clock either
+                b"RTC " if length != RTC_LENGTH => {
+                    return Err("Invalid RTC block in the BESS state".to_string());
+                }
+                // NAME, XOAM and the blocks of other hardware
+                _ => {}
+            }
+        }
+
+        let Some(core) = core else {
+            return Err("The BESS state has no CORE block".to_string());
+        };
+        self.load_core(core);
+        self.seek_movie();
+        Ok(())
+    }
+
+    fn check_info(&self, content: &[u8]) -> Result<(), String> {
+        if content.len() < 0x12 {
+            return Err("Invalid INFO block in the BESS state".to_string());
+        }
+        let checksum = [
+            self.cartridge.read_byte(0x014e),
+            self.cartridge.read_byte(0x014f),
+        ];
+        match content[0x10..0x12] == checksum {
+            true => Ok(()),
+            false => Err("The BESS state is for a different ROM".to_string()),
+        }
+    }
+
+    fn load_core(&mut self, core: Core) {
+        let registers = &mut self.cpu.register_set;
+        for (register, value) in CORE_REGISTERS.into_iter().zip(core.registers) {
+            registers.set_w(register, value);
+        }
+        // the lower bits of F do not exist
+        let f = *registers.get_b(ByteRegister::F);
+        registers.set_b(ByteRegister::F, f & 0xf0);
+
+        self.cpu.interrupt_master_enable = match core.ime {
+            false => InterruptMasterEnableStatus::Disabled,
+            true => InterruptMasterEnableStatus::Enabled,
+        };
+        self.cpu.halted = core.execution_state == 1;
+        self.cpu.stopped = core.execution_state == 2;
+
+        for (offset, value) in core.io.iter().enumerate() {
+            self.mem_map
+                .io_registers
+                .restore_byte(0xff00 + offset as u16, *value);
+        }
+        self.mem_map.io_registers.ie_register.0 = core.ie;
+
+        let mem_map = &mut self.mem_map;
+        let targets: [&mut [u8]; 5] = [
+            &mut mem_map.working_ram.data,
+            &mut mem_map.video_ram.data,
+            &mut [],
+            &mut mem_map.object_attribute_memory.data,
+            &mut mem_map.hram.data,
+        ];
+        for (target, buffer) in targets.into_iter().zip(core.memories) {
+            // the CGB has more of some memories, the first banks are the ones we have
+            let length = buffer.len().min(target.len());
+            target[..length].copy_from_slice(&buffer[..length]);
+        }
+
+        // what the CPU and the PPU were doing in detail is not known
+        self.cpu.current_instruction = None;
+        self.cpu.last_state = None;
+        self.cpu.call_stack = Default::default();
+        let frame_count = self.ppu.frame_count;
+        self.ppu = PPU::new();
+        self.ppu.frame_count = frame_count;
+    }
+}
+
+/// What the CORE block holds, read before any of it is loaded
+struct Core<'s> {
+    registers: [u16; 6],
+    ime: bool,
+    ie: u8,
+    execution_state: u8,
+    io: [u8; 0x80],
+    /// RAM, VRAM, MBC RAM, OAM and HRAM, in the state the block is part of
+    memories: [&'s [u8]; 5],
+}
+
+fn read_core<'s>(content: &[u8], state: &'s [u8]) -> Result<Core<'s>, String> {
+    if content.len() < CORE_LENGTH as usize {
+        return Err("Invalid CORE block in the BESS state".to_string());
+    }
+    let mut reader = StateReader::new(content);
+
+    let major = reader.u16()?;
+    if major != MAJOR_VERSION {
+        return Err(format!(
+            "BESS version {} is not supported, only version {}",
+            major, MAJOR_VERSION
+        ));
+    }
+    reader.u16()?;
+    // any model is fine, states of the other models just have more of everything
+    reader.bytes(&mut [0u8; 4])?;
+
+    let mut registers = [0u16; 6];
+    for register in &mut registers {
+        *register = reader.u16()?;
+    }
+    let ime = reader.u8()? != 0;
+    let ie = reader.u8()?;
+    let execution_state = reader.u8()?;
+    reader.u8()?;
+
+    let mut io = [0u8; 0x80];
+    reader.bytes(&mut io)?;
+
+    let mut memories: [&[u8]; 5] = [&[]; 5];
+    for memory in &mut memories {
+        let size = reader.u32()? as usize;
+        let offset = reader.u32()? as usize;
+        *memory = state
+            .get(offset..offset + size)
+            .ok_or_else(|| "A memory of the BESS state is out of bounds".to_string())?;
+    }
+
+    Ok(Core {
+        registers,
+        ime,
+        ie,
+        execution_state,
+        io,
+        memories,
+    })
+}
+
+/// The blocks part of a BESS state, from the first block to the footer
+fn find_blocks(state: &[u8]) -> Result<&[u8], String> {
+    let footer_start = state
+        .len()
+        .checked_sub(FOOTER_LENGTH)
+        .filter(|start| &state[start + 4..] == FOOTER_MAGIC)
+        .ok_or_else(|| "Not a BESS state".to_string())?;
+
+    let first_block =
+        u32::from_le_bytes(state[footer_start..footer_start + 4].try_into().unwrap()) as usize;
+    state
+        .get(first_block..footer_start)
+        .ok_or_else(|| "Invalid offset of the BESS blocks".to_string())
+}
+
+/// Whether `state` ends with BESS blocks
+pub(crate) fn has_bess(state: &[u8]) -> bool {
+    state.ends_with(FOOTER_MAGIC)
+}
+
+fn block_header(writer: &mut StateWriter, id: &[u8; 4], length: u32) {
+    writer.bytes(id);
+    writer.u32(length);
+}
+
+#[cfg(test)]
+mod tests {
+    use mygbcartridge::cartridge::Cartridge;
+
+    use crate::{cpu::register_set::WordRegister, device::device::Device};
+
+    fn cartridge() -> Cartridge {
+        let mut data = vec![0u8; 0x8000];
+        data[0x0134..0x0138].copy_from_slice(b"TEST");
+        data[0x014e] = 0x12;
+        data[0x014f] = 0x34;
+        Cartridge { data }
+    }
+
+    fn device() -> Device {
+        let mut device = Device::new(cartridge());
+        device.cpu.register_set.set_w(WordRegister::PC, 0x0150);
+        device.cpu.register_set.set_w(WordRegister::AF, 0x12b0);
+        device.cpu.register_set.set_w(WordRegister::HL, 0xc0de);
+        device.cpu.register_set.set_w(WordRegister::SP, 0xdff0);
+        device.cpu.halted = true;
+        device.mem_map.write_byte(0xc123, 0x42);
+        device.mem_map.write_byte(0x9800, 0x07);
+        device.mem_map.write_byte(0xfe00, 0x10);
+        device.mem_map.write_byte(0xff90, 0x99);
+        device.mem_map.write_byte(0xff06, 0x80);
+        device.mem_map.write_byte(0xff40, 0x91);
+        device.mem_map.write_byte(0xffff, 0x05);
+        device
+    }
+
+    #[test]
+    fn round_trips_a_state() {
+        let original = device();
+        let state = original.save_bess();
+
+        let mut loaded = Device::new(cartridge());
+        loaded.load_bess(&state).unwrap();
+
+        assert_eq!(
+            loaded.cpu.register_set.to_string(),
+            original.cpu.register_set.to_string()
+        );
+        assert!(loaded.cpu.halted);
+        assert_eq!(
+            loaded.mem_map.working_ram.data,
+            original.mem_map.working_ram.data
+        );
+        assert_eq!(
+            loaded.mem_map.video_ram.data,
+            original.mem_map.video_ram.data
+        );
+        assert_eq!(
+            loaded.mem_map.object_attribute_memory.data,
+            original.mem_map.object_attribute_memory.data
+        );
+        assert_eq!(loaded.mem_map.hram.data, original.mem_map.hram.data);
+        for address in (0xff00..=0xff7f).chain([0xffff]) {
+            assert_eq!(
+                loaded.mem_map.peek_byte(address),
+                original.mem_map.peek_byte(address),
+                "IO register {:04x}",
+                address
+            );
+        }
+
+        // the BESS part comes after the native state, which still loads on its own
+        let mut native = Device::new(cartridge());
+        native.load_state(&state).unwrap();
+        assert_eq!(native.save_bess(), state);
+    }
+
+    #[test]
+    fn rejects_states_of_other_roms() {
+        let state = device().save_bess();
+
+        let mut other = cartridge();
+        other.data[0x014f] = 0x35;
+        assert!(Device::new(other).load_bess(&state).is_err());
+    }
+
+    #[test]
+    fn leaves_the_device_alone_on_broken_states() {
+        let mut loaded = Device::new(cartridge());
+        let before = loaded.save_state();
+
+        // a byte too many in the body, only noticed once all of it was read
+        let mut native = device().save_state();
+        let length = u32::from_le_bytes(native[14..18].try_into().unwrap());
+        native[14..18].copy_from_slice(&(length + 1).to_le_bytes());
+        native.push(0);
+        assert!(loaded.load_state(&native).is_err());
+        assert_eq!(loaded.save_state(), before);
+
+        // an RTC block of the wrong length after the CORE block
+        let mut bess = device().save_bess();
+        let end = bess.len() - 16;
+        bess.splice(end..end, *b"RTC \x01\0\0\0\0");
+        assert!(loaded.load_bess(&bess).is_err());
+        assert_eq!(loaded.save_state(), before);
+
+        // a block claiming to be longer than the whole state
+        let mut bess = device().save_bess();
+        let end = bess.len() - 16;
+        bess.splice(end..end, *b"MBC \xff\xff\xff\xff");
+        assert!(loaded.load_bess(&bess).is_err());
+        assert_eq!(loaded.save_state(), before);
+    }
+}
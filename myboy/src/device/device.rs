@@ -1,36 +1,78 @@
 use crate::{
-    cpu::CYCLE_LENGTH,
+    debugger::{
+        breakpoints::{Breakpoint, BreakpointHit, BreakpointKind, BreakpointManager},
+        history::{DEFAULT_HISTORY_LENGTH, ExecutionHistory},
+        stepping::{FRAME_CYCLES, RunTarget},
+    },
+    disasm::symbols::SymbolTable,
     logging::log::{ConsoleLogger, Log, Logger},
 };
-use std::{thread, time::Instant};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 
 use mygbcartridge::cartridge::Cartridge;
 
 use crate::{PPU, cpu::CPU};
 
-use super::mem_map::MemMap;
+use super::{
+    frame_hashes::FrameHashes, mem_map::MemMap, movie::Movie, pacing::FramePacer,
+    rewind::RewindBuffer,
+};
 
-pub(crate) struct Device<'a> {
-    pub ppu: PPU<'a>,
+pub(crate) struct Device {
+    pub ppu: PPU,
     pub cpu: CPU,
     pub mem_map: MemMap,
     pub screen: Box<[u8]>,
 
     pub speed_multiplier: f64,
+    /// Whether runs are slowed down to the speed of the real hardware, times the multiplier
+    pub throttle: bool,
+    pub pacer: FramePacer,
 
     pub running: bool,
+    /// Set from other threads, e.g. on Ctrl-C, to stop the run after the current instruction
+    pub interrupted: Arc<AtomicBool>,
 
     pub serial_buffer: Vec<u8>,
 
-    pub breakpoint: Option<u16>,
+    pub breakpoints: BreakpointManager,
+
+    /// Where the current run stops, if it was started by stepping over, out or to an address
+    pub run_target: Option<RunTarget>,
+
+    /// T-cycles run since the start
+    pub cycle_count: u64,
+
+    pub history: ExecutionHistory,
+    /// The buttons held on the keyboard, the joypad sees them from the next frame on
+    pub input: u8,
+    /// The movie being recorded or played
+    pub movie: Option<Movie>,
+    /// The hashes of the frames written or checked during a headless run
+    pub frame_hashes: Option<FrameHashes>,
+    /// The recent snapshots to go back to while playing, or the checkpoints for reverse execution
+    pub rewind: Option<RewindBuffer>,
+    /// Where the history is written to if the emulation panics
+    pub history_file: Option<PathBuf>,
 
     pub cartridge: Cartridge,
+    /// The file the cartridge was loaded from, save states are stored next to it
+    pub rom_file: Option<PathBuf>,
+    /// The labels of the ROM, empty if it has no symbol file
+    pub symbols: Arc<SymbolTable>,
 
     pub logger: Box<dyn Logger>,
 }
 
-impl<'a> Device<'a> {
-    pub fn new(cartridge: Cartridge) -> Device<'a> {
+impl Device {
+    pub fn new(cartridge: Cartridge) -> Device {
         let mem_map = MemMap::new(cartridge.clone());
         let cpu = CPU::new();
         let ppu = PPU::new();
@@ -48,52 +90,211 @@ impl<'a> Device<'a> {
             screen,
 
             cartridge,
+            rom_file: None,
             speed_multiplier: 1.0,
+            throttle: true,
+            pacer: FramePacer::default(),
             mem_map,
             running,
+            interrupted: Arc::default(),
             serial_buffer,
-            breakpoint: None,
+            breakpoints: BreakpointManager::default(),
+            run_target: None,
+            cycle_count: 0,
+            history: ExecutionHistory::new(DEFAULT_HISTORY_LENGTH),
+            input: 0,
+            movie: None,
+            frame_hashes: None,
+            rewind: None,
+            history_file: None,
+            symbols: Arc::default(),
             logger,
-            // breakpoint: Some(0xcb23),
         }
     }
 
+    pub(crate) fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> u32 {
+        let id = self.breakpoints.add(breakpoint);
+        self.update_watchpoints();
+        id
+    }
+
+    pub(crate) fn remove_breakpoint(&mut self, id: u32) -> bool {
+        let removed = self.breakpoints.remove(id);
+        self.update_watchpoints();
+        removed
+    }
+
+    pub(crate) fn set_breakpoint_enabled(&mut self, id: u32, enabled: bool) -> bool {
+        let found = self.breakpoints.set_enabled(id, enabled);
+        self.update_watchpoints();
+        found
+    }
+
+    /**
+     * Removes the execution breakpoint at `addr`, or adds an unconditional one if there is none.
+     */
     pub(crate) fn toggle_breakpoint(&mut self, addr: u16) {
-        match self.breakpoint {
-            Some(breakpoint) if breakpoint == addr => {
-                self.breakpoint = None;
+        match self.breakpoints.execute_breakpoint_at(addr) {
+            Some(breakpoint) => {
+                let id = breakpoint.id;
+                self.remove_breakpoint(id);
             }
-            _ => {
-                self.breakpoint = Some(addr);
+            None => {
+                self.add_breakpoint(Breakpoint::new(
+                    BreakpointKind::Execute {
+                        address: addr,
+                        bank: None,
+                    },
+                    None,
+                ));
             }
         }
     }
 
+    /**
+     * Resets the hardware like switching the Game Boy off and on again.
+     * Breakpoints and the other debugger state are kept.
+     */
+    pub(crate) fn power_on(&mut self) {
+        self.cpu = CPU::new();
+        self.ppu = PPU::new();
+        self.mem_map = MemMap::new(self.cartridge.clone());
+        self.update_watchpoints();
+        self.screen.fill(0xff);
+        self.cycle_count = 0;
+    }
+
+    fn update_watchpoints(&mut self) {
+        self.mem_map
+            .watch
+            .set_ranges(self.breakpoints.watch_ranges());
+    }
+
     pub fn run(&mut self) {
-        self.running = true;
-        let _ = self.run_loop();
+        self.start_run();
+        self.run_guarded(|device| {
+            while device.running {
+                device.run_frame();
+            }
+        });
+    }
+
+    /// Runs `run`, dumping the history if the emulation panics in it
+    pub(crate) fn run_guarded<T>(&mut self, run: impl FnOnce(&mut Self) -> T) -> T {
+        match panic::catch_unwind(AssertUnwindSafe(|| run(self))) {
+            Ok(result) => result,
+            Err(payload) => {
+                self.running = false;
+                self.dump_history();
+                panic::resume_unwind(payload);
+            }
+        }
+    }
+
+    /**
+     * Writes the history to the history file, including the instruction that was executing.
+     */
+    fn dump_history(&mut self) {
+        if let Some(state) = self.cpu.last_state.take() {
+            self.history.push(state);
+        }
+        let Some(path) = &self.history_file else {
+            return;
+        };
+
+        match self.history.dump(path, &self.symbols) {
+            Ok(()) => eprintln!(
+                "Execution aborted, wrote the last {} instructions to {}",
+                self.history.len(),
+                path.display()
+            ),
+            Err(e) => eprintln!("Execution aborted, {}", e),
+        }
     }
 
     pub(crate) fn ppu_enabled(&self) -> bool {
         self.mem_map.io_registers.get_lcdc_register().lcd_enabled()
     }
 
-    fn run_loop(&mut self) {
-        loop {
-            if let Some(addr) = self.breakpoint {
-                if *self.cpu.register_set.pc() == addr {
-                    println!("Breakpoint hit at 0x{:04x}", self.breakpoint.unwrap());
-                    self.running = false;
-                }
+    /**
+     * Starts a run, which goes on frame by frame in `run_frame` until `running` is cleared.
+     */
+    pub(crate) fn start_run(&mut self) {
+        self.running = true;
+        self.interrupted.store(false, Ordering::Relaxed);
+        // accesses from single steps before are not of interest anymore
+        self.mem_map.watch.take_accesses();
+        self.reset_pacing();
+    }
+
+    /**
+     * Runs until the frame ends or a breakpoint or the run target stops the run,
+     * then waits for the frame to be due.
+     */
+    pub(crate) fn run_frame(&mut self) {
+        let frame = self.cycle_count / FRAME_CYCLES;
+
+        while self.running && self.cycle_count / FRAME_CYCLES == frame {
+            self.step();
+
+            if self
+                .run_target
+                .is_some_and(|run_target| run_target.is_reached(self))
+            {
+                self.running = false;
             }
-            if !self.running {
-                break;
+            if self.interrupted.load(Ordering::Relaxed) {
+                self.interrupted.store(false, Ordering::Relaxed);
+                self.running = false;
+            }
+
+            let accesses = self.mem_map.watch.take_accesses();
+            if !accesses.is_empty()
+                && let Some(hit) = self.breakpoints.check_accesses(
+                    &accesses,
+                    &self.cpu.register_set,
+                    &self.mem_map,
+                )
+            {
+                self.break_at(hit);
+            }
+
+            // checked once PC is on it, so the breakpoint we might be paused at
+            // does not stop us right away when resuming
+            let bank = self
+                .mem_map
+                .rom_bank_for_address(*self.cpu.register_set.pc());
+            if let Some(hit) =
+                self.breakpoints
+                    .check_execute(&self.cpu.register_set, bank, &self.mem_map)
+            {
+                self.break_at(hit);
             }
-            self.step();
         }
+
+        if !self.running {
+            self.run_target = None;
+        }
+        self.pace();
+    }
+
+    pub(crate) fn break_at(&mut self, hit: BreakpointHit) {
+        let pc = *self.cpu.register_set.pc();
+        let bank = self.mem_map.rom_bank_for_address(pc);
+        let message = format!(
+            "{} at {}\n{}",
+            hit,
+            self.symbols.format_address(bank, pc),
+            self.cpu.call_stack.backtrace(&self.symbols)
+        );
+        self.logger.info(Log::Msg(message.trim_end().to_string()));
+        self.running = false;
     }
 
     pub fn step(&mut self) {
+        self.take_rewind_snapshot();
+        let frame = self.cycle_count / FRAME_CYCLES;
+
         loop {
             self.cycle();
             self.cycle();
@@ -106,38 +307,25 @@ impl<'a> Device<'a> {
         }
 
         self.check_serial();
+        if self.cycle_count / FRAME_CYCLES != frame {
+            self.next_frame();
+            self.hash_frame();
+        }
     }
 
     fn cycle(&mut self) {
-        let speed_multiplier = self.speed_multiplier;
-        unsafe {
-            let cycle_start = Instant::now();
-            // TODO: Maybe there's a more elegant way?
-            let raw_device_pointer = self as *mut Device as usize;
-            {
-                let raw_device = raw_device_pointer as *mut Device;
-                let device = &mut *raw_device;
-                device.cpu.cycle(&mut device.mem_map, self.logger.as_mut());
-            }
-            {
-                let raw_device = raw_device_pointer as *mut Device;
-                let device = &mut *raw_device;
-                if self.ppu_enabled() {
-                    device.ppu.cycle(&mut device.mem_map, self.screen.as_mut());
-                }
-            }
-            let cycle_duration = cycle_start.elapsed();
-            let cycle_rest = CYCLE_LENGTH.checked_sub(cycle_duration).unwrap_or_default();
-            if cycle_rest.as_nanos() > 0 {
-                let sleep_dur = cycle_rest.div_f64(speed_multiplier);
-                thread::sleep(sleep_dur);
-            }
+        self.cycle_count += 1;
+        self.mem_map.writes.now = self.cycle_count;
+
+        self.mem_map.watch.armed = true;
+        self.cpu.cycle(&mut self.mem_map, self.logger.as_mut());
+        self.mem_map.watch.armed = false;
+        if let Some(state) = self.cpu.last_state.take() {
+            self.history.push(state);
         }
-    }
 
-    pub fn draw_screen(&mut self, pixels: &mut [u8]) {
-        for i in 0..(160 * 144 * 4) {
-            pixels[i] = self.screen[i];
+        if self.ppu_enabled() {
+            self.ppu.cycle(&mut self.mem_map, self.screen.as_mut());
         }
     }
 
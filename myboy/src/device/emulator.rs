@@ -0,0 +1,326 @@
+use std::{
+    sync::{
+        Arc, Mutex, MutexGuard,
+        mpsc::{self, Receiver, Sender, TryRecvError},
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    cpu::register_set::{ByteRegister, Flag, WordRegister},
+    debugger::{
+        breakpoints::{Breakpoint, BreakpointManager},
+        call_stack::CallStack,
+        history::ExecutionHistory,
+        stepping::RunTarget,
+        watch::WriteHistory,
+    },
+    logging::log::{Log, SilentLogger},
+};
+
+use super::device::Device;
+
+/// What the UI asks the emulator thread to do, done in the order sent
+pub(crate) enum Command {
+    /// Runs until paused, or until a breakpoint is hit
+    Run,
+    Pause,
+    /// Runs a single instruction
+    Step,
+    StepOver,
+    StepOut,
+    /// Runs until the PPU finished the frame it is drawing
+    Frame,
+    /// Runs until PC reaches the address
+    RunTo(u16),
+    StepBack,
+    ReverseContinue,
+    /// Goes back to the newest rewind snapshot and pauses, see `Device::rewind_step`
+    Rewind,
+    AddBreakpoint(Breakpoint),
+    RemoveBreakpoint(u32),
+    SetBreakpointEnabled(u32, bool),
+    ToggleBreakpoint(u16),
+    SetRegister(ByteRegister, u8),
+    SetWordRegister(WordRegister, u16),
+    SetFlag(Flag, bool),
+    /// Writes the byte through the memory map, like the CPU would
+    WriteByte(u16, u8),
+    SaveSlot(u8),
+    LoadSlot(u8),
+    /// The buttons held, see `Device::input`
+    SetInput(u8),
+    SetSpeed(f64),
+    /// Switches the movie between read-only and read-write
+    ToggleReadOnly,
+    /// Asks for a snapshot once something changed, sent by the debugger on every repaint
+    Inspect(Panels),
+}
+
+/// What the debugger shows besides the hardware, only this is copied into snapshots
+#[derive(Clone, Copy, PartialEq, Default)]
+pub(crate) struct Panels {
+    pub(crate) history: bool,
+    pub(crate) writes: bool,
+}
+
+/// The last frame the PPU completed, with what the play window shows besides it
+pub(crate) struct Frame {
+    pub(crate) pixels: Box<[u8]>,
+    pub(crate) movie_status: Option<String>,
+}
+
+/**
+ * The device as the debugger shows it: the hardware as a save state and the debugger state
+ * the panels need besides it. The UI shows it in a device of its own, see `Device::show_snapshot`.
+ */
+pub(crate) struct Snapshot {
+    state: Vec<u8>,
+    call_stack: CallStack,
+    /// Only taken if the panels show it, like the writes
+    history: Option<ExecutionHistory>,
+    writes: Option<WriteHistory>,
+    breakpoints: BreakpointManager,
+    running: bool,
+    speed_multiplier: f64,
+    /// Whether it can go back in time
+    pub(crate) reversible: bool,
+    /// Why the last command failed
+    pub(crate) error: Option<String>,
+}
+
+/// What the emulator thread hands over to the UI
+struct Published {
+    /// The device draws the next frame into its own screen meanwhile
+    frame: Mutex<Frame>,
+    /// Published when asked for with `Command::Inspect`
+    snapshot: Mutex<Option<Snapshot>>,
+}
+
+/**
+ * The thread the device runs on. It owns the device and is told what to do with `Command`s,
+ * between two frames while running. The UI sees the frames it publishes and, if it asks
+ * for them, snapshots of the device. Nothing else is shared, so the UI never sees a device
+ * halfway through an instruction.
+ */
+pub(crate) struct Emulator {
+    commands: Sender<Command>,
+    published: Arc<Published>,
+    thread: JoinHandle<Device>,
+}
+
+impl Emulator {
+    pub(crate) fn start(device: Device) -> Emulator {
+        let (commands, receiver) = mpsc::channel();
+        let published = Arc::new(Published {
+            frame: Mutex::new(Frame {
+                pixels: device.screen.clone(),
+                movie_status: None,
+            }),
+            snapshot: Mutex::new(None),
+        });
+
+        let thread = {
+            let published = published.clone();
+            thread::spawn(move || run_thread(device, receiver, &published))
+        };
+        Emulator {
+            commands,
+            published,
+            thread,
+        }
+    }
+
+    /// Sends the command, it is dropped if the emulation aborted
+    pub(crate) fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+    }
+
+    pub(crate) fn frame(&self) -> MutexGuard<'_, Frame> {
+        self.published.frame.lock().unwrap()
+    }
+
+    /// The snapshot published since the last call, if any
+    pub(crate) fn take_snapshot(&self) -> Option<Snapshot> {
+        self.published.snapshot.lock().unwrap().take()
+    }
+
+    /// Stops the thread and returns the device, `None` if the emulation aborted
+    pub(crate) fn stop(self) -> Option<Device> {
+        drop(self.commands);
+        self.thread.join().ok()
+    }
+}
+
+fn run_thread(mut device: Device, commands: Receiver<Command>, published: &Published) -> Device {
+    // the panels of the snapshot asked for, and of the last one published
+    let mut wanted = None;
+    let mut shown = None;
+    // whether the device changed since the last snapshot
+    let mut changed = true;
+    // of the last command, shown until the next one
+    let mut error = None;
+
+    loop {
+        // while paused there is nothing to do but to wait for the next command
+        let command = match device.running {
+            true => match commands.try_recv() {
+                Ok(command) => Some(command),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            },
+            false => match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => break,
+            },
+        };
+
+        match command {
+            Some(Command::Inspect(panels)) => wanted = Some(panels),
+            Some(command) => {
+                error = device.execute(command).err();
+                if let Some(e) = &error {
+                    device.logger.error(Log::Msg(e.clone()));
+                }
+                changed = true;
+            }
+            None => {
+                device.run_guarded(|device| device.run_frame());
+                changed = true;
+            }
+        }
+
+        if changed {
+            device.publish_frame(published);
+        }
+        // so a run going on is not copied faster than the UI repaints
+        if let Some(panels) = wanted.filter(|panels| changed || shown != Some(*panels)) {
+            *published.snapshot.lock().unwrap() = Some(Snapshot {
+                error: error.clone(),
+                ..device.snapshot(panels)
+            });
+            wanted = None;
+            shown = Some(panels);
+            changed = false;
+        }
+    }
+
+    device.running = false;
+    device
+}
+
+impl Device {
+    fn execute(&mut self, command: Command) -> Result<(), String> {
+        match command {
+            Command::Run => self.start_run(),
+            Command::Pause => {
+                self.running = false;
+                self.run_target = None;
+            }
+            Command::Step => self.run_guarded(|device| device.step()),
+            Command::StepOver => match RunTarget::step_over(self) {
+                Some(target) => self.run_until(target),
+                None => self.run_guarded(|device| device.step()),
+            },
+            Command::StepOut => self.run_until(RunTarget::step_out(self)),
+            Command::Frame => self.run_until(RunTarget::next_frame(self)),
+            Command::RunTo(address) => self.run_until(RunTarget::Address(address)),
+            Command::StepBack => return self.step_back(),
+            Command::ReverseContinue => return self.reverse_continue(),
+            Command::Rewind => {
+                self.running = false;
+                self.rewind_step();
+            }
+            Command::AddBreakpoint(breakpoint) => {
+                self.add_breakpoint(breakpoint);
+            }
+            Command::RemoveBreakpoint(id) => {
+                self.remove_breakpoint(id);
+            }
+            Command::SetBreakpointEnabled(id, enabled) => {
+                self.set_breakpoint_enabled(id, enabled);
+            }
+            Command::ToggleBreakpoint(address) => self.toggle_breakpoint(address),
+            Command::SetRegister(register, value) => {
+                self.cpu.register_set.set_b(register, value);
+            }
+            Command::SetWordRegister(register, value) => {
+                self.cpu.register_set.set_w(register, value)
+            }
+            Command::SetFlag(flag, value) => self.cpu.register_set.set_flag(flag, value),
+            Command::WriteByte(address, value) => self.mem_map.write_byte(address, value),
+            Command::SaveSlot(slot) => {
+                let path = self.save_slot(slot)?;
+                let message = format!("Saved slot {} to {}", slot, path.display());
+                self.logger.info(Log::Msg(message));
+            }
+            Command::LoadSlot(slot) => {
+                let path = self.load_slot(slot)?;
+                let message = format!("Loaded slot {} from {}", slot, path.display());
+                self.logger.info(Log::Msg(message));
+            }
+            Command::SetInput(input) => self.input = input,
+            Command::SetSpeed(speed_multiplier) => self.speed_multiplier = speed_multiplier,
+            Command::ToggleReadOnly => {
+                if let Some(movie) = &mut self.movie {
+                    movie.read_only = !movie.read_only;
+                }
+            }
+            Command::Inspect(_) => {}
+        }
+        Ok(())
+    }
+
+    fn run_until(&mut self, target: RunTarget) {
+        self.run_target = Some(target);
+        self.start_run();
+    }
+
+    fn publish_frame(&self, published: &Published) {
+        let mut frame = published.frame.lock().unwrap();
+        frame.pixels.copy_from_slice(&self.screen);
+        frame.movie_status = self.movie.as_ref().map(|movie| movie.status());
+    }
+
+    fn snapshot(&self, panels: Panels) -> Snapshot {
+        Snapshot {
+            state: self.save_state(),
+            call_stack: self.cpu.call_stack.clone(),
+            history: panels.history.then(|| self.history.clone()),
+            writes: panels.writes.then(|| self.mem_map.writes.clone()),
+            breakpoints: self.breakpoints.clone(),
+            running: self.running,
+            speed_multiplier: self.speed_multiplier,
+            reversible: self.rewind.is_some(),
+            error: None,
+        }
+    }
+
+    /// A device of the same ROM to show the snapshots of this one in
+    pub(crate) fn mirror(&self) -> Device {
+        let mut mirror = Device::new(self.cartridge.clone());
+        mirror.symbols = self.symbols.clone();
+        mirror.logger = Box::new(SilentLogger);
+        mirror.history = ExecutionHistory::new(0);
+        mirror
+    }
+
+    /**
+     * Makes this device, which only shows what the emulator thread runs, look like the
+     * snapshot. It must be of the same ROM.
+     */
+    pub(crate) fn show_snapshot(&mut self, snapshot: Snapshot) -> Result<(), String> {
+        self.load_state(&snapshot.state)?;
+        self.cpu.call_stack = snapshot.call_stack;
+        if let Some(history) = snapshot.history {
+            self.history = history;
+        }
+        if let Some(writes) = snapshot.writes {
+            self.mem_map.writes = writes;
+        }
+        self.breakpoints = snapshot.breakpoints;
+        self.running = snapshot.running;
+        self.speed_multiplier = snapshot.speed_multiplier;
+        Ok(())
+    }
+}
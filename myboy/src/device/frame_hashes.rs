@@ -0,0 +1,224 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use crate::debugger::stepping::FRAME_CYCLES;
+
+use super::device::Device;
+
+/// The memory hashed besides the screen and audio
+const REGIONS: [(&str, RangeInclusive<u16>); 4] = [
+    ("wram", 0xC000..=0xDFFF),
+    ("vram", 0x8000..=0x9FFF),
+    ("oam", 0xFE00..=0xFE9F),
+    ("hram", 0xFF80..=0xFFFE),
+];
+
+/// The hashes of a frame, in the order they are listed
+fn columns() -> impl Iterator<Item = &'static str> {
+    ["screen", "audio"]
+        .into_iter()
+        .chain(REGIONS.iter().map(|(name, _)| *name))
+}
+
+/// 64-bit FNV-1a, which unlike the hasher of the standard library never changes
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/**
+ * Hashes of the screen and the memory every `interval` frames of a headless run, one line
+ * per frame with the frame counted from power-on followed by the hashes in hex:
+ *
+ * ```text
+ * # frame screen audio wram vram oam hram
+ * 60 9a3f2c0e1b7d4a55 ...
+ * ```
+ *
+ * There is no audio emulated yet, its column is the hash of an empty buffer for now.
+ *
+ * The list written by one version can be checked by the next, which stops at the first frame
+ * whose hashes differ. Frames of the list the run did not reach are an error as well.
+ */
+pub(crate) struct FrameHashes {
+    interval: u64,
+    output: Option<BufWriter<Box<dyn Write + Send>>>,
+    /// The file checked against, with its hashes by frame
+    expected: Option<(PathBuf, HashMap<u64, Vec<String>>)>,
+    /// The frames checked so far
+    pub(crate) checked: usize,
+    /// The first difference found or write that failed, which stops the run
+    error: Option<String>,
+}
+
+impl FrameHashes {
+    /**
+     * Writes the hashes to `output`, `-` for stdout, and checks them against the list in
+     * `expected`, if given.
+     */
+    pub(crate) fn new(
+        interval: u64,
+        output: Option<&Path>,
+        expected: Option<&Path>,
+    ) -> Result<FrameHashes, String> {
+        let output =
+            match output {
+                Some(path) if path == Path::new("-") => {
+                    Some(Box::new(io::stdout()) as Box<dyn Write + Send>)
+                }
+                Some(path) => Some(Box::new(File::create(path).map_err(|e| {
+                    format!("Failed to create the hash list {}: {}", path.display(), e)
+                })?) as Box<dyn Write + Send>),
+                None => None,
+            };
+        let expected = match expected {
+            Some(path) => Some((path.to_path_buf(), FrameHashes::load(path)?)),
+            None => None,
+        };
+
+        let mut hashes = FrameHashes {
+            interval: interval.max(1),
+            output: output.map(BufWriter::new),
+            expected,
+            checked: 0,
+            error: None,
+        };
+        let header = ["# frame"]
+            .into_iter()
+            .chain(columns())
+            .collect::<Vec<&str>>();
+        hashes.write_line(&header.join(" "));
+        Ok(hashes)
+    }
+
+    fn load(path: &Path) -> Result<HashMap<u64, Vec<String>>, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read the hash list {}: {}", path.display(), e))?;
+
+        let mut frames = HashMap::new();
+        for (index, line) in content.lines().enumerate() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let frame = words
+                .next()
+                .and_then(|frame| frame.parse::<u64>().ok())
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid hash list {}: expected a frame in line {}",
+                        path.display(),
+                        index + 1
+                    )
+                })?;
+            let hashes = words.map(str::to_string).collect::<Vec<String>>();
+            if hashes.len() != columns().count() {
+                return Err(format!(
+                    "Invalid hash list {}: expected {} hashes in line {}",
+                    path.display(),
+                    columns().count(),
+                    index + 1
+                ));
+            }
+            frames.insert(frame, hashes);
+        }
+        Ok(frames)
+    }
+
+    fn write_line(&mut self, line: &str) {
+        let Some(output) = &mut self.output else {
+            return;
+        };
+        if let Err(e) = writeln!(output, "{}", line) {
+            self.output = None;
+            self.error
+                .get_or_insert(format!("Failed to write the hash list: {}", e));
+        }
+    }
+
+    /// Checks the hashes of `frame`, the first difference is the error
+    fn check(&mut self, frame: u64, hashes: &[String]) {
+        let Some((path, expected)) = &mut self.expected else {
+            return;
+        };
+        let Some(expected) = expected.remove(&frame) else {
+            return;
+        };
+        self.checked += 1;
+
+        let differing = columns()
+            .zip(hashes)
+            .enumerate()
+            .filter(|(index, (_, hash))| expected.get(*index) != Some(hash))
+            .map(|(_, (name, _))| name)
+            .collect::<Vec<&str>>();
+        if !differing.is_empty() {
+            self.error.get_or_insert(format!(
+                "Frame {} differs from {} in {}",
+                frame,
+                path.display(),
+                differing.join(", ")
+            ));
+        }
+    }
+
+    /**
+     * Writes out the rest, `Err` if the hashes differed, could not be written
+     * or frames of the checked list were never reached.
+     */
+    pub(crate) fn finish(mut self) -> Result<(), String> {
+        if let Some(Err(e)) = self.output.as_mut().map(|output| output.flush()) {
+            self.error
+                .get_or_insert(format!("Failed to write the hash list: {}", e));
+        }
+        if let Some((path, expected)) = &self.expected
+            && let Some(first) = expected.keys().min()
+        {
+            self.error.get_or_insert(format!(
+                "{} frames of {} were never reached, the first is frame {}",
+                expected.len(),
+                path.display(),
+                first
+            ));
+        }
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Device {
+    /// Called when a new frame starts, hashes the one that ended if it is one of the interval
+    pub(crate) fn hash_frame(&mut self) {
+        let frame = self.cycle_count / FRAME_CYCLES;
+        let Some(frame_hashes) = &mut self.frame_hashes else {
+            return;
+        };
+        if !frame.is_multiple_of(frame_hashes.interval) {
+            return;
+        }
+
+        let mem_map = &self.mem_map;
+        let hashes =
+            [fnv1a(self.screen.iter().copied()), fnv1a([])]
+                .into_iter()
+                .chain(REGIONS.iter().map(|(_, range)| {
+                    fnv1a(range.clone().map(|address| mem_map.peek_byte(address)))
+                }))
+                .map(|hash| format!("{:016x}", hash))
+                .collect::<Vec<String>>();
+
+        frame_hashes.write_line(&format!("{} {}", frame, hashes.join(" ")));
+        frame_hashes.check(frame, &hashes);
+        if frame_hashes.error.is_some() {
+            self.running = false;
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    fmt::{self, Display},
+    time::{Duration, Instant},
+};
+
+use crate::debugger::stepping::FRAME_CYCLES;
+
+use super::device::Device;
+
+/// The exit code when a frame or cycle limit ends the run before an `until` condition is met
+pub(crate) const EXIT_LIMIT: i32 = 2;
+pub(crate) const EXIT_TIMEOUT: i32 = 3;
+
+/**
+ * When a headless run ends. Frames are `FRAME_CYCLES` T-cycles of emulated time
+ * and counted like the cycles from the start of the run, the timeout is wall-clock time.
+ */
+#[derive(Default)]
+pub(crate) struct StopConditions {
+    pub(crate) frames: Option<u64>,
+    pub(crate) cycles: Option<u64>,
+    pub(crate) until_pc: Option<u16>,
+    pub(crate) until_serial: Option<String>,
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl StopConditions {
+    /// Whether the run waits for something the game does, not just for a limit
+    fn has_until(&self) -> bool {
+        self.until_pc.is_some() || self.until_serial.is_some()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StopReason {
+    Frames,
+    Cycles,
+    Pc,
+    Serial,
+    Timeout,
+}
+
+impl StopReason {
+    /**
+     * 0 when an `until` condition was met, or a limit was reached and there was none,
+     * `EXIT_LIMIT` when a limit was reached first and `EXIT_TIMEOUT` after the timeout.
+     */
+    pub(crate) fn exit_code(self, conditions: &StopConditions) -> i32 {
+        match self {
+            StopReason::Pc | StopReason::Serial => 0,
+            StopReason::Frames | StopReason::Cycles if conditions.has_until() => EXIT_LIMIT,
+            StopReason::Frames | StopReason::Cycles => 0,
+            StopReason::Timeout => EXIT_TIMEOUT,
+        }
+    }
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            StopReason::Frames => "the frame limit was reached",
+            StopReason::Cycles => "the cycle limit was reached",
+            StopReason::Pc => "PC reached the address",
+            StopReason::Serial => "the serial output contains the text",
+            StopReason::Timeout => "the timeout expired",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+impl Device {
+    /**
+     * Runs until one of the `conditions` is met. `None` if the emulation stopped otherwise,
+     * or never if there are no conditions.
+     */
+    pub(crate) fn run_headless(&mut self, conditions: &StopConditions) -> Option<StopReason> {
+        let started = Instant::now();
+        let frames_end = conditions
+            .frames
+            .map(|frames| self.cycle_count + frames * FRAME_CYCLES);
+        let cycles_end = conditions.cycles.map(|cycles| self.cycle_count + cycles);
+        let texts = conditions
+            .until_serial
+            .iter()
+            .map(String::as_bytes)
+            .collect();
+        let mut serial = SerialMatcher::new(texts, self.serial_buffer.len());
+
+        self.running = true;
+        self.reset_pacing();
+        let reason = self.run_guarded(|device| {
+            while device.running {
+                device.step();
+                device.pace();
+                let cycle_count = device.cycle_count;
+
+                if conditions.until_pc == Some(*device.cpu.register_set.pc()) {
+                    return Some(StopReason::Pc);
+                }
+                if serial.find(&device.serial_buffer).is_some() {
+                    return Some(StopReason::Serial);
+                }
+                if frames_end.is_some_and(|end| cycle_count >= end) {
+                    return Some(StopReason::Frames);
+                }
+                if cycles_end.is_some_and(|end| cycle_count >= end) {
+                    return Some(StopReason::Cycles);
+                }
+                if conditions
+                    .timeout
+                    .is_some_and(|timeout| started.elapsed() >= timeout)
+                {
+                    return Some(StopReason::Timeout);
+                }
+            }
+            None
+        });
+        self.running = false;
+        reason
+    }
+}
+
+/**
+ * Looks for texts in the serial output while it grows. Every call only searches
+ * the output added since the last one, and the end before it a text may have begun in.
+ */
+pub(crate) struct SerialMatcher<'t> {
+    texts: Vec<&'t [u8]>,
+    /// The serial output before was searched already
+    searched: usize,
+}
+
+impl<'t> SerialMatcher<'t> {
+    /// Starts searching after the first `searched` bytes of the output
+    pub(crate) fn new(texts: Vec<&'t [u8]>, searched: usize) -> SerialMatcher<'t> {
+        SerialMatcher { texts, searched }
+    }
+
+    /// The index of the first of the texts the output contains, if it grew since the last call
+    pub(crate) fn find(&mut self, output: &[u8]) -> Option<usize> {
+        if output.len() <= self.searched {
+            return None;
+        }
+
+        // a text that began in the output searched before has at most all but one byte there
+        let longest = self.texts.iter().map(|text| text.len()).max()?;
+        let from = self.searched.saturating_sub(longest.saturating_sub(1));
+        self.searched = output.len();
+        self.texts
+            .iter()
+            .position(|text| contains(&output[from..], text))
+    }
+}
+
+/// Whether `needle` occurs in `haystack`
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty()
+        || haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SerialMatcher;
+
+    #[test]
+    fn finds_texts_written_across_several_calls() {
+        let mut matcher = SerialMatcher::new(vec![b"Passed", b"Failed"], 0);
+        let mut output = b"Test ".to_vec();
+        assert_eq!(matcher.find(&output), None);
+
+        output.extend_from_slice(b"Pas");
+        assert_eq!(matcher.find(&output), None);
+        output.extend_from_slice(b"sed");
+        assert_eq!(matcher.find(&output), Some(0));
+        // nothing new was written
+        assert_eq!(matcher.find(&output), None);
+    }
+
+    #[test]
+    fn prefers_the_first_text() {
+        let mut matcher = SerialMatcher::new(vec![b"Passed", b"Failed"], 0);
+        assert_eq!(matcher.find(b"Failed 1, Passed 3"), Some(0));
+
+        let mut matcher = SerialMatcher::new(vec![b"Passed", b"Failed"], 0);
+        assert_eq!(matcher.find(b"Failed"), Some(1));
+    }
+
+    #[test]
+    fn skips_the_output_before_it_started() {
+        let mut output = b"Passed".to_vec();
+        let mut matcher = SerialMatcher::new(vec![b"Passed"], output.len());
+        assert_eq!(matcher.find(&output), None);
+
+        output.push(b'\n');
+        assert_eq!(matcher.find(&output), None);
+
+        // without texts there is nothing to find
+        let mut matcher = SerialMatcher::new(Vec::new(), 0);
+        assert_eq!(matcher.find(b"Passed"), None);
+    }
+}
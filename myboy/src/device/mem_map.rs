@@ -12,12 +12,49 @@
 // FFFF	FFFF	Interrupt Enable register (IE)
 
 use crate::{
+    debugger::watch::{AccessKind, MemoryWatch, WriteHistory},
+    device::save_state::{SaveState, StateReader, StateWriter},
     io::io_registers::IORegisters,
-    memory::{generic_memory::GenericMemory as _, hram::HRAM, vram::VRAM, wram::WRAM},
+    memory::{
+        generic_memory::{AddressSpace, GenericMemory as _},
+        hram::HRAM,
+        vram::VRAM,
+        wram::WRAM,
+    },
     ppu::oam::OAM,
 };
 use mygbcartridge::{cartridge::Cartridge, enums::cartridge_type::CartridgeType};
 
+/// The areas of the address space from the table above, by their last address
+const REGIONS: [(u16, &str); 12] = [
+    (0x3FFF, "ROM bank 00"),
+    (0x7FFF, "ROM bank 01-NN"),
+    (0x9FFF, "VRAM"),
+    (0xBFFF, "External RAM"),
+    (0xCFFF, "WRAM"),
+    (0xDFFF, "WRAM bank 1-7"),
+    (0xFDFF, "Echo RAM"),
+    (0xFE9F, "OAM"),
+    (0xFEFF, "Not usable"),
+    (0xFF7F, "I/O registers"),
+    (0xFFFE, "HRAM"),
+    (0xFFFF, "IE register"),
+];
+
+/// The name of the area `address` lies in
+pub(crate) fn region_name(address: u16) -> &'static str {
+    REGIONS
+        .iter()
+        .find(|(end, _)| address <= *end)
+        .map(|(_, name)| *name)
+        .unwrap_or_default()
+}
+
+/// The areas the memory map can write to without side effects on the cartridge
+pub(crate) fn is_editable(address: u16) -> bool {
+    matches!(address, 0x8000..=0x9FFF | 0xC000..=0xDFFF | 0xFE00..=0xFE9F | 0xFF00..=0xFFFF)
+}
+
 pub struct MemMap {
     pub(crate) cartridge: Cartridge,
     pub working_ram: WRAM,
@@ -25,6 +62,8 @@ pub struct MemMap {
     pub io_registers: IORegisters,
     pub object_attribute_memory: OAM,
     pub hram: HRAM,
+    pub(crate) watch: MemoryWatch,
+    pub(crate) writes: WriteHistory,
 }
 
 impl MemMap {
@@ -42,6 +81,8 @@ impl MemMap {
             io_registers,
             object_attribute_memory,
             hram,
+            watch: MemoryWatch::default(),
+            writes: WriteHistory::default(),
         }
     }
 
@@ -49,7 +90,32 @@ impl MemMap {
         &self.io_registers
     }
 
+    /**
+     * The ROM bank an address is currently mapped to, `None` for addresses outside the ROM.
+     * As there is no mapper yet, the switchable area always shows bank 1.
+     */
+    pub fn rom_bank_for_address(&self, address: u16) -> Option<u16> {
+        match address {
+            0x0000..=0x3FFF => Some(0),
+            0x4000..=0x7FFF => Some(1),
+            _ => None,
+        }
+    }
+
     pub fn read_byte(&self, address: u16) -> u8 {
+        if address == 0xff00 {
+            self.io_registers.joypad.polled.set(true);
+        }
+        let value = self.peek_byte(address);
+        self.watch.record(address, value, AccessKind::Read);
+        value
+    }
+
+    /**
+     * Reads a byte without it counting as an access for watchpoints,
+     * for the debugger and for instruction decoding.
+     */
+    pub fn peek_byte(&self, address: u16) -> u8 {
         match address {
             0x0000..=0x7FFF => self.cartridge.read_byte(address),
             0x8000..=0x9FFF => self.video_ram.read_byte(address),
@@ -65,6 +131,15 @@ impl MemMap {
     }
 
     pub fn read_word(&self, address: u16) -> u16 {
+        let value = self.peek_word(address);
+        let [low, high] = value.to_le_bytes();
+        self.watch.record(address, low, AccessKind::Read);
+        self.watch
+            .record(address.wrapping_add(1), high, AccessKind::Read);
+        value
+    }
+
+    fn peek_word(&self, address: u16) -> u16 {
         match address {
             0x0000..=0x7FFF => self.cartridge.read_word(address),
             0x8000..=0x9FFF => self.video_ram.read_word(address),
@@ -83,6 +158,9 @@ impl MemMap {
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
+        self.watch.record(address, value, AccessKind::Write);
+        self.writes.record(address);
+
         match address {
             0x0000..=0x7FFF => match self.cartridge.get_cartridge_type().unwrap() {
                 CartridgeType::RomRam
@@ -114,6 +192,13 @@ impl MemMap {
     }
 
     pub fn write_word(&mut self, address: u16, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.watch.record(address, low, AccessKind::Write);
+        self.watch
+            .record(address.wrapping_add(1), high, AccessKind::Write);
+        self.writes.record(address);
+        self.writes.record(address.wrapping_add(1));
+
         match address {
             0x0000..=0x7FFF => match self.cartridge.get_cartridge_type().unwrap() {
                 CartridgeType::RomRam
@@ -142,3 +227,72 @@ impl MemMap {
         }
     }
 }
+
+/**
+ * The memories and IO registers read from a save state. They replace the ones
+ * of the memory map with `MemMap::restore` once the whole state was read.
+ */
+pub(crate) struct Memories {
+    working_ram: WRAM,
+    video_ram: VRAM,
+    object_attribute_memory: OAM,
+    hram: HRAM,
+    io_registers: IORegisters,
+}
+
+impl Memories {
+    /// Reads what `MemMap::save` wrote
+    pub(crate) fn load(reader: &mut StateReader) -> Result<Memories, String> {
+        let mut memories = Memories {
+            working_ram: WRAM::new(),
+            video_ram: VRAM::new(),
+            object_attribute_memory: OAM::new(),
+            hram: HRAM::new(),
+            io_registers: IORegisters::new(),
+        };
+        reader.bytes(&mut memories.working_ram.data)?;
+        reader.bytes(&mut memories.video_ram.data)?;
+        reader.bytes(&mut memories.object_attribute_memory.data)?;
+        reader.bytes(&mut memories.hram.data)?;
+        memories.io_registers.load(reader)?;
+
+        // without a mapper the bank is always the same
+        reader.u16()?;
+        match reader.u32()? {
+            0 => Ok(memories),
+            length => Err(format!(
+                "The save state has {} bytes of cartridge RAM, which is not emulated",
+                length
+            )),
+        }
+    }
+}
+
+impl MemMap {
+    pub(crate) fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.working_ram.data);
+        writer.bytes(&self.video_ram.data);
+        writer.bytes(&self.object_attribute_memory.data);
+        writer.bytes(&self.hram.data);
+        self.io_registers.save(writer);
+
+        // the mapper: the bank at 0x4000 and the cartridge RAM, which no cartridge has yet
+        writer.u16(self.rom_bank_for_address(0x4000).unwrap_or(1));
+        writer.u32(0);
+    }
+
+    /// Replaces the memories and IO registers, the cartridge and the debugger state stay
+    pub(crate) fn restore(&mut self, memories: Memories) {
+        self.working_ram = memories.working_ram;
+        self.video_ram = memories.video_ram;
+        self.object_attribute_memory = memories.object_attribute_memory;
+        self.hram = memories.hram;
+        self.io_registers = memories.io_registers;
+    }
+}
+
+impl AddressSpace for MemMap {
+    fn read_byte(&self, address: u16) -> u8 {
+        self.peek_byte(address)
+    }
+}
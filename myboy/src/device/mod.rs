@@ -1,2 +1,11 @@
+pub(crate) mod bess;
 pub(crate) mod device;
+pub(crate) mod emulator;
+pub(crate) mod frame_hashes;
+pub(crate) mod headless;
 pub(crate) mod mem_map;
+pub(crate) mod movie;
+pub(crate) mod pacing;
+pub(crate) mod rewind;
+pub(crate) mod save_state;
+pub(crate) mod screenshot;
@@ -0,0 +1,493 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use mygbcartridge::cartridge::Cartridge;
+
+use crate::{debugger::stepping::FRAME_CYCLES, io::if_register::InterruptType, logging::log::Log};
+
+use super::{
+    device::Device,
+    save_state::{StateReader, StateWriter},
+};
+
+const MAGIC: &[u8; 8] = b"BRICOMOV";
+const VERSION: u16 = 2;
+const START_POWER_ON: u8 = 0;
+const START_STATE: u8 = 1;
+
+/**
+ * Where a movie begins. No cartridge has RAM yet, so a movie from power-on
+ * does not hold any, and games saving to it are not covered.
+ */
+pub(crate) enum MovieStart {
+    /// A freshly switched on Game Boy
+    PowerOn,
+    /// A save state, for movies branched off a running game
+    State(Vec<u8>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MovieMode {
+    Recording,
+    Playing,
+    /// Played to the end in read-only mode, the keyboard controls the game again
+    Finished,
+}
+
+/**
+ * The joypad input of every frame of a game, to replay it exactly.
+ *
+ * A frame is `FRAME_CYCLES` T-cycles of emulated time, counted from power-on whether the LCD
+ * is on or not, and the input changes only when a new one starts. Playback therefore does not
+ * depend on how fast the emulation runs or when keys were pressed on the host.
+ *
+ * Loading a save state seeks the movie to the frame of the state. In read-only mode it goes on
+ * playing from there, in read-write mode the recording is cut off there and continues with the
+ * keyboard, which branches it.
+ */
+pub(crate) struct Movie {
+    pub(crate) path: PathBuf,
+    start: MovieStart,
+    /// The checksums of the ROM header, as in save states
+    header_checksum: u8,
+    global_checksum: u16,
+    /// The buttons held in every frame so far, bits as in `Joypad::pressed`
+    inputs: Vec<u8>,
+    /// The frame the movie starts in, counted from power-on
+    first_frame: u64,
+    /// The current frame, counted from the start of the movie
+    pub(crate) frame: u64,
+    /// Whether the game read the joypad in every finished frame
+    polled: Vec<bool>,
+    pub(crate) mode: MovieMode,
+    pub(crate) read_only: bool,
+    /// Whether anything was recorded, only then it is written
+    modified: bool,
+}
+
+/// The header and global checksums of the ROM
+fn rom_checksums(cartridge: &Cartridge) -> (u8, u16) {
+    (
+        cartridge.read_byte(0x014d),
+        u16::from_be_bytes([cartridge.read_byte(0x014e), cartridge.read_byte(0x014f)]),
+    )
+}
+
+impl Movie {
+    fn new(path: &Path, start: MovieStart, cartridge: &Cartridge) -> Movie {
+        let (header_checksum, global_checksum) = rom_checksums(cartridge);
+        Movie {
+            path: path.to_path_buf(),
+            start,
+            header_checksum,
+            global_checksum,
+            inputs: Vec::new(),
+            first_frame: 0,
+            frame: 0,
+            polled: Vec::new(),
+            mode: MovieMode::Recording,
+            read_only: false,
+            modified: true,
+        }
+    }
+
+    pub(crate) fn load(path: &Path) -> Result<Movie, String> {
+        let bytes = fs::read(path)
+            .map_err(|e| format!("Failed to read the movie {}: {}", path.display(), e))?;
+        Movie::from_bytes(path, &bytes)
+            .map_err(|e| format!("Failed to load the movie {}: {}", path.display(), e))
+    }
+
+    fn from_bytes(path: &Path, bytes: &[u8]) -> Result<Movie, String> {
+        let mut reader = StateReader::new(bytes);
+        let mut magic = [0u8; MAGIC.len()];
+        reader
+            .bytes(&mut magic)
+            .map_err(|_| "Not a movie".to_string())?;
+        if &magic != MAGIC {
+            return Err("Not a movie".to_string());
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(format!(
+                "The movie has version {}, only version {} is supported",
+                version, VERSION
+            ));
+        }
+
+        let header_checksum = reader.u8()?;
+        let global_checksum = reader.u16()?;
+        let start = match reader.u8()? {
+            START_POWER_ON => MovieStart::PowerOn,
+            START_STATE => {
+                let length = reader.u32()? as usize;
+                MovieStart::State(reader.byte_vec(length)?)
+            }
+            kind => return Err(format!("Unknown start {} of the movie", kind)),
+        };
+        let length = reader.u32()? as usize;
+        let inputs = reader.byte_vec(length)?;
+
+        Ok(Movie {
+            path: path.to_path_buf(),
+            start,
+            header_checksum,
+            global_checksum,
+            inputs,
+            first_frame: 0,
+            frame: 0,
+            polled: Vec::new(),
+            mode: MovieMode::Playing,
+            read_only: true,
+            modified: false,
+        })
+    }
+
+    pub(crate) fn save(&self) -> Result<(), String> {
+        let mut writer = StateWriter::default();
+        writer.bytes(MAGIC);
+        writer.u16(VERSION);
+        writer.u8(self.header_checksum);
+        writer.u16(self.global_checksum);
+        match &self.start {
+            MovieStart::PowerOn => writer.u8(START_POWER_ON),
+            MovieStart::State(state) => {
+                writer.u8(START_STATE);
+                writer.u32(state.len() as u32);
+                writer.bytes(state);
+            }
+        }
+        writer.u32(self.inputs.len() as u32);
+        writer.bytes(&self.inputs);
+
+        fs::write(&self.path, writer.into_bytes())
+            .map_err(|e| format!("Failed to write the movie {}: {}", self.path.display(), e))
+    }
+
+    /// The frames so far in which the game did not read the joypad
+    pub(crate) fn lag_count(&self) -> usize {
+        self.polled.iter().filter(|polled| !**polled).count()
+    }
+
+    /// The mode with the frame and lag counters, e.g. `playing (read-only), frame 120/600, 3 lag`
+    pub(crate) fn status(&self) -> String {
+        let mode = match (self.mode, self.read_only) {
+            (MovieMode::Recording, _) => "recording",
+            (MovieMode::Playing, true) => "playing (read-only)",
+            (MovieMode::Playing, false) => "playing (read-write)",
+            (MovieMode::Finished, _) => "movie finished",
+        };
+        format!(
+            "{}, frame {}/{}, {} lag",
+            mode,
+            self.frame,
+            self.inputs.len(),
+            self.lag_count()
+        )
+    }
+
+    /// Ends the current frame and returns the input of the next one
+    fn advance(&mut self, held: u8, polled: bool) -> u8 {
+        self.polled.truncate(self.frame as usize);
+        self.polled.push(polled);
+        self.frame += 1;
+        self.input(held)
+    }
+
+    /// The input of the current frame, `held` if it is recorded
+    fn input(&mut self, held: u8) -> u8 {
+        let frame = self.frame as usize;
+        match self.mode {
+            MovieMode::Recording => {
+                self.inputs.truncate(frame);
+                self.inputs.push(held);
+                self.modified = true;
+                held
+            }
+            MovieMode::Playing => match self.inputs.get(frame) {
+                Some(input) => *input,
+                None if self.read_only => {
+                    self.mode = MovieMode::Finished;
+                    held
+                }
+                // a read-write movie goes on recording at its end
+                None => {
+                    self.mode = MovieMode::Recording;
+                    self.input(held)
+                }
+            },
+            MovieMode::Finished => held,
+        }
+    }
+
+    /// Goes to `frame` after a save state was loaded, in which `pressed` was held
+    fn seek(&mut self, frame: u64, pressed: u8) {
+        self.frame = frame;
+        // the frames skipped over by loading a later state were not seen to lag
+        self.polled.resize(frame as usize, true);
+        self.mode = match self.read_only {
+            true if frame < self.inputs.len() as u64 => MovieMode::Playing,
+            true => MovieMode::Finished,
+            false => {
+                self.inputs.resize(frame as usize, pressed);
+                self.inputs.push(pressed);
+                self.modified = true;
+                MovieMode::Recording
+            }
+        };
+    }
+}
+
+impl Device {
+    /**
+     * Starts recording a movie to `path`, from power-on or branched off the current state.
+     * The movie is written when it is stopped.
+     */
+    pub(crate) fn record_movie(&mut self, path: &Path, from_power_on: bool) -> Result<(), String> {
+        self.stop_movie()?;
+        let start = match from_power_on {
+            true => {
+                self.power_on();
+                MovieStart::PowerOn
+            }
+            false => MovieStart::State(self.save_state()),
+        };
+        let mut movie = Movie::new(path, start, &self.cartridge);
+        movie.first_frame = self.cycle_count / FRAME_CYCLES;
+        self.start_movie(movie);
+        Ok(())
+    }
+
+    /// Goes to the start of `movie` and plays it
+    pub(crate) fn play_movie(&mut self, mut movie: Movie, read_only: bool) -> Result<(), String> {
+        if (movie.header_checksum, movie.global_checksum) != rom_checksums(&self.cartridge) {
+            return Err(format!(
+                "The movie {} is for a different ROM",
+                movie.path.display()
+            ));
+        }
+        self.stop_movie()?;
+
+        match &movie.start {
+            MovieStart::PowerOn => self.power_on(),
+            MovieStart::State(state) => self.load_state(state)?,
+        }
+        movie.first_frame = self.cycle_count / FRAME_CYCLES;
+        movie.read_only = read_only;
+        movie.mode = MovieMode::Playing;
+        self.start_movie(movie);
+        Ok(())
+    }
+
+    fn start_movie(&mut self, mut movie: Movie) {
+        let input = movie.input(self.input);
+        self.movie = Some(movie);
+        self.press(input);
+    }
+
+    /// Stops the movie and writes it if anything was recorded
+    pub(crate) fn stop_movie(&mut self) -> Result<(), String> {
+        match self.movie.take() {
+            Some(movie) if movie.modified => movie.save(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Called when a new frame starts, applies its input
+    pub(crate) fn next_frame(&mut self) {
+        let polled = self.mem_map.io_registers.joypad.polled.replace(false);
+        let input = match &mut self.movie {
+            Some(movie) => {
+                let mode = movie.mode;
+                let input = movie.advance(self.input, polled);
+                if mode == MovieMode::Playing && movie.mode == MovieMode::Finished {
+                    self.logger.info(Log::Msg(format!(
+                        "Movie finished after {} frames",
+                        movie.frame
+                    )));
+                }
+                input
+            }
+            None => self.input,
+        };
+        self.press(input);
+    }
+
+    fn press(&mut self, buttons: u8) {
+        let io_registers = &mut self.mem_map.io_registers;
+        if io_registers.joypad.set_pressed(buttons) {
+            io_registers
+                .if_register
+                .request_interrupt(InterruptType::Joypad);
+        }
+    }
+
+    /// Moves the movie to the frame of a state that was just loaded
+    pub(crate) fn seek_movie(&mut self) {
+        let frame = self.cycle_count / FRAME_CYCLES;
+        let pressed = self.mem_map.io_registers.joypad.pressed;
+        let Some(movie) = &mut self.movie else {
+            return;
+        };
+        match frame.checked_sub(movie.first_frame) {
+            Some(frame) => movie.seek(frame, pressed),
+            None => {
+                movie.mode = MovieMode::Finished;
+                self.logger.info(Log::Msg(
+                    "The state is from before the movie started, the movie stopped".to_string(),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use mygbcartridge::cartridge::Cartridge;
+
+    use crate::{
+        debugger::{reverse::checkpoints, stepping::FRAME_CYCLES},
+        device::device::Device,
+    };
+
+    use super::{Movie, MovieMode, MovieStart};
+
+    /// A ROM copying the joypad register into the first tile, so the input shows on screen
+    fn joypad_rom() -> Cartridge {
+        let mut data = vec![0; 0x8000];
+        data[0x0100..0x010f].copy_from_slice(&[
+            0x3e, 0x91, // ld a, $91
+            0xe0, 0x40, // ldh [rLCDC], a
+            0x3e, 0x20, // ld a, $20
+            0xe0, 0x00, // ldh [rP1], a
+            0xf0, 0x00, // ldh a, [rP1]
+            0xea, 0x00, 0x80, // ld [$8000], a
+            0x18, 0xf9, // jr @-5
+        ]);
+        data[0x014d] = 0x5a;
+        data[0x014e..0x0150].copy_from_slice(&[0x12, 0x34]);
+        Cartridge { data }
+    }
+
+    /// The cycle count and screen after each of `frames` frames
+    fn run_frames(device: &mut Device, frames: usize) -> Vec<(u64, Box<[u8]>)> {
+        (0..frames)
+            .map(|_| {
+                let end = device.cycle_count + FRAME_CYCLES;
+                while device.cycle_count < end {
+                    device.step();
+                }
+                (device.cycle_count, device.screen.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_through_the_file_format() {
+        let path = env::temp_dir().join(format!("bricoboy-movie-{}.movie", std::process::id()));
+        let mut movie = Movie::new(&path, MovieStart::State(vec![1, 2, 3]), &joypad_rom());
+        movie.inputs = vec![0x00, 0x01, 0x81, 0xff];
+        movie.save().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        let loaded = Movie::from_bytes(&path, &bytes).unwrap();
+        assert!(matches!(&loaded.start, MovieStart::State(state) if state == &[1, 2, 3]));
+        assert_eq!(loaded.header_checksum, 0x5a);
+        assert_eq!(loaded.global_checksum, 0x1234);
+        assert_eq!(loaded.inputs, movie.inputs);
+        assert_eq!(loaded.mode, MovieMode::Playing);
+        assert!(loaded.read_only);
+
+        // lengths beyond the end of the file
+        for length_at in [14, bytes.len() - 8] {
+            let mut broken = bytes.clone();
+            broken[length_at..length_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+            assert!(Movie::from_bytes(&path, &broken).is_err());
+        }
+        assert!(Movie::from_bytes(&path, &bytes[..bytes.len() - 1]).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn playbacks_from_power_on_are_identical() {
+        let path = env::temp_dir().join(format!("bricoboy-replay-{}.movie", std::process::id()));
+        let mut device = Device::new(joypad_rom());
+        device.record_movie(&path, true).unwrap();
+        let mut recorded = Vec::new();
+        for input in [0x00, 0x01, 0x03, 0x0f, 0x00, 0x08] {
+            device.input = input;
+            recorded.extend(run_frames(&mut device, 2));
+        }
+        device.stop_movie().unwrap();
+
+        let mut playbacks = (0..2).map(|_| {
+            let mut device = Device::new(joypad_rom());
+            device
+                .play_movie(Movie::load(&path).unwrap(), true)
+                .unwrap();
+            run_frames(&mut device, recorded.len())
+        });
+        let first = playbacks.next().unwrap();
+        assert!(first == recorded);
+        assert!(playbacks.next().unwrap() == first);
+        // the input reached the screen
+        assert!(recorded.iter().any(|(_, screen)| screen != &recorded[0].1));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn seeking_ahead_keeps_the_lag_frames_in_place() {
+        let path = env::temp_dir().join("bricoboy-seek-test.movie");
+        let mut movie = Movie::new(&path, MovieStart::PowerOn, &joypad_rom());
+        movie.inputs = vec![0; 10];
+        movie.read_only = true;
+        movie.mode = MovieMode::Playing;
+
+        movie.seek(5, 0);
+        movie.advance(0, false);
+        assert_eq!(movie.polled.len(), 6);
+        assert!(!movie.polled[5]);
+        assert_eq!(movie.lag_count(), 1);
+        assert_eq!(movie.frame, 6);
+    }
+
+    #[test]
+    fn reverse_execution_keeps_the_recording() {
+        // a ROM looping at its entry point with `jr @`
+        let mut data = vec![0; 0x8000];
+        data[0x0100..0x0102].copy_from_slice(&[0x18, 0xfe]);
+        let mut device = Device::new(Cartridge { data });
+        device.rewind = Some(checkpoints());
+        let path = env::temp_dir().join("bricoboy-reverse-test.movie");
+        device.record_movie(&path, true).unwrap();
+        for input in [0x01, 0x02, 0x04, 0x08] {
+            device.input = input;
+            let end = device.cycle_count + FRAME_CYCLES;
+            while device.cycle_count < end {
+                device.step();
+            }
+        }
+        let recorded = device.movie.as_ref().unwrap().inputs.clone();
+
+        device.step_back().unwrap();
+        device.reverse_continue().unwrap();
+        let movie = device.movie.as_ref().unwrap();
+        assert_eq!(movie.inputs, recorded);
+        assert_eq!(movie.mode, MovieMode::Playing);
+        assert!(!movie.read_only);
+
+        // running forward again plays what was recorded
+        device.input = 0;
+        let end = device.cycle_count + 2 * FRAME_CYCLES;
+        while device.cycle_count < end {
+            device.step();
+        }
+        assert_eq!(device.movie.as_ref().unwrap().inputs, recorded);
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{cpu::CPU_FREQUENCY, debugger::stepping::FRAME_CYCLES};
+
+use super::device::Device;
+
+/// How long a frame takes on the real hardware, a bit less than 1/60 s
+pub(crate) const FRAME_DURATION: Duration =
+    Duration::from_nanos(FRAME_CYCLES * 1_000_000_000 / CPU_FREQUENCY);
+
+/// How far the emulation may fall behind before it gives up catching up
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/**
+ * Slows a run down to the speed of the real hardware. The core runs each frame as fast as it
+ * can, then waits until the frame is due. The deadlines follow each other, so the short frames
+ * make up for a sleep that overslept, but after a pause or a hiccup the pacing starts over
+ * instead of racing to catch up.
+ */
+#[derive(Default)]
+pub(crate) struct FramePacer {
+    /// The frame waited for last
+    frame: u64,
+    /// When that frame was due, `None` until the first frame of a run
+    deadline: Option<Instant>,
+}
+
+impl FramePacer {
+    /// Starts pacing over, for a run beginning at `frame`
+    pub(crate) fn reset(&mut self, frame: u64) {
+        self.frame = frame;
+        self.deadline = None;
+    }
+
+    /**
+     * Waits until `frame` is due if it began since the last call, with the frames
+     * `speed_multiplier` times as fast as on the hardware.
+     */
+    pub(crate) fn wait(&mut self, frame: u64, speed_multiplier: f64) {
+        if frame == self.frame {
+            return;
+        }
+        let frames = frame.saturating_sub(self.frame) as u32;
+        self.frame = frame;
+
+        let now = Instant::now();
+        let Some(deadline) = self.deadline else {
+            self.deadline = Some(now);
+            return;
+        };
+        let deadline = deadline + (FRAME_DURATION * frames).div_f64(speed_multiplier.max(0.01));
+
+        if deadline > now {
+            thread::sleep(deadline - now);
+            self.deadline = Some(deadline);
+        } else if now - deadline > MAX_LAG {
+            self.deadline = Some(now);
+        } else {
+            self.deadline = Some(deadline);
+        }
+    }
+}
+
+impl Device {
+    /// Starts pacing over, when a run begins
+    pub(crate) fn reset_pacing(&mut self) {
+        self.pacer.reset(self.cycle_count / FRAME_CYCLES);
+    }
+
+    /// Called after every step of a run, waits for the frame to be due when one began
+    pub(crate) fn pace(&mut self) {
+        if self.throttle {
+            self.pacer
+                .wait(self.cycle_count / FRAME_CYCLES, self.speed_multiplier);
+        }
+    }
+}
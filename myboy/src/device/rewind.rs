@@ -0,0 +1,281 @@
+use std::collections::VecDeque;
+
+use crate::debugger::{call_stack::CallStack, stepping::FRAME_CYCLES};
+
+use super::{
+    device::Device,
+    save_state::{StateReader, StateWriter},
+};
+
+/// A snapshot is taken every this many frames while playing
+pub(crate) const SNAPSHOT_FRAMES: u64 = 4;
+/// About 40 seconds of play
+const DEFAULT_CAPACITY: usize = 600;
+
+/// A save state, with the call stack that save states leave out
+pub(crate) struct Snapshot {
+    pub(crate) cycle_count: u64,
+    pub(crate) state: Vec<u8>,
+    pub(crate) call_stack: CallStack,
+}
+
+/**
+ * The save states of the last seconds, to go back in time while playing or debugging.
+ *
+ * Only the newest snapshot is kept as it is. Every older one is stored as its difference
+ * to the next newer one, which is mostly zeros, as little changes within a few frames,
+ * and the runs of zeros are left out.
+ */
+pub(crate) struct RewindBuffer {
+    latest: Option<Snapshot>,
+    /// The older snapshots, oldest first, their states as deltas
+    deltas: VecDeque<Snapshot>,
+    capacity: usize,
+    /// The T-cycles between two snapshots
+    interval: u64,
+}
+
+impl Default for RewindBuffer {
+    fn default() -> Self {
+        RewindBuffer::new(DEFAULT_CAPACITY, SNAPSHOT_FRAMES * FRAME_CYCLES)
+    }
+}
+
+impl RewindBuffer {
+    pub(crate) fn new(capacity: usize, interval: u64) -> RewindBuffer {
+        RewindBuffer {
+            latest: None,
+            deltas: VecDeque::with_capacity(capacity),
+            capacity,
+            interval,
+        }
+    }
+
+    /// Whether it is time for the next snapshot
+    pub(crate) fn is_due(&self, cycle_count: u64) -> bool {
+        match &self.latest {
+            Some(latest) => cycle_count.abs_diff(latest.cycle_count) >= self.interval,
+            None => true,
+        }
+    }
+
+    pub(crate) fn push(&mut self, snapshot: Snapshot) {
+        if let Some(latest) = self.latest.take() {
+            if self.deltas.len() + 1 >= self.capacity {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(Snapshot {
+                state: encode_delta(&snapshot.state, &latest.state),
+                ..latest
+            });
+        }
+        self.latest = Some(snapshot);
+    }
+
+    /// Takes the newest snapshot, the one before it becomes the newest
+    pub(crate) fn pop(&mut self) -> Option<Snapshot> {
+        let latest = self.latest.take()?;
+        if let Some(delta) = self.deltas.pop_back() {
+            self.latest = Some(Snapshot {
+                state: decode_delta(&latest.state, &delta.state),
+                ..delta
+            });
+        }
+        Some(latest)
+    }
+
+    /**
+     * The newest snapshot taken at or before `cycle_count`, the ones after it are dropped.
+     * `None` if all snapshots are newer, then none is dropped.
+     */
+    pub(crate) fn newest_at(&mut self, cycle_count: u64) -> Option<&Snapshot> {
+        let oldest = self.deltas.front().or(self.latest.as_ref())?;
+        if oldest.cycle_count > cycle_count {
+            return None;
+        }
+
+        while self
+            .latest
+            .as_ref()
+            .is_some_and(|latest| latest.cycle_count > cycle_count)
+        {
+            self.pop();
+        }
+        self.latest.as_ref()
+    }
+}
+
+impl Device {
+    /// Adds a snapshot to the rewind buffer every few frames, if rewinding is on
+    pub(crate) fn take_rewind_snapshot(&mut self) {
+        let cycle_count = self.cycle_count;
+        if !self
+            .rewind
+            .as_ref()
+            .is_some_and(|rewind| rewind.is_due(cycle_count))
+        {
+            return;
+        }
+
+        let snapshot = Snapshot {
+            cycle_count,
+            state: self.save_state(),
+            call_stack: self.cpu.call_stack.clone(),
+        };
+        if let Some(rewind) = &mut self.rewind {
+            rewind.push(snapshot);
+        }
+    }
+
+    pub(crate) fn restore_snapshot(&mut self, snapshot: &Snapshot) {
+        // the snapshots were taken from this device
+        self.load_state(&snapshot.state).unwrap();
+        self.cpu.call_stack = snapshot.call_stack.clone();
+    }
+
+    /**
+     * Goes back to the newest snapshot and drops it, so the next call goes back further.
+     * `false` if there is nothing left to go back to.
+     */
+    pub(crate) fn rewind_step(&mut self) -> bool {
+        let Some(snapshot) = self.rewind.as_mut().and_then(RewindBuffer::pop) else {
+            return false;
+        };
+        self.restore_snapshot(&snapshot);
+        true
+    }
+}
+
+/**
+ * What turns `base` into `target`: the length of `target`, then pairs of a run of
+ * unchanged bytes and the changed bytes after it, XORed with `base`.
+ */
+fn encode_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let byte = |data: &[u8], index: usize| data.get(index).copied().unwrap_or_default();
+    let mut writer = StateWriter::default();
+    writer.u32(target.len() as u32);
+
+    let mut index = 0;
+    while index < target.len() {
+        let unchanged = (index..target.len())
+            .take_while(|i| byte(base, *i) == target[*i])
+            .count();
+        index += unchanged;
+        let changed = (index..target.len())
+            .take_while(|i| byte(base, *i) != target[*i])
+            .count();
+
+        writer.u32(unchanged as u32);
+        writer.u32(changed as u32);
+        for (i, value) in target.iter().enumerate().skip(index).take(changed) {
+            writer.u8(byte(base, i) ^ value);
+        }
+        index += changed;
+    }
+
+    writer.into_bytes()
+}
+
+fn decode_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    // the deltas are our own, they cannot be truncated
+    let mut reader = StateReader::new(delta);
+    let length = reader.u32().unwrap() as usize;
+    let mut target = base.to_vec();
+    target.resize(length, 0);
+
+    let mut index = 0;
+    while !reader.is_empty() {
+        index += reader.u32().unwrap() as usize;
+        let changed = reader.u32().unwrap() as usize;
+        for byte in &mut target[index..index + changed] {
+            *byte ^= reader.u8().unwrap();
+        }
+        index += changed;
+    }
+
+    target
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debugger::call_stack::CallStack;
+
+    use super::{RewindBuffer, Snapshot, decode_delta, encode_delta};
+
+    fn round_trip(base: &[u8], target: &[u8]) -> Vec<u8> {
+        let delta = encode_delta(base, target);
+        assert_eq!(decode_delta(base, &delta), target);
+        delta
+    }
+
+    fn snapshot(cycle_count: u64) -> Snapshot {
+        Snapshot {
+            cycle_count,
+            state: vec![cycle_count as u8; 16],
+            call_stack: CallStack::default(),
+        }
+    }
+
+    #[test]
+    fn deltas_round_trip() {
+        let base = [1, 2, 3, 4, 5, 6, 7, 8];
+
+        // the length and a single run of unchanged bytes for equal states
+        assert_eq!(round_trip(&base, &base).len(), 12);
+        round_trip(&base, &[1, 2, 0, 4, 5, 6, 7, 0]);
+        round_trip(&base, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        round_trip(&base, &[1, 2, 3, 0, 0]);
+        round_trip(&base, &[8, 7, 6, 5, 4, 3, 2, 1]);
+        round_trip(&base, &[]);
+        round_trip(&[], &base);
+    }
+
+    #[test]
+    fn pops_the_snapshots_newest_first() {
+        let mut rewind = RewindBuffer::new(10, 100);
+        assert!(rewind.is_due(0));
+        for cycle_count in [0, 100, 200] {
+            rewind.push(snapshot(cycle_count));
+        }
+        assert!(!rewind.is_due(250));
+        assert!(rewind.is_due(300));
+
+        for cycle_count in [200, 100, 0] {
+            let popped = rewind.pop().unwrap();
+            assert_eq!(popped.cycle_count, cycle_count);
+            assert_eq!(popped.state, vec![cycle_count as u8; 16]);
+        }
+        assert!(rewind.pop().is_none());
+    }
+
+    #[test]
+    fn drops_the_oldest_snapshots_beyond_the_capacity() {
+        let mut rewind = RewindBuffer::new(3, 100);
+        for cycle_count in [0, 100, 200, 300, 400] {
+            rewind.push(snapshot(cycle_count));
+        }
+
+        assert!(rewind.newest_at(100).is_none());
+        for cycle_count in [400, 300, 200] {
+            assert_eq!(rewind.pop().unwrap().cycle_count, cycle_count);
+        }
+        assert!(rewind.pop().is_none());
+    }
+
+    #[test]
+    fn newest_at_drops_the_newer_snapshots() {
+        let mut rewind = RewindBuffer::new(10, 100);
+        for cycle_count in [100, 200, 300, 400] {
+            rewind.push(snapshot(cycle_count));
+        }
+
+        // nothing is dropped when all snapshots are newer
+        assert!(rewind.newest_at(50).is_none());
+        let newest = rewind.newest_at(250).unwrap();
+        assert_eq!(newest.cycle_count, 200);
+        assert_eq!(newest.state, vec![200; 16]);
+        assert_eq!(rewind.pop().unwrap().cycle_count, 200);
+        assert_eq!(rewind.pop().unwrap().cycle_count, 100);
+        assert!(rewind.pop().is_none());
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use mygbcartridge::cartridge::Cartridge;
+
+use crate::{cpu::cpu::CPU, ppu::ppu::PPU};
+
+use super::{bess::has_bess, device::Device, mem_map::Memories};
+
+const MAGIC: &[u8; 8] = b"BRICOSAV";
+/// Bumped whenever the layout of the state changes, older states are rejected
+pub(crate) const VERSION: u16 = 2;
+/// The only model emulated so far
+const MODEL_DMG: u8 = 0;
+const HEADER_LENGTH: usize = MAGIC.len() + 2 + 1 + 1 + 2 + 4;
+
+/**
+ * A part of the device that is written to save states. The layout of every part
+ * is fixed by `VERSION`, so `load` reads exactly what `save` wrote.
+ */
+pub(crate) trait SaveState {
+    fn save(&self, writer: &mut StateWriter);
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), String>;
+}
+
+/// Collects the bytes of a save state, numbers are little endian
+#[derive(Default)]
+pub(crate) struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Reads back what a `StateWriter` wrote
+pub(crate) struct StateReader<'b> {
+    bytes: &'b [u8],
+}
+
+impl<'b> StateReader<'b> {
+    pub(crate) fn new(bytes: &'b [u8]) -> StateReader<'b> {
+        StateReader { bytes }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'b [u8], String> {
+        if self.bytes.len() < length {
+            return Err("The save state is truncated".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, String> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(format!("Invalid boolean {} in the save state", value)),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Fills `target` completely
+    pub(crate) fn bytes(&mut self, target: &mut [u8]) -> Result<(), String> {
+        target.copy_from_slice(self.take(target.len())?);
+        Ok(())
+    }
+
+    /// The next `length` bytes, checked against the remaining ones before allocating
+    pub(crate) fn byte_vec(&mut self, length: usize) -> Result<Vec<u8>, String> {
+        Ok(self.take(length)?.to_vec())
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+/**
+ * What a save state was made from: the format version, the model and the ROM,
+ * identified by the checksums of its header.
+ */
+struct Header {
+    version: u16,
+    model: u8,
+    header_checksum: u8,
+    global_checksum: u16,
+    body_length: u32,
+}
+
+impl Header {
+    fn for_rom(cartridge: &Cartridge, body_length: usize) -> Header {
+        Header {
+            version: VERSION,
+            model: MODEL_DMG,
+            header_checksum: cartridge.read_byte(0x014d),
+            global_checksum: u16::from_be_bytes([
+                cartridge.read_byte(0x014e),
+                cartridge.read_byte(0x014f),
+            ]),
+            body_length: body_length as u32,
+        }
+    }
+
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(MAGIC);
+        writer.u16(self.version);
+        writer.u8(self.model);
+        writer.u8(self.header_checksum);
+        writer.u16(self.global_checksum);
+        writer.u32(self.body_length);
+    }
+
+    fn load(reader: &mut StateReader) -> Result<Header, String> {
+        let mut magic = [0u8; MAGIC.len()];
+        reader
+            .bytes(&mut magic)
+            .map_err(|_| "Not a save state".to_string())?;
+        if &magic != MAGIC {
+            return Err("Not a save state".to_string());
+        }
+
+        Ok(Header {
+            version: reader.u16()?,
+            model: reader.u8()?,
+            header_checksum: reader.u8()?,
+            global_checksum: reader.u16()?,
+            body_length: reader.u32()?,
+        })
+    }
+}
+
+/// The file of save slot `slot`, next to the ROM: `game.ss1` for slot 1
+pub(crate) fn slot_path(rom: &Path, slot: u8) -> PathBuf {
+    rom.with_extension(format!("ss{}", slot))
+}
+
+impl Device {
+    /**
+     * A snapshot of everything the emulated hardware needs to continue from this point:
+     * the CPU, all memories, the IO registers with the timers, the PPU in the middle
+     * of its line and the screen drawn so far. Debugger state like breakpoints is not included.
+     */
+    pub(crate) fn save_state(&self) -> Vec<u8> {
+        let mut body = StateWriter::default();
+        body.u64(self.cycle_count);
+        self.cpu.save(&mut body);
+        self.mem_map.save(&mut body);
+        self.ppu.save(&mut body);
+        body.bytes(&self.screen);
+        let body = body.into_bytes();
+
+        let mut state = StateWriter::default();
+        Header::for_rom(&self.cartridge, body.len()).save(&mut state);
+        state.bytes(&body);
+        state.into_bytes()
+    }
+
+    /**
+     * Continues from a state written by `save_state`, anything after it is ignored.
+     * The state must be from the same ROM. It is read completely before the device
+     * is touched, so a broken state leaves the device as it was.
+     */
+    pub(crate) fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        let mut reader = StateReader::new(state);
+        let header = Header::load(&mut reader)?;
+        let expected = Header::for_rom(&self.cartridge, 0);
+
+        if header.version != VERSION {
+            return Err(format!(
+                "The save state has version {}, only version {} is supported",
+                header.version, VERSION
+            ));
+        }
+        if header.model != MODEL_DMG {
+            return Err(format!("Unknown model {} in the save state", header.model));
+        }
+        if header.header_checksum != expected.header_checksum
+            || header.global_checksum != expected.global_checksum
+        {
+            return Err("The save state is for a different ROM".to_string());
+        }
+        // BESS blocks may follow the native state
+        let Some(state) = state.get(..HEADER_LENGTH + header.body_length as usize) else {
+            return Err("The save state is truncated".to_string());
+        };
+        let mut reader = StateReader::new(&state[HEADER_LENGTH..]);
+
+        let cycle_count = reader.u64()?;
+        let mut cpu = CPU::new();
+        cpu.load(&mut reader)?;
+        let memories = Memories::load(&mut reader)?;
+        let mut ppu = PPU::new();
+        ppu.load(&mut reader)?;
+        let mut screen = vec![0; self.screen.len()].into_boxed_slice();
+        reader.bytes(&mut screen)?;
+        if !reader.is_empty() {
+            return Err("Unexpected data at the end of the save state".to_string());
+        }
+
+        self.cycle_count = cycle_count;
+        self.mem_map.writes.now = cycle_count;
+        self.cpu = cpu;
+        self.mem_map.restore(memories);
+        self.ppu = ppu;
+        self.screen = screen;
+        self.seek_movie();
+        Ok(())
+    }
+
+    /// Saves to the numbered slot next to the ROM, returns the file written
+    pub(crate) fn save_slot(&self, slot: u8) -> Result<PathBuf, String> {
+        let path = self.slot_file(slot)?;
+        self.save_state_to(&path)?;
+        Ok(path)
+    }
+
+    pub(crate) fn load_slot(&mut self, slot: u8) -> Result<PathBuf, String> {
+        let path = self.slot_file(slot)?;
+        self.load_state_from(&path)?;
+        Ok(path)
+    }
+
+    fn slot_file(&self, slot: u8) -> Result<PathBuf, String> {
+        self.rom_file
+            .as_deref()
+            .map(|rom| slot_path(rom, slot))
+            .ok_or_else(|| "Save slots need the ROM file".to_string())
+    }
+
+    /// Writes the state with BESS blocks, so other emulators can load it as well
+    pub(crate) fn save_state_to(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.save_bess())
+            .map_err(|e| format!("Failed to write the save state {}: {}", path.display(), e))
+    }
+
+    pub(crate) fn load_state_from(&mut self, path: &Path) -> Result<(), String> {
+        let state = fs::read(path)
+            .map_err(|e| format!("Failed to read the save state {}: {}", path.display(), e))?;
+        let result = match self.load_state(&state) {
+            // a state of another emulator, or of an older version of ours
+            Err(_) if has_bess(&state) => self.load_bess(&state),
+            result => result,
+        };
+        result.map_err(|e| format!("Failed to load {}: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use crate::screen::{HEIGHT, WIDTH};
+
+use super::device::Device;
+
+impl Device {
+    /// Writes the LCD as it is now to a PNG file
+    pub(crate) fn save_screenshot(&self, path: &Path) -> Result<(), String> {
+        write_screenshot(path, &self.screen)
+    }
+}
+
+/// Writes RGBA pixels of the size of the LCD to a PNG file
+pub(crate) fn write_screenshot(path: &Path, pixels: &[u8]) -> Result<(), String> {
+    let error = |e: &dyn std::fmt::Display| {
+        format!("Failed to write the screenshot {}: {}", path.display(), e)
+    };
+
+    let file = File::create(path).map_err(|e| error(&e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), WIDTH, HEIGHT);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(|e| error(&e))?;
+    writer.write_image_data(pixels).map_err(|e| error(&e))?;
+    writer.finish().map_err(|e| error(&e))
+}
+
+/**
+ * Reads a PNG of the size of the LCD, like a screenshot, as RGBA pixels.
+ * Other color types are converted, so references can be saved by any image editor.
+ */
+pub(crate) fn load_screenshot(path: &Path) -> Result<Vec<u8>, String> {
+    let error = |e: &dyn std::fmt::Display| {
+        format!("Failed to read the screenshot {}: {}", path.display(), e)
+    };
+
+    let file = File::open(path).map_err(|e| error(&e))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| error(&e))?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).map_err(|e| error(&e))?;
+    if (info.width, info.height) != (WIDTH, HEIGHT) {
+        return Err(error(&format!(
+            "it is {}x{} pixels instead of {}x{}",
+            info.width, info.height, WIDTH, HEIGHT
+        )));
+    }
+
+    let pixels = &pixels[..info.buffer_size()];
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xff])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks(2)
+            .flat_map(|gray| [gray[0], gray[0], gray[0], gray[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels
+            .iter()
+            .flat_map(|gray| [*gray, *gray, *gray, 0xff])
+            .collect(),
+        // expanded to RGB by the decoder
+        png::ColorType::Indexed => return Err(error(&"the palette was not expanded")),
+    };
+    Ok(rgba)
+}
@@ -0,0 +1,201 @@
+use std::ops::RangeInclusive;
+
+use mygbcartridge::cartridge::Cartridge;
+
+use crate::{
+    cpu::{
+        CPUState,
+        addressing_mode::AddressingMode,
+        instruction::{Instruction, InstructionType},
+    },
+    memory::generic_memory::AddressSpace,
+};
+
+/**
+ * The ROM part of the address space (0x0000-0x7FFF) with a fixed bank
+ * mapped into the switchable area at 0x4000-0x7FFF.
+ * Everything above 0x7FFF reads as 0xFF.
+ */
+pub(crate) struct RomBank<'a> {
+    cartridge: &'a Cartridge,
+    bank: u16,
+}
+
+impl<'a> RomBank<'a> {
+    pub(crate) fn new(cartridge: &'a Cartridge, bank: u16) -> RomBank<'a> {
+        // like on the real hardware, bank 0 cannot be mapped to the switchable area
+        RomBank {
+            cartridge,
+            bank: bank.max(1),
+        }
+    }
+
+    pub(crate) fn bank_for_address(&self, address: u16) -> u16 {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.bank,
+        }
+    }
+}
+
+impl AddressSpace for RomBank<'_> {
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => self
+                .cartridge
+                .read_banked_byte(self.bank_for_address(address), address),
+            _ => 0xff,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct DisassembledInstruction {
+    pub(crate) bank: u16,
+    pub(crate) address: u16,
+    pub(crate) bytes: Vec<u8>,
+    /// `None` if the bytes at this address do not decode to a valid opcode
+    pub(crate) instruction: Option<Instruction>,
+    /// The address a jump, call or rst would continue at, if it is statically known
+    pub(crate) jump_target: Option<u16>,
+}
+
+impl DisassembledInstruction {
+    pub(crate) fn size(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    pub(crate) fn immediate_byte(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    pub(crate) fn immediate_word(&self) -> u16 {
+        u16::from_le_bytes([self.bytes[1], self.bytes[2]])
+    }
+}
+
+pub(crate) struct Disassembler<'a> {
+    rom: RomBank<'a>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub(crate) fn new(cartridge: &'a Cartridge, bank: u16) -> Disassembler<'a> {
+        Disassembler {
+            rom: RomBank::new(cartridge, bank),
+        }
+    }
+
+    pub(crate) fn disassemble_at(&self, address: u16) -> DisassembledInstruction {
+        disassemble(&self.rom, self.rom.bank_for_address(address), address)
+    }
+
+    /**
+     * Linearly disassembles the given address range.
+     * The last instruction may extend past the end of the range.
+     */
+    pub(crate) fn disassemble(&self, range: RangeInclusive<u16>) -> Disassembly<'_, 'a> {
+        Disassembly {
+            disassembler: self,
+            next_address: *range.start() as u32,
+            end: *range.end() as u32,
+        }
+    }
+}
+
+pub(crate) struct Disassembly<'d, 'a> {
+    disassembler: &'d Disassembler<'a>,
+    next_address: u32,
+    end: u32,
+}
+
+impl Iterator for Disassembly<'_, '_> {
+    type Item = DisassembledInstruction;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_address > self.end {
+            return None;
+        }
+
+        let disassembled = self.disassembler.disassemble_at(self.next_address as u16);
+        self.next_address += disassembled.size() as u32;
+
+        Some(disassembled)
+    }
+}
+
+/**
+ * Decodes the single instruction at `address`, `bank` is only used to tag the result.
+ * Invalid opcodes are returned as a one byte instruction without decoded content.
+ */
+pub(crate) fn disassemble(
+    memory: &impl AddressSpace,
+    bank: u16,
+    address: u16,
+) -> DisassembledInstruction {
+    match Instruction::create(address, memory) {
+        Ok(instruction) => {
+            let bytes = (0..instruction.size() as u16)
+                .map(|i| memory.read_byte(address.wrapping_add(i)))
+                .collect::<Vec<u8>>();
+            let jump_target = resolve_jump_target(&instruction, &bytes);
+
+            DisassembledInstruction {
+                bank,
+                address,
+                bytes,
+                instruction: Some(instruction),
+                jump_target,
+            }
+        }
+        Err(_) => DisassembledInstruction {
+            bank,
+            address,
+            bytes: vec![memory.read_byte(address)],
+            instruction: None,
+            jump_target: None,
+        },
+    }
+}
+
+/**
+ * The bytes at PC captured in a CPU state, enough to decode the instruction.
+ */
+struct CapturedInstruction<'a>(&'a CPUState);
+
+impl AddressSpace for CapturedInstruction<'_> {
+    fn read_byte(&self, address: u16) -> u8 {
+        let offset = address.wrapping_sub(*self.0.register_set.pc()) as usize;
+        self.0
+            .current_instruction_bytes
+            .get(offset)
+            .copied()
+            .unwrap_or(0xff)
+    }
+}
+
+/**
+ * Decodes the instruction a CPU state was captured before, from the bytes it holds.
+ */
+pub(crate) fn disassemble_state(state: &CPUState) -> DisassembledInstruction {
+    disassemble(
+        &CapturedInstruction(state),
+        state.bank.unwrap_or(0),
+        *state.register_set.pc(),
+    )
+}
+
+fn resolve_jump_target(instruction: &Instruction, bytes: &[u8]) -> Option<u16> {
+    match (instruction.instruction_type, instruction.target) {
+        (InstructionType::Jump | InstructionType::Call, Some(AddressingMode::ImmediateWord)) => {
+            Some(u16::from_le_bytes([bytes[1], bytes[2]]))
+        }
+        (InstructionType::JumpRelative, _) => Some(
+            instruction
+                .address
+                .wrapping_add(2)
+                .wrapping_add(bytes[1] as i8 as u16),
+        ),
+        (InstructionType::Reset, Some(AddressingMode::Target(target))) => Some(target),
+        _ => None,
+    }
+}
@@ -0,0 +1,5 @@
+pub(crate) mod disassembler;
+pub(crate) mod project;
+pub(crate) mod rgbds;
+pub(crate) mod symbols;
+pub(crate) mod tracer;
@@ -0,0 +1,466 @@
+use std::{fmt::Write as _, fs, path::Path};
+
+use mygbcartridge::cartridge::Cartridge;
+
+use crate::cpu::{addressing_mode::AddressingMode, instruction::InstructionType};
+
+use super::{
+    disassembler::{DisassembledInstruction, Disassembler},
+    rgbds::{format_data, format_instruction},
+    tracer::{BANK_SIZE, ByteUsage, RomAnalysis},
+};
+
+const HEADER_LABELS: [(u16, &str); 13] = [
+    (0x0104, "HeaderLogo"),
+    (0x0134, "HeaderTitle"),
+    (0x0143, "HeaderCGBFlag"),
+    (0x0144, "HeaderNewLicenseeCode"),
+    (0x0146, "HeaderSGBFlag"),
+    (0x0147, "HeaderCartridgeType"),
+    (0x0148, "HeaderROMSize"),
+    (0x0149, "HeaderRAMSize"),
+    (0x014A, "HeaderDestinationCode"),
+    (0x014B, "HeaderOldLicenseeCode"),
+    (0x014C, "HeaderMaskROMVersion"),
+    (0x014D, "HeaderChecksum"),
+    (0x014E, "HeaderGlobalChecksum"),
+];
+
+const DATA_BYTES_PER_LINE: usize = 8;
+
+/**
+ * Writes an RGBDS project for the traced ROM to `output`: one source file per bank,
+ * a main file including all of them, and a Makefile building the ROM with rgbasm/rgblink.
+ *
+ * The sources rebuild the original ROM byte for byte, everything that was not
+ * identified as code or jump table is emitted as `db`, and so are instructions
+ * rgbasm might encode differently.
+ */
+pub(crate) fn write_project(
+    cartridge: &Cartridge,
+    analysis: &RomAnalysis,
+    output: &Path,
+) -> Result<(), String> {
+    fs::create_dir_all(output)
+        .map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+
+    let mut main_source = String::new();
+    let _ = writeln!(
+        main_source,
+        "; Disassembly of \"{}\"",
+        cartridge.get_title()
+    );
+    if let Some(cartridge_type) = cartridge.get_cartridge_type() {
+        let _ = writeln!(main_source, "; Cartridge type: {}", cartridge_type);
+    }
+    let _ = writeln!(main_source, "; ROM banks: {}", analysis.usage.len());
+    let _ = writeln!(main_source);
+
+    for bank in 0..analysis.usage.len() as u16 {
+        let file_name = format!("bank_{:03x}.asm", bank);
+        let source = bank_source(cartridge, analysis, bank);
+        fs::write(output.join(&file_name), source)
+            .map_err(|e| format!("Failed to write {}: {}", file_name, e))?;
+
+        let _ = writeln!(main_source, "INCLUDE \"{}\"", file_name);
+    }
+
+    fs::write(output.join("game.asm"), main_source)
+        .map_err(|e| format!("Failed to write game.asm: {}", e))?;
+    fs::write(output.join("Makefile"), MAKEFILE)
+        .map_err(|e| format!("Failed to write Makefile: {}", e))?;
+
+    Ok(())
+}
+
+const MAKEFILE: &str = "game.gb: game.o
+\trgblink -o $@ $<
+
+game.o: game.asm $(wildcard bank_*.asm)
+\trgbasm -o $@ $<
+
+clean:
+\trm -f game.o game.gb
+
+.PHONY: clean
+";
+
+fn bank_source(cartridge: &Cartridge, analysis: &RomAnalysis, bank: u16) -> String {
+    let base_address: u16 = if bank == 0 { 0x0000 } else { 0x4000 };
+    let bank_length = cartridge
+        .size()
+        .saturating_sub(bank as usize * BANK_SIZE)
+        .min(BANK_SIZE);
+    let disassembler = Disassembler::new(cartridge, bank);
+
+    // labels from the analysis first, as code might refer to them
+    let label_at = |address: u16| -> Option<String> {
+        analysis.label(bank, address).cloned().or_else(|| {
+            HEADER_LABELS
+                .iter()
+                .find(|(header_address, _)| bank == 0 && *header_address == address)
+                .filter(|_| analysis.is_boundary(bank, address))
+                .map(|(_, name)| name.to_string())
+        })
+    };
+
+    let mut source = String::new();
+    let _ = match bank {
+        0 => writeln!(source, "SECTION \"ROM Bank $000\", ROM0[$0000]"),
+        _ => writeln!(
+            source,
+            "SECTION \"ROM Bank ${:03x}\", ROMX[$4000], BANK[${:x}]",
+            bank, bank
+        ),
+    };
+
+    let mut offset = 0;
+    while offset < bank_length {
+        let address = base_address + offset as u16;
+
+        if let Some(label) = label_at(address) {
+            let _ = writeln!(source, "\n{}:", label);
+        }
+
+        match analysis.usage_at(bank, address) {
+            ByteUsage::InstructionStart => {
+                let disassembled = disassembler.disassemble_at(address);
+                let is_stop = matches!(
+                    disassembled.instruction.map(|i| i.instruction_type),
+                    Some(InstructionType::Stop)
+                );
+                let is_relative = matches!(
+                    disassembled.instruction.map(|i| i.instruction_type),
+                    Some(InstructionType::JumpRelative)
+                );
+
+                let _ = match has_ambiguous_encoding(&disassembled) {
+                    true => writeln!(
+                        source,
+                        "    {:<32} ; ${:04x} {}",
+                        format_data(&disassembled.bytes),
+                        address,
+                        disassembled
+                    ),
+                    false => {
+                        let text = format_instruction(&disassembled, &|target| {
+                            target_label(analysis, bank, target, is_relative)
+                        });
+                        writeln!(source, "    {:<32} ; ${:04x}", text, address)
+                    }
+                };
+
+                // STOP is traced including its padding byte
+                offset += match is_stop {
+                    true => 2,
+                    false => disassembled.size() as usize,
+                };
+            }
+            ByteUsage::JumpTableEntry => {
+                let target = u16::from_le_bytes([
+                    cartridge.read_banked_byte(bank, address),
+                    cartridge.read_banked_byte(bank, address + 1),
+                ]);
+                let text = target_label(analysis, bank, target, false)
+                    .unwrap_or_else(|| format!("${:04x}", target));
+                let _ = writeln!(source, "    dw {}", text);
+
+                offset += 2;
+            }
+            ByteUsage::Unknown | ByteUsage::Operand => {
+                let mut bytes = vec![cartridge.read_banked_byte(bank, address)];
+                offset += 1;
+
+                // data runs until the next label, code or the end of the line
+                while offset < bank_length && bytes.len() < DATA_BYTES_PER_LINE {
+                    let address = base_address + offset as u16;
+                    if label_at(address).is_some()
+                        || analysis.usage_at(bank, address) != ByteUsage::Unknown
+                    {
+                        break;
+                    }
+                    bytes.push(cartridge.read_banked_byte(bank, address));
+                    offset += 1;
+                }
+
+                let _ = writeln!(source, "    {}", format_data(&bytes));
+            }
+        }
+    }
+
+    source
+}
+
+/**
+ * Whether rgbasm could emit other bytes for the instruction than the ones in the ROM:
+ * depending on its version and flags it assembles `ld` from $FF00-$FFFF as `ldh`,
+ * and follows `halt` with a `nop`.
+ */
+fn has_ambiguous_encoding(disassembled: &DisassembledInstruction) -> bool {
+    let Some(instruction) = disassembled.instruction else {
+        return false;
+    };
+
+    match instruction.instruction_type {
+        InstructionType::Halt => true,
+        InstructionType::LoadByte => {
+            let pointer = |mode| matches!(mode, Some(AddressingMode::ImmediatePointer));
+            (pointer(instruction.target) || pointer(instruction.source))
+                && disassembled.immediate_word() >= 0xff00
+        }
+        _ => false,
+    }
+}
+
+/**
+ * The label for a jump target as seen from code in `bank`.
+ * Relative jumps only use labels from their own section, as rgblink
+ * cannot resolve them across sections.
+ */
+fn target_label(
+    analysis: &RomAnalysis,
+    bank: u16,
+    target: u16,
+    same_section_only: bool,
+) -> Option<String> {
+    match target {
+        0x0000..=0x3FFF if bank == 0 || !same_section_only => analysis.label(0, target).cloned(),
+        0x4000..=0x7FFF if bank != 0 => analysis.label(bank, target).cloned(),
+        // from bank 0 we can only tell the bank if exactly one of them has a label there
+        0x4000..=0x7FFF if !same_section_only => {
+            let mut labels =
+                (1..analysis.usage.len() as u16).filter_map(|bank| analysis.label(bank, target));
+            match (labels.next(), labels.next()) {
+                (Some(label), None) => Some(label.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use mygbcartridge::cartridge::Cartridge;
+
+    use crate::disasm::tracer::{BANK_SIZE, CodeTracer};
+
+    use super::bank_source;
+
+    /**
+     * The instructions of the test ROM with their opcodes from the SM83 opcode table,
+     * independent of the decoder. Operands are `{n8}`, `{n16}`, `{e8}` for a relative jump
+     * target, `{s8}` for a signed offset and `{hram}` for an address in $FF00-$FFFF.
+     */
+    const OPCODES: [(&str, &[u8]); 18] = [
+        ("nop", &[0x00]),
+        ("ret", &[0xc9]),
+        ("jp {n16}", &[0xc3]),
+        ("call {n16}", &[0xcd]),
+        ("jr {e8}", &[0x18]),
+        ("jr nz, {e8}", &[0x20]),
+        ("jr z, {e8}", &[0x28]),
+        ("ld hl, {n16}", &[0x21]),
+        ("ld [{n16}], a", &[0xea]),
+        ("ld a, [{n16}]", &[0xfa]),
+        ("ldh [{hram}], a", &[0xe0]),
+        ("ldh a, [{hram}]", &[0xf0]),
+        ("ldh [c], a", &[0xe2]),
+        ("ld hl, sp {s8}", &[0xf8]),
+        ("add sp, {s8}", &[0xe8]),
+        ("bit 7, h", &[0xcb, 0x7c]),
+        ("halt", &[0x76]),
+        ("stop", &[0x10, 0x00]),
+    ];
+
+    /// Parses a `$` hex number at the start of `text`, returns it with the rest of the text
+    fn parse_hex(text: &str) -> Option<(u16, &str)> {
+        let digits = text.strip_prefix('$')?;
+        let length = digits
+            .find(|c: char| !c.is_ascii_hexdigit())
+            .unwrap_or(digits.len());
+        let value = u16::from_str_radix(&digits[..length], 16).ok()?;
+        Some((value, &digits[length..]))
+    }
+
+    /// The operand bytes if `line` is the instruction `template` at `address`
+    fn match_template(template: &str, line: &str, address: u16, size: u16) -> Option<Vec<u8>> {
+        let Some((prefix, rest)) = template.split_once('{') else {
+            return (template == line).then(Vec::new);
+        };
+        let (operand, suffix) = rest.split_once('}')?;
+        let line = line.strip_prefix(prefix)?;
+
+        let (bytes, rest) = match operand {
+            "n8" => parse_hex(line).map(|(value, rest)| (vec![value as u8], rest))?,
+            "n16" => parse_hex(line).map(|(value, rest)| (value.to_le_bytes().to_vec(), rest))?,
+            "hram" => match parse_hex(line)? {
+                (value @ 0xff00..=0xffff, rest) => (vec![value as u8], rest),
+                _ => return None,
+            },
+            "e8" => {
+                let (target, rest) = parse_hex(line)?;
+                let offset = target.wrapping_sub(address.wrapping_add(size)) as i16;
+                (vec![i8::try_from(offset).ok()? as u8], rest)
+            }
+            "s8" => {
+                let (negative, line) = match line.strip_prefix('-') {
+                    Some(line) => (true, line.trim_start()),
+                    None => (false, line.trim_start_matches("+ ")),
+                };
+                let (value, rest) = parse_hex(line)?;
+                let value = if negative {
+                    -(value as i16)
+                } else {
+                    value as i16
+                };
+                (vec![i8::try_from(value).ok()? as u8], rest)
+            }
+            _ => panic!("Unknown operand {{{}}}", operand),
+        };
+        (rest == suffix).then_some(bytes)
+    }
+
+    /**
+     * Assembles an instruction like rgbasm with all of its optimizations enabled:
+     * `ld` from $FF00-$FFFF becomes `ldh` and `halt` is followed by a `nop`.
+     */
+    fn assemble_instruction(line: &str, address: u16) -> Vec<u8> {
+        for (template, opcode) in OPCODES {
+            let operand_size = ["{n16}", "{n8}", "{e8}", "{s8}", "{hram}"]
+                .iter()
+                .find(|operand| template.contains(*operand))
+                .map_or(0, |operand| if *operand == "{n16}" { 2 } else { 1 });
+            let size = opcode.len() as u16 + operand_size;
+            let Some(operands) = match_template(template, line, address, size) else {
+                continue;
+            };
+
+            return match (template, operands.as_slice()) {
+                ("ld [{n16}], a", [low, 0xff]) => vec![0xe0, *low],
+                ("ld a, [{n16}]", [low, 0xff]) => vec![0xf0, *low],
+                ("halt", _) => vec![0x76, 0x00],
+                _ => [opcode, operands.as_slice()].concat(),
+            };
+        }
+        panic!("Cannot assemble \"{}\"", line);
+    }
+
+    /// Assembles the bank sources back into a ROM, in two passes to resolve the labels
+    fn assemble(sources: &[String]) -> Vec<u8> {
+        let mut labels: HashMap<String, u16> = HashMap::new();
+        let mut rom = Vec::new();
+
+        for pass in 0..2 {
+            rom.clear();
+            for source in sources {
+                let mut address = 0;
+                for line in source.lines() {
+                    let line = line.split(';').next().unwrap().trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    if line.starts_with("SECTION") {
+                        address = if line.contains("ROM0") {
+                            0x0000
+                        } else {
+                            0x4000
+                        };
+                        continue;
+                    }
+                    if let Some(label) = line.strip_suffix(':') {
+                        labels.insert(label.to_string(), address);
+                        continue;
+                    }
+
+                    // labels are the only operands with upper case letters or underscores
+                    let value = |name: &str| match labels.get(name) {
+                        Some(value) => *value,
+                        None if pass == 0 => address,
+                        None => panic!("Undefined label {}", name),
+                    };
+                    let is_label =
+                        |token: &str| token.contains('_') || token.starts_with(char::is_uppercase);
+
+                    let bytes = if let Some(data) = line.strip_prefix("db ") {
+                        data.split(", ")
+                            .map(|byte| u8::from_str_radix(&byte[1..], 16).unwrap())
+                            .collect()
+                    } else if let Some(word) = line.strip_prefix("dw ") {
+                        let word = match is_label(word) {
+                            true => value(word),
+                            false => u16::from_str_radix(&word[1..], 16).unwrap(),
+                        };
+                        word.to_le_bytes().to_vec()
+                    } else {
+                        let line = line
+                            .split(' ')
+                            .map(|token| match is_label(token) {
+                                true => format!("${:04x}", value(token)),
+                                false => token.to_string(),
+                            })
+                            .collect::<Vec<String>>()
+                            .join(" ");
+                        assemble_instruction(&line, address)
+                    };
+
+                    address += bytes.len() as u16;
+                    rom.extend(bytes);
+                }
+            }
+        }
+
+        rom
+    }
+
+    #[test]
+    fn sources_rebuild_the_rom() {
+        let mut data = vec![0x00; 2 * BANK_SIZE];
+        let mut code = |address: usize, bytes: &[u8]| {
+            data[address..address + bytes.len()].copy_from_slice(bytes);
+        };
+        code(0x0100, &[0x00, 0xc3, 0x50, 0x01]); // nop, jp $0150
+        code(
+            0x0150,
+            &[
+                0xea, 0x44, 0xff, // ld [$ff44], a
+                0xe0, 0x44, // ldh [$ff44], a
+                0xfa, 0x00, 0xff, // ld a, [$ff00]
+                0xfa, 0x00, 0xc0, // ld a, [$c000]
+                0x76, // halt
+                0x10, 0x00, // stop
+                0x20, 0xfe, // jr nz, @
+                0xcd, 0x00, 0x40, // call $4000
+                0xe2, // ldh [c], a
+                0xf8, 0xfe, // ld hl, sp - $02
+                0xe8, 0x80, // add sp, -$80
+                0x18, 0xf4, // jr $015e
+            ],
+        );
+        code(0x0170, &[0xc9]); // ret
+        code(0x0200, &[0x70, 0x01, 0x10, 0x40]); // jump table
+        code(0x0300, b"DATA\xd3\xff");
+        code(
+            0x4000,
+            &[
+                0x21, 0x34, 0x12, // ld hl, $1234
+                0xcb, 0x7c, // bit 7, h
+                0x28, 0xf9, // jr z, $4000
+                0xc3, 0x70, 0x01, // jp $0170
+            ],
+        );
+        code(0x4010, &[0xc9]); // ret
+        let cartridge = Cartridge { data: data.clone() };
+
+        let mut tracer = CodeTracer::new(&cartridge);
+        tracer.add_jump_table(0, 0x0200, 2);
+        let analysis = tracer.run();
+        let sources = (0..2)
+            .map(|bank| bank_source(&cartridge, &analysis, bank))
+            .collect::<Vec<String>>();
+
+        assert_eq!(assemble(&sources), data);
+    }
+}
@@ -0,0 +1,242 @@
+use std::fmt::Display;
+
+use crate::cpu::{
+    CPUState,
+    addressing_mode::AddressingMode,
+    instruction::{Instruction, InstructionType},
+    register_set::WordRegister,
+};
+
+use super::{
+    disassembler::{DisassembledInstruction, disassemble_state},
+    symbols::SymbolTable,
+};
+
+/**
+ * Renders a disassembled instruction in RGBDS (rgbasm) syntax.
+ *
+ * `address_name` may return a label for jump and call targets, if it returns
+ * `None` the plain address is used.
+ */
+pub(crate) fn format_instruction(
+    disassembled: &DisassembledInstruction,
+    address_name: &dyn Fn(u16) -> Option<String>,
+) -> String {
+    let Some(instruction) = &disassembled.instruction else {
+        return format_data(&disassembled.bytes);
+    };
+
+    let operands = operands(disassembled, instruction, address_name);
+    let mnemonic = mnemonic(instruction);
+
+    let condition = instruction
+        .condition
+        .map(|condition| format!("{}", condition).to_lowercase());
+
+    let operands = condition
+        .into_iter()
+        .chain(operands)
+        .collect::<Vec<String>>();
+
+    match operands.is_empty() {
+        true => mnemonic.to_string(),
+        false => format!("{} {}", mnemonic, operands.join(", ")),
+    }
+}
+
+pub(crate) fn format_data(bytes: &[u8]) -> String {
+    let bytes = bytes
+        .iter()
+        .map(|byte| format!("${:02x}", byte))
+        .collect::<Vec<String>>();
+
+    format!("db {}", bytes.join(", "))
+}
+
+/**
+ * A full listing line: the instruction, followed by its address and raw bytes as a comment.
+ */
+pub(crate) fn format_listing_line(disassembled: &DisassembledInstruction) -> String {
+    let bytes = disassembled
+        .bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<String>>();
+
+    format!(
+        "    {:<24} ; {:02x}:{:04x}  {}",
+        disassembled.to_string(),
+        disassembled.bank,
+        disassembled.address,
+        bytes.join(" ")
+    )
+}
+
+/**
+ * The instruction a CPU state was captured before, with its location: `bb:aaaa  instruction`.
+ * Code running from RAM has no bank. Jump targets are named by their label.
+ */
+pub(crate) fn format_state_instruction(state: &CPUState, symbols: &SymbolTable) -> String {
+    let pc = *state.register_set.pc();
+    let location = match state.bank {
+        Some(bank) => format!("{:02x}:{:04x}", bank, pc),
+        None => format!("   {:04x}", pc),
+    };
+    // code in bank 0 does not tell which bank is mapped
+    let mapped_bank = state.bank.filter(|_| pc >= 0x4000);
+    let instruction = format_instruction(&disassemble_state(state), &|target| {
+        symbols.label(mapped_bank, target).map(str::to_string)
+    });
+
+    format!("{}  {}", location, instruction)
+}
+
+impl Display for DisassembledInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format_instruction(self, &|_| None))
+    }
+}
+
+fn mnemonic(instruction: &Instruction) -> &'static str {
+    let cb_prefixed = instruction.opcode == 0xcb;
+
+    match instruction.instruction_type {
+        InstructionType::Nop => "nop",
+        InstructionType::LoadByte | InstructionType::LoadWord | InstructionType::LoadHLAdjusted => {
+            "ld"
+        }
+        InstructionType::LoadHigh => "ldh",
+        InstructionType::IncByte | InstructionType::IncWord => "inc",
+        InstructionType::DecByte | InstructionType::DecWord => "dec",
+        InstructionType::AddByte | InstructionType::AddWord | InstructionType::AddSPAdjusted => {
+            "add"
+        }
+        InstructionType::AddWithCarry => "adc",
+        InstructionType::Sub => "sub",
+        InstructionType::SubWithCarry => "sbc",
+        InstructionType::And => "and",
+        InstructionType::Xor => "xor",
+        InstructionType::Or => "or",
+        InstructionType::Cp => "cp",
+        InstructionType::RotateLeft if cb_prefixed => "rl",
+        InstructionType::RotateLeft => "rla",
+        InstructionType::RotateRight if cb_prefixed => "rr",
+        InstructionType::RotateRight => "rra",
+        InstructionType::RotateLeftCircular if cb_prefixed => "rlc",
+        InstructionType::RotateLeftCircular => "rlca",
+        InstructionType::RotateRightCircular if cb_prefixed => "rrc",
+        InstructionType::RotateRightCircular => "rrca",
+        InstructionType::ShiftLeftArithmetically => "sla",
+        InstructionType::ShiftRightArithmetically => "sra",
+        InstructionType::Swap => "swap",
+        InstructionType::ShiftRightLogically => "srl",
+        InstructionType::DecimalAdjustAccumulator => "daa",
+        InstructionType::ComplementAccumulator => "cpl",
+        InstructionType::SetCarryFlag => "scf",
+        InstructionType::ComplementCarryFlag => "ccf",
+        InstructionType::Jump => "jp",
+        InstructionType::JumpRelative => "jr",
+        InstructionType::Push => "push",
+        InstructionType::Pop => "pop",
+        InstructionType::Return => "ret",
+        InstructionType::ReturnInterrupt => "reti",
+        InstructionType::Call => "call",
+        InstructionType::Reset => "rst",
+        InstructionType::Stop => "stop",
+        InstructionType::Halt => "halt",
+        InstructionType::DisableInterrupts => "di",
+        InstructionType::EnableInterrupts => "ei",
+        InstructionType::TestBit => "bit",
+        InstructionType::ResetBit => "res",
+        InstructionType::SetBit => "set",
+    }
+}
+
+fn operands(
+    disassembled: &DisassembledInstruction,
+    instruction: &Instruction,
+    address_name: &dyn Fn(u16) -> Option<String>,
+) -> Vec<String> {
+    let operand =
+        |mode: &Option<AddressingMode>| mode.map(|mode| format_operand(&mode, disassembled));
+    let jump_target = || {
+        disassembled
+            .jump_target
+            .map(|target| address_name(target).unwrap_or_else(|| format!("${:04x}", target)))
+    };
+
+    match instruction.instruction_type {
+        // the accumulator versions have an implicit operand
+        InstructionType::RotateLeft
+        | InstructionType::RotateRight
+        | InstructionType::RotateLeftCircular
+        | InstructionType::RotateRightCircular
+            if instruction.opcode != 0xcb =>
+        {
+            vec![]
+        }
+        InstructionType::TestBit | InstructionType::ResetBit | InstructionType::SetBit => {
+            operand(&instruction.source)
+                .into_iter()
+                .chain(operand(&instruction.target))
+                .collect()
+        }
+        InstructionType::Sub
+        | InstructionType::And
+        | InstructionType::Xor
+        | InstructionType::Or
+        | InstructionType::Cp => operand(&instruction.source).into_iter().collect(),
+        InstructionType::JumpRelative => jump_target().into_iter().collect(),
+        InstructionType::Jump | InstructionType::Call if disassembled.jump_target.is_some() => {
+            jump_target().into_iter().collect()
+        }
+        InstructionType::Reset => match instruction.target {
+            Some(AddressingMode::Target(target)) => vec![format!("${:02x}", target)],
+            _ => vec![],
+        },
+        InstructionType::AddSPAdjusted => {
+            vec![
+                "sp".to_string(),
+                format_signed(disassembled.immediate_byte() as i8),
+            ]
+        }
+        InstructionType::LoadHLAdjusted => {
+            let offset = disassembled.immediate_byte() as i8;
+            let sign = if offset < 0 { '-' } else { '+' };
+            vec![
+                "hl".to_string(),
+                format!("sp {} ${:02x}", sign, offset.unsigned_abs()),
+            ]
+        }
+        _ => operand(&instruction.target)
+            .into_iter()
+            .chain(operand(&instruction.source))
+            .collect(),
+    }
+}
+
+fn format_signed(value: i8) -> String {
+    match value < 0 {
+        true => format!("-${:02x}", value.unsigned_abs()),
+        false => format!("${:02x}", value),
+    }
+}
+
+fn format_operand(mode: &AddressingMode, disassembled: &DisassembledInstruction) -> String {
+    match mode {
+        AddressingMode::Target(target) => format!("${:02x}", target),
+        AddressingMode::ByteRegister(register) => format!("{}", register).to_lowercase(),
+        AddressingMode::WordRegister(register) => format!("{}", register).to_lowercase(),
+        AddressingMode::RegisterPointer(WordRegister::HLi) => "[hl+]".to_string(),
+        AddressingMode::RegisterPointer(WordRegister::HLd) => "[hl-]".to_string(),
+        AddressingMode::RegisterPointer(register) => format!("[{}]", register).to_lowercase(),
+        AddressingMode::RegisterPointerHigh(register) => format!("[{}]", register).to_lowercase(),
+        AddressingMode::ImmediateByte => format!("${:02x}", disassembled.immediate_byte()),
+        AddressingMode::ImmediateWord => format!("${:04x}", disassembled.immediate_word()),
+        AddressingMode::ImmediatePointer => format!("[${:04x}]", disassembled.immediate_word()),
+        AddressingMode::ImmediatePointerHigh => {
+            format!("[$ff{:02x}]", disassembled.immediate_byte())
+        }
+        AddressingMode::BitPosition(position) => format!("{}", position),
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/**
+ * The labels of an RGBDS symbol file, as written by `rgblink -n game.sym`:
+ * one `BB:AAAA Name` line per label, comments start with `;`.
+ *
+ * The bank only tells apart labels in the switchable ROM area at 0x4000-0x7FFF,
+ * lookups take the ROM bank mapped there, if it is known.
+ */
+#[derive(Default, Debug)]
+pub(crate) struct SymbolTable {
+    /// Names by (address, bank)
+    labels: BTreeMap<(u16, u16), String>,
+    /// (bank, address) by name
+    addresses: HashMap<String, (u16, u16)>,
+}
+
+impl SymbolTable {
+    /// Where RGBDS puts the symbols of `rom` by convention, next to it
+    pub(crate) fn path_for_rom(rom: &Path) -> PathBuf {
+        rom.with_extension("sym")
+    }
+
+    pub(crate) fn load(path: &Path) -> Result<SymbolTable, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read symbols from {}: {}", path.display(), e))?;
+
+        SymbolTable::parse(&content)
+            .map_err(|e| format!("Invalid symbol file {}: {}", path.display(), e))
+    }
+
+    pub(crate) fn parse(content: &str) -> Result<SymbolTable, String> {
+        let mut symbols = SymbolTable::default();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let entry = line
+                .split_once(char::is_whitespace)
+                .and_then(|(location, name)| Some((location.split_once(':')?, name.trim())));
+            let Some(((bank, address), name)) = entry else {
+                return Err(format!("Expected BB:AAAA Name in line {}", index + 1));
+            };
+            let bank = u16::from_str_radix(bank, 16)
+                .map_err(|e| format!("Invalid bank '{}' in line {}: {}", bank, index + 1, e))?;
+            let address = u16::from_str_radix(address, 16).map_err(|e| {
+                format!("Invalid address '{}' in line {}: {}", address, index + 1, e)
+            })?;
+
+            symbols.add(bank, address, name);
+        }
+
+        Ok(symbols)
+    }
+
+    fn add(&mut self, bank: u16, address: u16, name: &str) {
+        // the first label at an address is usually the routine, later ones are local labels
+        self.labels
+            .entry((address, bank))
+            .or_insert_with(|| name.to_string());
+        self.addresses
+            .entry(name.to_string())
+            .or_insert((bank, address));
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    /// The (bank, address) of a label
+    pub(crate) fn address_of(&self, name: &str) -> Option<(u16, u16)> {
+        self.addresses.get(name).copied()
+    }
+
+    /// The label at exactly `address`
+    pub(crate) fn label(&self, bank: Option<u16>, address: u16) -> Option<&str> {
+        self.labels
+            .range((address, 0)..=(address, u16::MAX))
+            .find(|((_, label_bank), _)| matches_bank(address, bank, *label_bank))
+            .map(|(_, name)| name.as_str())
+    }
+
+    /**
+     * The closest label at or before `address` in the same memory area,
+     * with the offset of `address` from it.
+     */
+    pub(crate) fn nearest(&self, bank: Option<u16>, address: u16) -> Option<(&str, u16)> {
+        self.labels
+            .range((area_start(address), 0)..=(address, u16::MAX))
+            .rev()
+            .find(|((_, label_bank), _)| matches_bank(address, bank, *label_bank))
+            .map(|((label_address, _), name)| (name.as_str(), address - label_address))
+    }
+
+    /// `0x0155 (Main.loop+5)`, or just the address if there is no label before it
+    pub(crate) fn format_address(&self, bank: Option<u16>, address: u16) -> String {
+        match self.nearest(bank, address) {
+            Some((name, 0)) => format!("0x{:04x} ({})", address, name),
+            Some((name, offset)) => format!("0x{:04x} ({}+{})", address, name, offset),
+            None => format!("0x{:04x}", address),
+        }
+    }
+}
+
+fn matches_bank(address: u16, bank: Option<u16>, label_bank: u16) -> bool {
+    match (address, bank) {
+        (0x4000..=0x7FFF, Some(bank)) => label_bank == bank,
+        _ => true,
+    }
+}
+
+/// The start of the memory area `address` lies in, labels never reach across areas
+fn area_start(address: u16) -> u16 {
+    match address {
+        0x0000..=0x3FFF => 0x0000,
+        0x4000..=0x7FFF => 0x4000,
+        0x8000..=0x9FFF => 0x8000,
+        0xA000..=0xBFFF => 0xA000,
+        0xC000..=0xDFFF => 0xC000,
+        0xFE00..=0xFE9F => 0xFE00,
+        0xFF80..=0xFFFE => 0xFF80,
+        _ => address,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SymbolTable;
+
+    const SYMBOLS: &str = "; File generated by rgblink
+00:0150 Main ; the entry point
+00:0150 Main.start
+00:0160 Main.loop
+01:4000 BankedRoutine
+02:4000 OtherRoutine
+02:4000 BankedRoutine
+00:c000 wCounter
+";
+
+    #[test]
+    fn skips_comments_and_keeps_the_first_label() {
+        let symbols = SymbolTable::parse(SYMBOLS).unwrap();
+
+        assert_eq!(symbols.len(), 6);
+        assert_eq!(symbols.address_of("Main"), Some((0x00, 0x0150)));
+        assert_eq!(symbols.address_of("Main.start"), Some((0x00, 0x0150)));
+        assert_eq!(symbols.label(None, 0x0150), Some("Main"));
+        // a label defined in two banks resolves to its first definition
+        assert_eq!(symbols.address_of("BankedRoutine"), Some((0x01, 0x4000)));
+    }
+
+    #[test]
+    fn looks_up_banked_labels_in_the_mapped_bank() {
+        let symbols = SymbolTable::parse(SYMBOLS).unwrap();
+
+        assert_eq!(symbols.label(Some(1), 0x4000), Some("BankedRoutine"));
+        assert_eq!(symbols.label(Some(2), 0x4000), Some("OtherRoutine"));
+        assert_eq!(symbols.label(Some(3), 0x4000), None);
+        assert_eq!(symbols.label(None, 0x4000), Some("BankedRoutine"));
+        // the bank only matters for the switchable area
+        assert_eq!(symbols.label(Some(2), 0x0160), Some("Main.loop"));
+        assert_eq!(
+            symbols.format_address(Some(2), 0x4010),
+            "0x4010 (OtherRoutine+16)"
+        );
+    }
+
+    #[test]
+    fn nearest_stays_within_the_memory_area() {
+        let symbols = SymbolTable::parse(SYMBOLS).unwrap();
+
+        assert_eq!(symbols.nearest(None, 0x0165), Some(("Main.loop", 5)));
+        assert_eq!(symbols.nearest(None, 0x3fff), Some(("Main.loop", 0x3e9f)));
+        assert_eq!(
+            symbols.nearest(Some(1), 0x7fff),
+            Some(("BankedRoutine", 0x3fff))
+        );
+        assert_eq!(symbols.nearest(Some(3), 0x4000), None);
+        assert_eq!(symbols.nearest(None, 0x8000), None);
+        assert_eq!(symbols.nearest(None, 0xc001), Some(("wCounter", 1)));
+        assert_eq!(symbols.nearest(None, 0xe000), None);
+        assert_eq!(symbols.format_address(None, 0x0100), "0x0100");
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for content in ["Main", "0150 Main", "00:zz50 Main", "xx:0150 Main"] {
+            assert!(SymbolTable::parse(content).is_err(), "'{}' parsed", content);
+        }
+        let symbols = SymbolTable::parse("00:0150 Main\n\n   ; only a comment\n").unwrap();
+        assert_eq!(symbols.len(), 1);
+    }
+}
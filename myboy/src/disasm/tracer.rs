@@ -0,0 +1,374 @@
+use std::collections::BTreeMap;
+
+use mygbcartridge::cartridge::Cartridge;
+
+use crate::cpu::{
+    addressing_mode::AddressingMode,
+    instruction::{Instruction, InstructionType},
+    register_set::ByteRegister,
+};
+
+use super::disassembler::{DisassembledInstruction, Disassembler};
+
+pub(crate) const BANK_SIZE: usize = 0x4000;
+
+const RST_VECTORS: [u16; 8] = [0x00, 0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38];
+
+const INTERRUPT_VECTORS: [(u16, &str); 5] = [
+    (0x0040, "VBlankInterrupt"),
+    (0x0048, "LCDStatInterrupt"),
+    (0x0050, "TimerInterrupt"),
+    (0x0058, "SerialInterrupt"),
+    (0x0060, "JoypadInterrupt"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ByteUsage {
+    Unknown,
+    InstructionStart,
+    /// Any byte following the first byte of an instruction or a jump table entry
+    Operand,
+    JumpTableEntry,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Relative,
+    Jump,
+    Call,
+    JumpTable,
+    Named,
+}
+
+/**
+ * The result of tracing the control flow through a ROM:
+ * which bytes are code, and the labels for everything that is jumped to.
+ */
+pub(crate) struct RomAnalysis {
+    /// One entry per byte, per ROM bank
+    pub(crate) usage: Vec<Vec<ByteUsage>>,
+    /// Labels by (bank, CPU address)
+    pub(crate) labels: BTreeMap<(u16, u16), String>,
+}
+
+impl RomAnalysis {
+    pub(crate) fn usage_at(&self, bank: u16, address: u16) -> ByteUsage {
+        self.usage[bank as usize][address as usize % BANK_SIZE]
+    }
+
+    /**
+     * Whether a label for this address can be emitted, which is not
+     * the case if the address points into the middle of an instruction.
+     */
+    pub(crate) fn is_boundary(&self, bank: u16, address: u16) -> bool {
+        self.usage_at(bank, address) != ByteUsage::Operand
+    }
+
+    pub(crate) fn label(&self, bank: u16, address: u16) -> Option<&String> {
+        match self.is_boundary(bank, address) {
+            true => self.labels.get(&(bank, address)),
+            false => None,
+        }
+    }
+}
+
+struct PendingPath {
+    bank: u16,
+    address: u16,
+    /// The bank we believe is mapped to 0x4000-0x7FFF while this path runs
+    mapped_bank: Option<u16>,
+}
+
+/**
+ * Follows the control flow from the entry points of a ROM to separate code from data.
+ *
+ * Jumps into the switchable area from bank 0 are only followed if the mapped bank
+ * is known, either because the ROM has just one switchable bank, or because
+ * the traced code selected it with `ld a, n` / `ld [$2000-$3FFF], a` before.
+ */
+pub(crate) struct CodeTracer<'a> {
+    cartridge: &'a Cartridge,
+    bank_count: u16,
+    usage: Vec<Vec<ByteUsage>>,
+    labels: BTreeMap<(u16, u16), (LabelKind, String)>,
+    pending: Vec<PendingPath>,
+}
+
+impl<'a> CodeTracer<'a> {
+    pub(crate) fn new(cartridge: &'a Cartridge) -> CodeTracer<'a> {
+        let bank_count = (cartridge.size().div_ceil(BANK_SIZE) as u16).max(1);
+
+        let mut tracer = CodeTracer {
+            cartridge,
+            bank_count,
+            usage: vec![vec![ByteUsage::Unknown; BANK_SIZE]; bank_count as usize],
+            labels: BTreeMap::new(),
+            pending: Vec::new(),
+        };
+
+        tracer.add_entry_point(0, 0x0100, Some("EntryPoint".to_string()));
+        for vector in RST_VECTORS {
+            tracer.add_entry_point(0, vector, Some(format!("Rst_{:02x}", vector)));
+        }
+        for (vector, name) in INTERRUPT_VECTORS {
+            tracer.add_entry_point(0, vector, Some(name.to_string()));
+        }
+
+        tracer
+    }
+
+    pub(crate) fn bank_count(&self) -> u16 {
+        self.bank_count
+    }
+
+    pub(crate) fn add_entry_point(&mut self, bank: u16, address: u16, name: Option<String>) {
+        let name = name.unwrap_or_else(|| Self::label_name(LabelKind::Jump, bank, address));
+        self.add_label(bank, address, LabelKind::Named, name);
+        self.pending.push(PendingPath {
+            bank,
+            address,
+            mapped_bank: (bank != 0).then_some(bank),
+        });
+    }
+
+    /**
+     * Marks `count` little endian words at `address` as a table of jump targets
+     * and follows each of them.
+     */
+    pub(crate) fn add_jump_table(&mut self, bank: u16, address: u16, count: u16) {
+        self.add_label(
+            bank,
+            address,
+            LabelKind::JumpTable,
+            Self::label_name(LabelKind::JumpTable, bank, address),
+        );
+
+        for entry in 0..count {
+            let entry_address = address.wrapping_add(entry * 2);
+            if !self.in_bank_window(bank, entry_address)
+                || !self.in_bank_window(bank, entry_address.wrapping_add(1))
+            {
+                break;
+            }
+
+            let index = entry_address as usize % BANK_SIZE;
+            let usage = &mut self.usage[bank as usize];
+            if usage[index] != ByteUsage::Unknown || usage[index + 1] != ByteUsage::Unknown {
+                break;
+            }
+            usage[index] = ByteUsage::JumpTableEntry;
+            usage[index + 1] = ByteUsage::Operand;
+
+            let target = u16::from_le_bytes([
+                self.cartridge.read_banked_byte(bank, entry_address),
+                self.cartridge.read_banked_byte(bank, entry_address + 1),
+            ]);
+            self.follow(bank, None, target, LabelKind::Jump);
+        }
+    }
+
+    pub(crate) fn run(mut self) -> RomAnalysis {
+        while let Some(path) = self.pending.pop() {
+            self.trace(path);
+        }
+
+        RomAnalysis {
+            usage: self.usage,
+            labels: self
+                .labels
+                .into_iter()
+                .map(|(key, (_, name))| (key, name))
+                .collect(),
+        }
+    }
+
+    fn trace(&mut self, path: PendingPath) {
+        let PendingPath {
+            bank,
+            mut address,
+            mut mapped_bank,
+        } = path;
+        if bank >= self.bank_count {
+            return;
+        }
+
+        let disassembler = Disassembler::new(self.cartridge, bank);
+        // the value of A, as long as it is known from an immediate load
+        let mut accumulator: Option<u8> = None;
+
+        loop {
+            if !self.in_bank_window(bank, address)
+                || self.usage_at(bank, address) != ByteUsage::Unknown
+            {
+                return;
+            }
+
+            let disassembled = disassembler.disassemble_at(address);
+            let Some(instruction) = disassembled.instruction else {
+                return;
+            };
+
+            let size = match instruction.instruction_type {
+                // rgbasm always emits STOP with its padding byte
+                InstructionType::Stop => {
+                    match self
+                        .cartridge
+                        .read_banked_byte(bank, address.wrapping_add(1))
+                    {
+                        0x00 => 2,
+                        _ => return,
+                    }
+                }
+                _ => disassembled.size(),
+            };
+
+            if !self.claim(bank, address, size) {
+                return;
+            }
+
+            if let Some(target) = disassembled.jump_target {
+                let kind = match instruction.instruction_type {
+                    InstructionType::Call | InstructionType::Reset => LabelKind::Call,
+                    InstructionType::JumpRelative => LabelKind::Relative,
+                    _ => LabelKind::Jump,
+                };
+                self.follow(bank, mapped_bank, target, kind);
+            }
+
+            if bank == 0
+                && let Some(selected) =
+                    Self::selected_bank(&instruction, &disassembled, &mut accumulator)
+            {
+                mapped_bank = Some((selected % self.bank_count).max(1));
+            }
+
+            if Self::ends_path(&instruction) {
+                return;
+            }
+            address = address.wrapping_add(size);
+        }
+    }
+
+    /**
+     * Keeps track of immediate loads to A and returns the bank number
+     * if the instruction writes A to the MBC's ROM bank register.
+     */
+    fn selected_bank(
+        instruction: &Instruction,
+        disassembled: &DisassembledInstruction,
+        accumulator: &mut Option<u8>,
+    ) -> Option<u16> {
+        match (instruction.target, instruction.source) {
+            (
+                Some(AddressingMode::ByteRegister(ByteRegister::A)),
+                Some(AddressingMode::ImmediateByte),
+            ) if matches!(instruction.instruction_type, InstructionType::LoadByte) => {
+                *accumulator = Some(disassembled.immediate_byte());
+                None
+            }
+            (
+                Some(AddressingMode::ImmediatePointer),
+                Some(AddressingMode::ByteRegister(ByteRegister::A)),
+            ) if (0x2000..=0x3FFF).contains(&disassembled.immediate_word()) => {
+                accumulator.map(|value| value as u16)
+            }
+            (Some(AddressingMode::ByteRegister(ByteRegister::A)), _) => {
+                *accumulator = None;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn ends_path(instruction: &Instruction) -> bool {
+        match instruction.instruction_type {
+            InstructionType::Jump | InstructionType::JumpRelative | InstructionType::Return => {
+                instruction.condition.is_none()
+            }
+            InstructionType::ReturnInterrupt => true,
+            _ => false,
+        }
+    }
+
+    fn follow(&mut self, bank: u16, mapped_bank: Option<u16>, target: u16, kind: LabelKind) {
+        let (target_bank, target_mapped_bank) = match target {
+            0x0000..=0x3FFF if bank == 0 => (0, mapped_bank),
+            0x0000..=0x3FFF => (0, Some(bank)),
+            0x4000..=0x7FFF if bank != 0 => (bank, Some(bank)),
+            0x4000..=0x7FFF => {
+                let guessed_bank = match mapped_bank {
+                    Some(mapped_bank) => mapped_bank,
+                    None if self.bank_count == 2 => 1,
+                    None => return,
+                };
+                (guessed_bank, Some(guessed_bank))
+            }
+            // RAM, there is nothing we could trace
+            _ => return,
+        };
+
+        self.add_label(
+            target_bank,
+            target,
+            kind,
+            Self::label_name(kind, target_bank, target),
+        );
+        self.pending.push(PendingPath {
+            bank: target_bank,
+            address: target,
+            mapped_bank: target_mapped_bank,
+        });
+    }
+
+    fn add_label(&mut self, bank: u16, address: u16, kind: LabelKind, name: String) {
+        let label = self
+            .labels
+            .entry((bank, address))
+            .or_insert((kind, name.clone()));
+        if label.0 < kind {
+            *label = (kind, name);
+        }
+    }
+
+    fn label_name(kind: LabelKind, bank: u16, address: u16) -> String {
+        let prefix = match kind {
+            LabelKind::Relative => "jr",
+            LabelKind::Jump | LabelKind::Named => "Jump",
+            LabelKind::Call => "Call",
+            LabelKind::JumpTable => "JumpTable",
+        };
+        format!("{}_{:03x}_{:04x}", prefix, bank, address)
+    }
+
+    /// Marks the bytes of an instruction as code, if none of them is already in use.
+    fn claim(&mut self, bank: u16, address: u16, size: u16) -> bool {
+        let last_address = address.wrapping_add(size - 1);
+        if !self.in_bank_window(bank, last_address) || last_address < address {
+            return false;
+        }
+
+        let start = address as usize % BANK_SIZE;
+        let usage = &mut self.usage[bank as usize][start..start + size as usize];
+        if usage.iter().any(|usage| *usage != ByteUsage::Unknown) {
+            return false;
+        }
+
+        usage[0] = ByteUsage::InstructionStart;
+        usage[1..].fill(ByteUsage::Operand);
+        true
+    }
+
+    fn usage_at(&self, bank: u16, address: u16) -> ByteUsage {
+        self.usage[bank as usize][address as usize % BANK_SIZE]
+    }
+
+    fn in_bank_window(&self, bank: u16, address: u16) -> bool {
+        let window = match bank {
+            0 => 0x0000..=0x3FFF,
+            _ => 0x4000..=0x7FFF,
+        };
+        let offset = bank as usize * BANK_SIZE + address as usize % BANK_SIZE;
+
+        window.contains(&address) && offset < self.cartridge.size()
+    }
+}
@@ -1,3 +1,4 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum InterruptType {
     VBlank = 0x01,
     LCDStat = 0x02,
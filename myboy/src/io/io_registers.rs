@@ -1,8 +1,12 @@
-use crate::memory::generic_memory::{GenericMemory, OffsetMemory, ReadableMemory, WritableMemory};
+use crate::{
+    device::save_state::{SaveState, StateReader, StateWriter},
+    memory::generic_memory::{GenericMemory, OffsetMemory, ReadableMemory, WritableMemory},
+};
 
 use super::{
     ie_register::IERegister,
     if_register::{IFRegister, InterruptType},
+    joypad::Joypad,
     lcdc::LCDCRegister,
     // lcdpos::LCDPosRegister,
     timers::Timers,
@@ -16,6 +20,7 @@ pub struct IORegisters {
     pub lcdc_register: LCDCRegister,
     // pub lcdpos_register: LCDPosRegister,
     pub timers: Timers,
+    pub joypad: Joypad,
 }
 
 impl OffsetMemory for IORegisters {
@@ -42,6 +47,26 @@ impl GenericMemory<256> for IORegisters {
     }
 }
 
+impl SaveState for IORegisters {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.data);
+        writer.u8(self.ie_register.0);
+        writer.u8(self.if_register.0);
+        writer.u8(self.lcdc_register.0);
+        self.timers.save(writer);
+        self.joypad.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        reader.bytes(&mut self.data)?;
+        self.ie_register.0 = reader.u8()?;
+        self.if_register.0 = reader.u8()?;
+        self.lcdc_register.0 = reader.u8()?;
+        self.timers.load(reader)?;
+        self.joypad.load(reader)
+    }
+}
+
 impl IORegisters {
     pub fn new() -> IORegisters {
         let data = Self::default_data();
@@ -51,6 +76,7 @@ impl IORegisters {
         let lcdc_register = LCDCRegister::new();
         // let lcdpos_register = LCDPosRegister::new();
         let timers = Timers::new();
+        let joypad = Joypad::new();
 
         IORegisters {
             data,
@@ -59,6 +85,7 @@ impl IORegisters {
             lcdc_register,
             // lcdpos_register,
             timers,
+            joypad,
         }
     }
 
@@ -117,6 +144,7 @@ impl IORegisters {
     pub fn read_byte(&self, address: u16) -> u8 {
         match address {
             // TODO: ff01 and ff02 are the serial registers
+            0xff00 => return self.joypad.read_byte(),
             0xff04 | 0xff05 | 0xff06 | 0xff07 => return self.timers.read_byte(address),
             0xff40 => return self.lcdc_register.0,
             0xff0f => return self.if_register.read_byte(),
@@ -135,6 +163,7 @@ impl IORegisters {
         }
         match address {
             // TODO: ff01 and ff02 are the serial registers
+            0xff00 => return self.joypad.write_byte(value),
             0xff04 | 0xff05 | 0xff06 | 0xff07 => return self.timers.write_byte(address, value),
             0xff40 => self.lcdc_register.0 = value,
             0xff0f => return self.if_register.write_byte(value),
@@ -151,6 +180,22 @@ impl IORegisters {
         }
     }
 
+    /**
+     * Sets a register to `value` like it was saved, without the side effects of a write
+     * (writing DIV resets it, LY cannot be written at all).
+     */
+    pub(crate) fn restore_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0xff00 => return self.joypad.write_byte(value),
+            0xff04 => self.timers.sys = (value as u16) << 8,
+            0xff05..=0xff07 => self.timers.write_byte(address, value),
+            0xff40 => self.lcdc_register.0 = value,
+            0xff0f => self.if_register.0 = value,
+            0xffff => self.ie_register.0 = value,
+            _ => self.data[(address - self.offset()) as usize] = value,
+        }
+    }
+
     pub fn default_data() -> [u8; 256] {
         let mut data = [0; 256];
         data[0x00] = 0xcf;
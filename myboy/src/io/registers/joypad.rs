@@ -0,0 +1,85 @@
+use std::cell::Cell;
+
+use crate::device::save_state::{SaveState, StateReader, StateWriter};
+
+/**
+ * The buttons, by their bit in `Joypad::pressed` and in the inputs of movies.
+ * The lower four are read with the d-pad selected, the upper four with the buttons selected.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    Right = 0x01,
+    Left = 0x02,
+    Up = 0x04,
+    Down = 0x08,
+    A = 0x10,
+    B = 0x20,
+    Select = 0x40,
+    Start = 0x80,
+}
+
+/**
+ * The joypad register at FF00. The game selects the d-pad or the buttons with bits 4 and 5
+ * and reads the selected ones in the lower nibble, a pressed button reads as 0.
+ */
+pub struct Joypad {
+    /// Bits 4 and 5 of FF00, a 0 selects
+    select: u8,
+    /// The buttons held down, one bit per `Button`
+    pub(crate) pressed: u8,
+    /// Whether the CPU read the register since this was last reset, for counting lag frames
+    pub(crate) polled: Cell<bool>,
+}
+
+impl Joypad {
+    pub fn new() -> Joypad {
+        Joypad {
+            select: 0x00,
+            pressed: 0x00,
+            polled: Cell::new(false),
+        }
+    }
+
+    pub fn read_byte(&self) -> u8 {
+        0xc0 | self.select | self.lines()
+    }
+
+    pub fn write_byte(&mut self, value: u8) {
+        self.select = value & 0x30;
+    }
+
+    /**
+     * Holds down exactly the buttons in `pressed`. `true` if one of the selected lines
+     * went low, which requests the joypad interrupt.
+     */
+    pub fn set_pressed(&mut self, pressed: u8) -> bool {
+        let before = self.lines();
+        self.pressed = pressed;
+        before & !self.lines() != 0
+    }
+
+    /// The lower nibble, 0 for the selected buttons held down
+    fn lines(&self) -> u8 {
+        let mut lines = 0x0f;
+        if self.select & 0x10 == 0 {
+            lines &= !self.pressed & 0x0f;
+        }
+        if self.select & 0x20 == 0 {
+            lines &= !(self.pressed >> 4);
+        }
+        lines
+    }
+}
+
+impl SaveState for Joypad {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(self.select);
+        writer.u8(self.pressed);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.select = reader.u8()? & 0x30;
+        self.pressed = reader.u8()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+pub mod joypad;
 pub mod lcdc;
 pub mod lcdpos;
 pub mod timers;
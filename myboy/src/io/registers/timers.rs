@@ -1,4 +1,7 @@
-use crate::memory::generic_memory::OffsetMemory;
+use crate::{
+    device::save_state::{SaveState, StateReader, StateWriter},
+    memory::generic_memory::OffsetMemory,
+};
 
 pub struct Timers {
     pub sys: u16,
@@ -52,7 +55,7 @@ impl Timers {
                 self.tma = value;
             }
             0xff07 => {
-                self.tac |= value & 0b0000_1111; // only the lower 3 bits are used
+                self.tac = 0xf8 | (value & 0b0000_0111); // only the lower 3 bits are used
             }
             _ => panic!("Invalid address for Timers: {:#X}", address),
         }
@@ -112,3 +115,43 @@ impl Timers {
         false
     }
 }
+
+impl SaveState for Timers {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u16(self.sys);
+        writer.u16(self.last_sys);
+        writer.u8(self.tima);
+        writer.u8(self.tma);
+        writer.u8(self.tac);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.sys = reader.u16()?;
+        self.last_sys = reader.u16()?;
+        self.tima = reader.u8()?;
+        self.tma = reader.u8()?;
+        self.tac = reader.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Timers;
+
+    #[test]
+    fn tac_reads_back_the_lower_bits_with_the_others_set() {
+        let mut timers = Timers::new();
+        assert_eq!(timers.read_byte(0xff07), 0xf8);
+
+        timers.write_byte(0xff07, 0x07);
+        assert_eq!(timers.read_byte(0xff07), 0xff);
+        // a write replaces the bits written before
+        timers.write_byte(0xff07, 0x04);
+        assert_eq!(timers.read_byte(0xff07), 0xfc);
+        timers.write_byte(0xff07, 0x00);
+        assert_eq!(timers.read_byte(0xff07), 0xf8);
+        timers.write_byte(0xff07, 0xf5);
+        assert_eq!(timers.read_byte(0xff07), 0xfd);
+    }
+}
@@ -0,0 +1,17 @@
+pub mod cli;
+mod cpu;
+mod debugger;
+mod device;
+mod disasm;
+pub(crate) mod io;
+mod logging;
+mod memory;
+mod ppu;
+mod screen;
+mod testing;
+mod ui;
+
+use device::device::Device;
+use ppu::ppu::PPU;
+
+pub use testing::{runner::Outcome, screenshot::ScreenshotTest};
@@ -22,13 +22,19 @@ pub enum LogLevel {
     Error,
 }
 
-pub(crate) trait Logger {
+/// Sent to the emulator thread with the device
+pub(crate) trait Logger: Send {
     fn log(&mut self, level: LogLevel, log_type: Log);
 
     fn set_disabled_outputs(&mut self, _outputs: Vec<LogOutput>) {
         // Default implementation does nothing
     }
 
+    /// Writes out anything the logger buffered so far
+    fn flush(&mut self) {
+        // Default implementation does nothing
+    }
+
     fn info(&mut self, log: Log) {
         self.log(LogLevel::Info, log)
     }
@@ -72,6 +78,13 @@ impl Logger for ConsoleLogger {
     }
 }
 
+/// Drops everything, for running again what was logged before
+pub struct SilentLogger;
+
+impl Logger for SilentLogger {
+    fn log(&mut self, _level: LogLevel, _log_type: Log) {}
+}
+
 #[derive(Default)]
 pub struct InMemoryLogger(Vec<(LogLevel, Log)>);
 
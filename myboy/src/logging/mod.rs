@@ -1 +1,3 @@
 pub mod log;
+pub mod trace;
+pub mod trace_diff;
@@ -0,0 +1,139 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    ops::RangeInclusive,
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{cpu::CPUState, disasm::symbols::SymbolTable};
+
+use super::log::{Log, LogLevel, LogOutput, Logger};
+
+/**
+ * Which CPU states end up in the trace.
+ *
+ * Tracing starts with the first instruction if there is no start trigger,
+ * and once the stop trigger was hit, nothing is traced anymore.
+ */
+#[derive(Default, Clone, Debug)]
+pub struct TraceFilter {
+    pub address_range: Option<RangeInclusive<u16>>,
+    /// Only trace code running from this ROM bank
+    pub bank: Option<u16>,
+    pub start_pc: Option<u16>,
+    pub stop_pc: Option<u16>,
+}
+
+impl TraceFilter {
+    fn matches(&self, state: &CPUState) -> bool {
+        let pc = *state.register_set.pc();
+
+        let in_range = match &self.address_range {
+            Some(range) => range.contains(&pc),
+            None => true,
+        };
+        let in_bank = match self.bank {
+            Some(bank) => state.bank == Some(bank),
+            None => true,
+        };
+
+        in_range && in_bank
+    }
+}
+
+/**
+ * Writes the CPU state before every instruction to a file, in the format
+ * used by Gameboy Doctor: `A:00 F:11 B:22 ... PC:0100 PCMEM:00,C3,13,02`.
+ *
+ * With `labels`, a `Label:` line is written before each instruction at a label,
+ * which Gameboy Doctor does not understand.
+ *
+ * All other logs are passed on to the wrapped logger.
+ */
+pub struct TraceLogger {
+    inner: Box<dyn Logger>,
+    writer: BufWriter<File>,
+    filter: TraceFilter,
+    labels: Option<Arc<SymbolTable>>,
+    tracing: bool,
+    stopped: bool,
+}
+
+impl TraceLogger {
+    pub(crate) fn new(
+        path: &Path,
+        filter: TraceFilter,
+        labels: Option<Arc<SymbolTable>>,
+        inner: Box<dyn Logger>,
+    ) -> Result<TraceLogger, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create trace file {}: {}", path.display(), e))?;
+
+        Ok(TraceLogger {
+            inner,
+            writer: BufWriter::new(file),
+            tracing: filter.start_pc.is_none(),
+            filter,
+            labels,
+            stopped: false,
+        })
+    }
+
+    fn trace(&mut self, state: CPUState) {
+        if self.stopped {
+            return;
+        }
+
+        let pc = *state.register_set.pc();
+        if !self.tracing && self.filter.start_pc == Some(pc) {
+            self.tracing = true;
+        }
+        if self.tracing && self.filter.stop_pc == Some(pc) {
+            self.tracing = false;
+            self.stopped = true;
+            self.flush();
+            return;
+        }
+
+        if self.tracing && self.filter.matches(&state) {
+            let label = self
+                .labels
+                .as_ref()
+                .and_then(|labels| labels.label(state.bank, pc));
+            let result = match label {
+                Some(label) => writeln!(self.writer, "{}:\n{:?}", label, state),
+                None => writeln!(self.writer, "{:?}", state),
+            };
+            if let Err(e) = result {
+                self.stopped = true;
+                self.inner
+                    .error(Log::Msg(format!("Failed to write trace: {}", e)));
+            }
+        }
+    }
+}
+
+impl Logger for TraceLogger {
+    fn log(&mut self, level: LogLevel, log_type: Log) {
+        match log_type {
+            Log::CPUState(state) => self.trace(state),
+            log_type => self.inner.log(level, log_type),
+        }
+    }
+
+    fn set_disabled_outputs(&mut self, outputs: Vec<LogOutput>) {
+        self.inner.set_disabled_outputs(outputs);
+    }
+
+    fn flush(&mut self) {
+        let _ = self.writer.flush();
+        self.inner.flush();
+    }
+}
+
+impl Drop for TraceLogger {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs::File,
+    io::{BufRead, BufReader, Lines},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    cpu::CPUState,
+    disasm::{rgbds::format_state_instruction, symbols::SymbolTable},
+};
+
+use super::log::{Log, LogLevel, LogOutput, Logger};
+
+const HIGHLIGHT_EXPECTED: &str = "\x1b[1;32m";
+const HIGHLIGHT_ACTUAL: &str = "\x1b[1;31m";
+const HIGHLIGHT_RESET: &str = "\x1b[0m";
+
+pub enum TraceDiffResult {
+    /// The reference ended without a difference, after this many lines
+    Matched(usize),
+    Mismatch(TraceMismatch),
+    Failed(String),
+}
+
+pub struct TraceMismatch {
+    /// The line of the reference file, starting at 1
+    pub line_number: usize,
+    /// The matching lines right before the mismatch
+    pub context: Vec<String>,
+    pub expected: String,
+    pub actual: CPUState,
+    /// The state before the instruction which caused the difference
+    pub previous_state: Option<CPUState>,
+}
+
+/**
+ * Compares the CPU states of a running device with a reference trace
+ * in the Gameboy Doctor format, line by line.
+ *
+ * The comparison is finished once `result` is set, the device should be stopped then.
+ */
+pub struct TraceComparison {
+    reference: Lines<BufReader<File>>,
+    line_number: usize,
+    context: VecDeque<String>,
+    context_size: usize,
+    previous_state: Option<CPUState>,
+    pub result: Option<TraceDiffResult>,
+}
+
+impl TraceComparison {
+    pub fn new(reference: &Path, context_size: usize) -> Result<TraceComparison, String> {
+        let file = File::open(reference).map_err(|e| {
+            format!(
+                "Failed to open reference trace {}: {}",
+                reference.display(),
+                e
+            )
+        })?;
+
+        Ok(TraceComparison {
+            reference: BufReader::new(file).lines(),
+            line_number: 0,
+            context: VecDeque::with_capacity(context_size),
+            context_size,
+            previous_state: None,
+            result: None,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.result.is_some()
+    }
+
+    fn compare(&mut self, state: CPUState) {
+        if self.is_finished() {
+            return;
+        }
+
+        let expected = match self.reference.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => {
+                self.result = Some(TraceDiffResult::Failed(format!(
+                    "Failed to read line {} of the reference trace: {}",
+                    self.line_number + 1,
+                    e
+                )));
+                return;
+            }
+            None => {
+                self.result = Some(TraceDiffResult::Matched(self.line_number));
+                return;
+            }
+        };
+        self.line_number += 1;
+
+        let actual = format!("{:?}", state);
+        if !expected.trim().eq_ignore_ascii_case(&actual) {
+            self.result = Some(TraceDiffResult::Mismatch(TraceMismatch {
+                line_number: self.line_number,
+                context: self.context.drain(..).collect(),
+                expected: expected.trim().to_string(),
+                actual: state,
+                previous_state: self.previous_state,
+            }));
+            return;
+        }
+
+        if self.context_size > 0 {
+            if self.context.len() == self.context_size {
+                self.context.pop_front();
+            }
+            self.context.push_back(actual);
+        }
+        self.previous_state = Some(state);
+    }
+}
+
+/**
+ * Feeds the CPU states into a shared `TraceComparison`, all other logs
+ * are passed on to the wrapped logger.
+ */
+pub struct TraceDiffLogger {
+    comparison: Arc<Mutex<TraceComparison>>,
+    inner: Box<dyn Logger>,
+}
+
+impl TraceDiffLogger {
+    pub fn new(comparison: Arc<Mutex<TraceComparison>>, inner: Box<dyn Logger>) -> Self {
+        TraceDiffLogger { comparison, inner }
+    }
+}
+
+impl Logger for TraceDiffLogger {
+    fn log(&mut self, level: LogLevel, log_type: Log) {
+        match log_type {
+            Log::CPUState(state) => self.comparison.lock().unwrap().compare(state),
+            log_type => self.inner.log(level, log_type),
+        }
+    }
+
+    fn set_disabled_outputs(&mut self, outputs: Vec<LogOutput>) {
+        self.inner.set_disabled_outputs(outputs);
+    }
+
+    fn flush(&mut self) {
+        self.inner.flush();
+    }
+}
+
+/// Splits a trace line into its `NAME:VALUE` fields.
+fn fields(line: &str) -> Vec<(&str, &str)> {
+    line.split_whitespace()
+        .map(|field| field.split_once(':').unwrap_or((field, "")))
+        .collect()
+}
+
+/// Renders the line with the fields whose value differs in the other line highlighted.
+fn highlight(line: &str, other: &str, color: &str) -> String {
+    let other_fields = fields(other);
+
+    fields(line)
+        .iter()
+        .map(|(name, value)| {
+            let differs = other_fields
+                .iter()
+                .find(|(other_name, _)| other_name.eq_ignore_ascii_case(name))
+                .is_none_or(|(_, other_value)| !other_value.eq_ignore_ascii_case(value));
+            match differs {
+                true => format!("{}{}:{}{}", color, name, value, HIGHLIGHT_RESET),
+                false => format!("{}:{}", name, value),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+impl TraceMismatch {
+    pub(crate) fn report(&self, symbols: &SymbolTable) -> String {
+        let actual = format!("{:?}", self.actual);
+        let mut report = String::new();
+
+        let _ = writeln!(
+            report,
+            "Mismatch at line {} of the reference trace",
+            self.line_number
+        );
+        let _ = writeln!(report);
+        for (index, line) in self.context.iter().enumerate() {
+            let line_number = self.line_number - self.context.len() + index;
+            let _ = writeln!(report, "  {:>8}  {}", line_number, line);
+        }
+        let _ = writeln!(
+            report,
+            "- {:>8}  {}",
+            self.line_number,
+            highlight(&self.expected, &actual, HIGHLIGHT_EXPECTED)
+        );
+        let _ = writeln!(
+            report,
+            "+ {:>8}  {}",
+            self.line_number,
+            highlight(&actual, &self.expected, HIGHLIGHT_ACTUAL)
+        );
+
+        let _ = writeln!(report);
+        let _ = writeln!(report, "Differing registers:");
+        let actual_fields = fields(&actual);
+        for (name, expected_value) in fields(&self.expected) {
+            let actual_value = actual_fields
+                .iter()
+                .find(|(actual_name, _)| actual_name.eq_ignore_ascii_case(name))
+                .map(|(_, value)| *value);
+            if actual_value.is_none_or(|value| !value.eq_ignore_ascii_case(expected_value)) {
+                let _ = writeln!(
+                    report,
+                    "  {:<6} expected {}, got {}",
+                    name,
+                    expected_value,
+                    actual_value.unwrap_or("nothing")
+                );
+            }
+        }
+
+        let _ = writeln!(report);
+        match &self.previous_state {
+            Some(previous_state) => {
+                let _ = writeln!(report, "Last executed instruction:");
+                let _ = writeln!(
+                    report,
+                    "  {}",
+                    format_state_instruction(previous_state, symbols)
+                );
+            }
+            None => {
+                let _ = writeln!(report, "The initial state differs, next instruction:");
+                let _ = writeln!(
+                    report,
+                    "  {}",
+                    format_state_instruction(&self.actual, symbols)
+                );
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use crate::cpu::{ByteRegister, CPUState, RegisterSet, WordRegister};
+
+    use super::{HIGHLIGHT_ACTUAL, HIGHLIGHT_RESET, TraceComparison, TraceDiffResult, highlight};
+
+    fn state(a: u8, pc: u16) -> CPUState {
+        let mut register_set = RegisterSet::default();
+        register_set.set_b(ByteRegister::A, a);
+        register_set.set_w(WordRegister::PC, pc);
+        CPUState {
+            register_set,
+            bank: Some(0),
+            current_instruction_bytes: [0x00; 4],
+        }
+    }
+
+    fn reference(name: &str, states: &[CPUState]) -> PathBuf {
+        let path = env::temp_dir().join(format!("bricoboy-{}-{}.log", name, std::process::id()));
+        let lines = states
+            .iter()
+            .map(|state| format!("{:?}\n", state).to_lowercase())
+            .collect::<String>();
+        fs::write(&path, lines).unwrap();
+        path
+    }
+
+    #[test]
+    fn matches_until_the_reference_ends() {
+        let states = [state(0x01, 0x0100), state(0x02, 0x0101)];
+        let path = reference("trace-match", &states);
+        let mut comparison = TraceComparison::new(&path, 3).unwrap();
+
+        for state in states {
+            comparison.compare(state);
+            assert!(!comparison.is_finished());
+        }
+        comparison.compare(state(0x03, 0x0102));
+        assert!(matches!(
+            comparison.result,
+            Some(TraceDiffResult::Matched(2))
+        ));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reports_the_first_mismatch_with_its_context() {
+        let states = [
+            state(0x01, 0x0100),
+            state(0x02, 0x0101),
+            state(0x03, 0x0102),
+            state(0x04, 0x0103),
+        ];
+        let path = reference("trace-mismatch", &states);
+        let mut comparison = TraceComparison::new(&path, 2).unwrap();
+
+        for state in &states[..3] {
+            comparison.compare(*state);
+        }
+        comparison.compare(state(0xff, 0x0103));
+        // nothing is compared after the mismatch
+        comparison.compare(state(0x05, 0x0104));
+
+        let Some(TraceDiffResult::Mismatch(mismatch)) = comparison.result else {
+            panic!("The trace matched");
+        };
+        assert_eq!(mismatch.line_number, 4);
+        assert_eq!(
+            mismatch.context,
+            vec![format!("{:?}", states[1]), format!("{:?}", states[2])]
+        );
+        assert_eq!(mismatch.expected, format!("{:?}", states[3]).to_lowercase());
+        assert_eq!(*mismatch.actual.register_set.pc(), 0x0103);
+        assert_eq!(
+            mismatch
+                .previous_state
+                .map(|state| *state.register_set.pc()),
+            Some(0x0102)
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn highlights_only_differing_fields() {
+        let highlighted = highlight("A:01 F:B0 PC:0100", "a:01 F:80 PC:0100", HIGHLIGHT_ACTUAL);
+        assert_eq!(
+            highlighted,
+            format!("A:01 {}F:B0{} PC:0100", HIGHLIGHT_ACTUAL, HIGHLIGHT_RESET)
+        );
+
+        // a field missing in the other line differs as well
+        let highlighted = highlight("A:01 SP:FFFE", "A:01", HIGHLIGHT_ACTUAL);
+        assert_eq!(
+            highlighted,
+            format!("A:01 {}SP:FFFE{}", HIGHLIGHT_ACTUAL, HIGHLIGHT_RESET)
+        );
+    }
+}
@@ -63,3 +63,13 @@ pub trait GenericMemory<const SIZE: usize> {
     fn read_word(&self, address: u16) -> u16;
     fn write_word(&mut self, address: u16, value: u16);
 }
+
+/**
+ * A read-only view on the 16-bit address space, as seen by the CPU.
+ *
+ * This is what the instruction decoder works on, so it can be used
+ * on the live memory map as well as on a plain cartridge image.
+ */
+pub trait AddressSpace {
+    fn read_byte(&self, address: u16) -> u8;
+}
@@ -1,12 +1,15 @@
 use fixed_vec_deque::FixedVecDeque;
 
 use crate::{
-    device::mem_map::MemMap,
+    device::{
+        mem_map::MemMap,
+        save_state::{StateReader, StateWriter},
+    },
     io::{if_register::InterruptType, io_registers::IORegisters, lcdc::LCDCRegister},
     memory::generic_memory::GenericMemory,
 };
 
-use super::oam::{OAM, OAMEntry};
+use super::oam::OAM;
 
 enum RenderMode {
     Mode0,
@@ -31,18 +34,22 @@ impl TryFrom<u16> for BGMode {
     }
 }
 
-pub(crate) struct PPU<'a> {
+pub(crate) struct PPU {
     last_render_mode: RenderMode,
     current_line_cycle: u16,
     current_x_pos: u8,
     current_window_line: u8,
 
-    sprite_buffer: Vec<OAMEntry<'a>>,
+    /// The sprites found on the line, by their index in OAM
+    sprite_buffer: Vec<u8>,
     fifos: (FixedVecDeque<[u8; 16]>, FixedVecDeque<[u8; 16]>),
+
+    /// The number of frames completed, counted when entering VBlank
+    pub(crate) frame_count: u64,
 }
 
-impl<'a> PPU<'a> {
-    pub(crate) fn new() -> PPU<'a> {
+impl PPU {
+    pub(crate) fn new() -> PPU {
         PPU {
             last_render_mode: RenderMode::Mode1,
             current_line_cycle: 0,
@@ -52,6 +59,8 @@ impl<'a> PPU<'a> {
             sprite_buffer: Vec::with_capacity(10),
             // first is OBJ, second is BG/WIN
             fifos: (FixedVecDeque::new(), FixedVecDeque::new()),
+
+            frame_count: 0,
         }
     }
 
@@ -60,9 +69,73 @@ impl<'a> PPU<'a> {
         self.current_x_pos = 0;
         self.current_line_cycle = 0;
         io_registers.set_lcd_ly((io_registers.get_lcd_ly() + 1) % 154);
+        if io_registers.get_lcd_ly() == 144 {
+            self.frame_count += 1;
+        }
+    }
+
+    /// Writes the state, which may be in the middle of a line
+    pub(crate) fn save(&self, writer: &mut StateWriter) {
+        writer.u8(match self.last_render_mode {
+            RenderMode::Mode0 => 0,
+            RenderMode::Mode1 => 1,
+            RenderMode::Mode2 => 2,
+            RenderMode::Mode3 => 3,
+        });
+        writer.u16(self.current_line_cycle);
+        writer.u8(self.current_x_pos);
+        writer.u8(self.current_window_line);
+
+        writer.u8(self.sprite_buffer.len() as u8);
+        for index in &self.sprite_buffer {
+            writer.u8(*index);
+        }
+        for fifo in [&self.fifos.0, &self.fifos.1] {
+            writer.u8(fifo.len() as u8);
+            for pixel in fifo.iter() {
+                writer.u8(*pixel);
+            }
+        }
+
+        writer.u64(self.frame_count);
+    }
+
+    pub(crate) fn load(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.last_render_mode = match reader.u8()? {
+            0 => RenderMode::Mode0,
+            1 => RenderMode::Mode1,
+            2 => RenderMode::Mode2,
+            3 => RenderMode::Mode3,
+            mode => return Err(format!("Invalid PPU mode {} in the save state", mode)),
+        };
+        self.current_line_cycle = reader.u16()?;
+        self.current_x_pos = reader.u8()?;
+        self.current_window_line = reader.u8()?;
+
+        self.sprite_buffer.clear();
+        for _ in 0..reader.u8()? {
+            let index = reader.u8()?;
+            if index >= 40 {
+                return Err(format!("Invalid sprite {} in the save state", index));
+            }
+            self.sprite_buffer.push(index);
+        }
+        for fifo in [&mut self.fifos.0, &mut self.fifos.1] {
+            fifo.clear();
+            let length = reader.u8()? as usize;
+            if length > fifo.capacity() {
+                return Err(format!("Invalid FIFO length {} in the save state", length));
+            }
+            for _ in 0..length {
+                *fifo.push_back() = reader.u8()?;
+            }
+        }
+
+        self.frame_count = reader.u64()?;
+        Ok(())
     }
 
-    pub(crate) fn cycle(&mut self, mem_map: &'a mut MemMap, screen: &mut [u8]) {
+    pub(crate) fn cycle(&mut self, mem_map: &mut MemMap, screen: &mut [u8]) {
         let current_line = mem_map.io_registers.get_lcd_ly() as usize;
         // println!(
         //     "Current pos: {}, line: {} | current line cycle: {}",
@@ -102,7 +175,7 @@ impl<'a> PPU<'a> {
         self.current_line_cycle = self.current_line_cycle.wrapping_add(1)
     }
 
-    pub fn cycle_mode2(&mut self, oam: &'a OAM, lcdc: &LCDCRegister, current_line: u8) {
+    pub fn cycle_mode2(&mut self, oam: &OAM, lcdc: &LCDCRegister, current_line: u8) {
         if self.sprite_buffer.len() > 9 {
             // sprite buffer is full
             return;
@@ -124,7 +197,7 @@ impl<'a> PPU<'a> {
             return;
         }
 
-        self.sprite_buffer.push(sprite);
+        self.sprite_buffer.push(sprite_index as u8);
     }
 
     fn cycle_mode3(&mut self, mem_map: &mut MemMap, current_line: u8, screen: &mut [u8]) {
@@ -137,6 +210,7 @@ impl<'a> PPU<'a> {
     fn push_sprite_pixel(&mut self, mem_map: &mut MemMap, current_line: u8) {
         let ioregs = &mut mem_map.io_registers;
         let vram = &mem_map.video_ram;
+        let oam = &mem_map.object_attribute_memory;
         let lcdc = ioregs.get_lcdc_register();
 
         if !lcdc.obj_enabled() {
@@ -144,7 +218,11 @@ impl<'a> PPU<'a> {
             return;
         }
 
-        let sprite = self.sprite_buffer.iter().find(|sprite| {
+        let mut sprites = self
+            .sprite_buffer
+            .iter()
+            .map(|index| oam.get_sprite(*index as usize));
+        let sprite = sprites.find(|sprite| {
             if *sprite.x > (self.current_x_pos + 8) {
                 return false;
             }
@@ -234,7 +312,7 @@ impl<'a> PPU<'a> {
         }
     }
 
-    fn bpp_to_pixelrow(bpp_data: u16) -> [u8; 8] {
+    pub(crate) fn bpp_to_pixelrow(bpp_data: u16) -> [u8; 8] {
         let [lower_byte, higher_byte] = bpp_data.to_le_bytes();
 
         [
@@ -249,7 +327,7 @@ impl<'a> PPU<'a> {
         ]
     }
 
-    fn bpp_to_rgba(color: u8) -> (u8, u8, u8, u8) {
+    pub(crate) fn bpp_to_rgba(color: u8) -> (u8, u8, u8, u8) {
         match color {
             0 => (0xff, 0xff, 0xff, 0xff), // white
             1 => (0xab, 0xab, 0xab, 0xff), // black
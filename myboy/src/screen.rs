@@ -1,19 +1,56 @@
 use std::sync::Arc;
+use std::time::Instant;
 
 use pixels::{Error, Pixels, SurfaceTexture};
 use tao::dpi::LogicalSize;
-use tao::event::{Event, KeyEvent, WindowEvent};
+use tao::event::{ElementState, Event, KeyEvent, WindowEvent};
 use tao::event_loop::{ControlFlow, EventLoop};
-use tao::keyboard::KeyCode;
+use tao::keyboard::{KeyCode, ModifiersState};
 use tao::window::WindowBuilder;
 
 use crate::Device;
-use crate::ui::emulator_view::run_emulator;
+use crate::device::emulator::{Command, Emulator};
+use crate::device::pacing::FRAME_DURATION;
+use crate::device::rewind::{RewindBuffer, SNAPSHOT_FRAMES};
+use crate::io::joypad::Button;
+use crate::logging::log::Log;
 
 pub const WIDTH: u32 = 160;
 pub const HEIGHT: u32 = 144;
 
-pub fn open_gamescreen(mut device: Device<'static>) -> Result<(), Error> {
+const SLOT_KEYS: [KeyCode; 9] = [
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+];
+
+const BUTTON_KEYS: [(KeyCode, Button); 8] = [
+    (KeyCode::ArrowRight, Button::Right),
+    (KeyCode::ArrowLeft, Button::Left),
+    (KeyCode::ArrowUp, Button::Up),
+    (KeyCode::ArrowDown, Button::Down),
+    (KeyCode::KeyX, Button::A),
+    (KeyCode::KeyZ, Button::B),
+    (KeyCode::Space, Button::Select),
+    (KeyCode::Enter, Button::Start),
+];
+
+/**
+ * Opens the play window. The joypad is on the arrow keys, X and Z are A and B,
+ * space is select and enter is start.
+ *
+ * F1-F9 load the save state in slot 1-9, with shift they save it.
+ * While backspace is held the game goes back in time, it continues from there on release.
+ * F11 switches a movie between read-only and read-write, it is written when the window closes.
+ */
+pub fn open_gamescreen(mut device: Device) -> Result<(), Error> {
+    device.rewind = Some(RewindBuffer::default());
     let event_loop = EventLoop::new();
     let window = {
         let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
@@ -37,9 +74,22 @@ pub fn open_gamescreen(mut device: Device<'static>) -> Result<(), Error> {
         pixels
     };
 
-    let _ = run_emulator(&mut device);
+    let emulator = Emulator::start(device);
+    emulator.send(Command::Run);
+    let mut emulator = Some(emulator);
+    let mut modifiers = ModifiersState::empty();
+    let mut input = 0;
+    let mut rewinding = false;
+    let mut last_rewind = Instant::now();
+    // every step back goes back one snapshot, so it runs backwards as fast as forwards
+    let rewind_interval = FRAME_DURATION * SNAPSHOT_FRAMES as u32;
+    let mut title = String::new();
 
     event_loop.run(move |event, _, control_flow| {
+        // the events after closing the window
+        let Some(thread) = &emulator else {
+            return;
+        };
         match event {
             Event::WindowEvent { event, .. } => match event {
                 // Close events
@@ -52,9 +102,71 @@ pub fn open_gamescreen(mut device: Device<'static>) -> Result<(), Error> {
                         },
                     ..
                 } => {
+                    if let Some(emulator) = emulator.take() {
+                        close(emulator);
+                    }
                     *control_flow = ControlFlow::Exit;
                 }
 
+                WindowEvent::ModifiersChanged(state) => modifiers = state,
+
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            physical_key: KeyCode::Backspace,
+                            state,
+                            repeat: false,
+                            ..
+                        },
+                    ..
+                } => match state {
+                    ElementState::Pressed if !rewinding => {
+                        rewinding = true;
+                        thread.send(Command::Rewind);
+                        last_rewind = Instant::now();
+                    }
+                    ElementState::Released if rewinding => {
+                        rewinding = false;
+                        thread.send(Command::Run);
+                    }
+                    _ => {}
+                },
+
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            physical_key,
+                            state,
+                            repeat,
+                            ..
+                        },
+                    ..
+                } => {
+                    let button = BUTTON_KEYS
+                        .iter()
+                        .find(|(key, _)| *key == physical_key)
+                        .map(|(_, button)| *button as u8);
+                    if let Some(button) = button {
+                        // seen by the game from the next frame on
+                        match state {
+                            ElementState::Pressed => input |= button,
+                            _ => input &= !button,
+                        }
+                        thread.send(Command::SetInput(input));
+                    } else if state == ElementState::Pressed && !repeat {
+                        if let Some(slot) = SLOT_KEYS.iter().position(|key| *key == physical_key) {
+                            // done between two frames, so the state is not changed halfway
+                            let slot = slot as u8 + 1;
+                            thread.send(match modifiers.shift_key() {
+                                true => Command::SaveSlot(slot),
+                                false => Command::LoadSlot(slot),
+                            });
+                        } else if physical_key == KeyCode::F11 {
+                            thread.send(Command::ToggleReadOnly);
+                        }
+                    }
+                }
+
                 // Resize the window
                 WindowEvent::Resized(size) => {
                     if let Err(_err) = pixels.resize_surface(size.width, size.height) {
@@ -68,13 +180,26 @@ pub fn open_gamescreen(mut device: Device<'static>) -> Result<(), Error> {
 
             // Update internal state and request a redraw
             Event::MainEventsCleared => {
+                if rewinding && last_rewind.elapsed() >= rewind_interval {
+                    thread.send(Command::Rewind);
+                    last_rewind = Instant::now();
+                }
                 // world.update();
                 window.request_redraw();
             }
 
             // Draw the current frame
             Event::RedrawRequested(_) => {
-                device.draw_screen(pixels.frame_mut());
+                let frame = thread.frame();
+                pixels.frame_mut().copy_from_slice(&frame.pixels);
+                if let Some(movie_status) = &frame.movie_status {
+                    let status = format!("bricoboy - {}", movie_status);
+                    if status != title {
+                        window.set_title(&status);
+                        title = status;
+                    }
+                }
+                drop(frame);
 
                 if let Err(_) = pixels.render() {
                     *control_flow = ControlFlow::Exit;
@@ -87,3 +212,14 @@ pub fn open_gamescreen(mut device: Device<'static>) -> Result<(), Error> {
         }
     });
 }
+
+/// Stops the emulator thread and writes the movie
+fn close(emulator: Emulator) {
+    let Some(mut device) = emulator.stop() else {
+        return;
+    };
+    if let Err(e) = device.stop_movie() {
+        device.logger.error(Log::Msg(e));
+    }
+    device.logger.flush();
+}
@@ -0,0 +1,268 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// How a test ROM tells whether it passed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Check {
+    /// Whichever of the blargg serial output and the mooneye signature comes first
+    Auto,
+    /// Passed once the serial output contains `pass`, failed once it contains `fail`
+    Serial { pass: String, fail: String },
+    /// Passed if B, C, D, E, H and L are 3, 5, 8, 13, 21 and 34 when `LD B,B` runs
+    Mooneye,
+    /// Passed if the LCD matches this PNG at the end of the run, see `ScreenComparison`
+    Screenshot(PathBuf),
+}
+
+/// What is expected of a single test ROM
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Expectation {
+    pub(crate) check: Check,
+    /// Overrides the frame limit of the run
+    pub(crate) frames: Option<u64>,
+    /// Overrides the timeout of the run, in seconds
+    pub(crate) timeout: Option<u64>,
+    /// Overrides how much a color channel may differ from the reference screenshot
+    pub(crate) tolerance: Option<u8>,
+    pub(crate) skip: bool,
+}
+
+impl Default for Expectation {
+    fn default() -> Expectation {
+        Expectation {
+            check: Check::Auto,
+            frames: None,
+            timeout: None,
+            tolerance: None,
+            skip: false,
+        }
+    }
+}
+
+pub(crate) const DEFAULT_PASS_TEXT: &str = "Passed";
+pub(crate) const DEFAULT_FAIL_TEXT: &str = "Failed";
+
+/**
+ * The expectations file of a test directory: one `ROM CHECK...` line per ROM, with the paths
+ * relative to the test directory. Comments start with `#`. For example
+ *
+ * ```text
+ * cpu_instrs/01-special.gb  serial=Passed
+ * acceptance/div_timing.gb  mooneye
+ * dmg-acid2.gb              screenshot=dmg-acid2.png frames=10
+ * halt_bug.gb               skip
+ * ```
+ *
+ * `fail=TEXT` sets the serial text of a failure, `Failed` by default, `frames=N` and
+ * `timeout=SECONDS` override the limits of the run and `tolerance=N` how much a color channel
+ * may differ from the screenshot. A ROM without a check is compared with
+ * the PNG next to it if there is one, or else checked like blargg and mooneye tests.
+ */
+#[derive(Default, Debug)]
+pub(crate) struct Expectations {
+    entries: HashMap<PathBuf, Expectation>,
+}
+
+impl Expectations {
+    pub(crate) fn load(path: &Path) -> Result<Expectations, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read expectations from {}: {}", path.display(), e))?;
+
+        Expectations::parse(&content)
+            .map_err(|e| format!("Invalid expectations file {}: {}", path.display(), e))
+    }
+
+    pub(crate) fn parse(content: &str) -> Result<Expectations, String> {
+        let mut expectations = Expectations::default();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let mut words = line.split_whitespace();
+            let Some(rom) = words.next() else {
+                continue;
+            };
+
+            let expectation =
+                parse_expectation(words).map_err(|e| format!("{} in line {}", e, index + 1))?;
+            expectations.entries.insert(PathBuf::from(rom), expectation);
+        }
+
+        Ok(expectations)
+    }
+
+    /// The expectation of the ROM at `rom`, relative to the test `directory`
+    pub(crate) fn for_rom(&self, directory: &Path, rom: &Path) -> Expectation {
+        let mut expectation = self.entries.get(rom).cloned().unwrap_or_default();
+
+        let reference = rom.with_extension("png");
+        if expectation.check == Check::Auto && directory.join(&reference).is_file() {
+            expectation.check = Check::Screenshot(reference);
+        }
+        expectation
+    }
+
+    /// The ROMs listed that are not in `roms`
+    pub(crate) fn missing<'e>(&'e self, roms: &[PathBuf]) -> Vec<&'e Path> {
+        let mut missing = self
+            .entries
+            .keys()
+            .filter(|rom| !roms.contains(rom))
+            .map(PathBuf::as_path)
+            .collect::<Vec<&Path>>();
+        missing.sort();
+        missing
+    }
+}
+
+fn parse_expectation<'l>(words: impl Iterator<Item = &'l str>) -> Result<Expectation, String> {
+    let mut expectation = Expectation::default();
+    let mut fail = None;
+
+    for word in words {
+        let (key, value) = match word.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (word, None),
+        };
+        let number = |value: Option<&str>| {
+            value
+                .and_then(|value| value.parse::<u64>().ok())
+                .ok_or_else(|| format!("Expected {}=NUMBER", key))
+        };
+
+        match (key, value) {
+            ("serial", Some(text)) => {
+                expectation.check = Check::Serial {
+                    pass: text.to_string(),
+                    fail: DEFAULT_FAIL_TEXT.to_string(),
+                }
+            }
+            ("fail", Some(text)) => fail = Some(text.to_string()),
+            ("mooneye", None) => expectation.check = Check::Mooneye,
+            ("screenshot", Some(path)) => {
+                expectation.check = Check::Screenshot(PathBuf::from(path))
+            }
+            ("frames", _) => expectation.frames = Some(number(value)?),
+            ("timeout", _) => expectation.timeout = Some(number(value)?),
+            ("tolerance", _) => {
+                expectation.tolerance = Some(
+                    u8::try_from(number(value)?)
+                        .map_err(|_| "The tolerance is at most 255".to_string())?,
+                )
+            }
+            ("skip", None) => expectation.skip = true,
+            _ => return Err(format!("Unknown check '{}'", word)),
+        }
+    }
+
+    if let Some(text) = fail {
+        match &mut expectation.check {
+            Check::Serial { fail, .. } => *fail = text,
+            _ => return Err("fail= needs serial=".to_string()),
+        }
+    }
+
+    Ok(expectation)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{Check, Expectation, Expectations};
+
+    fn parse_line(line: &str) -> Result<Expectation, String> {
+        let expectations = Expectations::parse(line)?;
+        let rom = line.split_whitespace().next().unwrap();
+        Ok(expectations.entries[Path::new(rom)].clone())
+    }
+
+    #[test]
+    fn parses_every_check() {
+        let content = "# the blargg tests
+cpu_instrs/01-special.gb  serial=Passed  # with a comment
+instr_timing.gb           serial=ok fail=bad timeout=20
+
+acceptance/div_timing.gb  mooneye frames=600
+dmg-acid2.gb              screenshot=dmg-acid2.png tolerance=8
+halt_bug.gb               skip
+";
+        let expectations = Expectations::parse(content).unwrap();
+        assert_eq!(expectations.entries.len(), 5);
+
+        let expectation = |rom: &str| expectations.entries[Path::new(rom)].clone();
+        assert_eq!(
+            expectation("cpu_instrs/01-special.gb").check,
+            Check::Serial {
+                pass: "Passed".to_string(),
+                fail: "Failed".to_string()
+            }
+        );
+        let instr_timing = expectation("instr_timing.gb");
+        assert_eq!(
+            instr_timing.check,
+            Check::Serial {
+                pass: "ok".to_string(),
+                fail: "bad".to_string()
+            }
+        );
+        assert_eq!(instr_timing.timeout, Some(20));
+        let div_timing = expectation("acceptance/div_timing.gb");
+        assert_eq!(div_timing.check, Check::Mooneye);
+        assert_eq!(div_timing.frames, Some(600));
+        let acid2 = expectation("dmg-acid2.gb");
+        assert_eq!(
+            acid2.check,
+            Check::Screenshot(PathBuf::from("dmg-acid2.png"))
+        );
+        assert_eq!(acid2.tolerance, Some(8));
+        assert!(expectation("halt_bug.gb").skip);
+
+        assert_eq!(
+            expectations.missing(&[PathBuf::from("halt_bug.gb")]),
+            [
+                Path::new("acceptance/div_timing.gb"),
+                Path::new("cpu_instrs/01-special.gb"),
+                Path::new("dmg-acid2.gb"),
+                Path::new("instr_timing.gb"),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_rom_without_checks_is_checked_automatically() {
+        assert_eq!(parse_line("rom.gb").unwrap(), Expectation::default());
+        assert_eq!(
+            Expectations::default().for_rom(Path::new("/nonexistent"), Path::new("rom.gb")),
+            Expectation::default()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_checks() {
+        for (line, error) in [
+            ("rom.gb blargg", "Unknown check 'blargg' in line 1"),
+            (
+                "rom.gb mooneye=yes",
+                "Unknown check 'mooneye=yes' in line 1",
+            ),
+            ("rom.gb serial", "Unknown check 'serial' in line 1"),
+            ("rom.gb frames", "Expected frames=NUMBER in line 1"),
+            ("rom.gb timeout=soon", "Expected timeout=NUMBER in line 1"),
+            (
+                "rom.gb tolerance=256",
+                "The tolerance is at most 255 in line 1",
+            ),
+            ("rom.gb mooneye fail=bad", "fail= needs serial= in line 1"),
+        ] {
+            assert_eq!(parse_line(line), Err(error.to_string()));
+        }
+
+        assert_eq!(
+            Expectations::parse("a.gb skip\n\nb.gb skipped").unwrap_err(),
+            "Unknown check 'skipped' in line 3"
+        );
+    }
+}
@@ -0,0 +1,4 @@
+pub(crate) mod expectations;
+pub(crate) mod report;
+pub(crate) mod runner;
+pub(crate) mod screenshot;
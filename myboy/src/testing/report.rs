@@ -0,0 +1,150 @@
+use std::{fs, path::Path, time::Duration};
+
+use super::runner::{Outcome, TestResult};
+
+/// How many results ended in each way
+#[derive(Default, Debug, PartialEq, Eq)]
+pub(crate) struct Totals {
+    pub(crate) passed: usize,
+    pub(crate) failed: usize,
+    pub(crate) errors: usize,
+    pub(crate) skipped: usize,
+}
+
+impl Totals {
+    pub(crate) fn of(results: &[TestResult]) -> Totals {
+        let mut totals = Totals::default();
+        for result in results {
+            match result.outcome {
+                Outcome::Passed => totals.passed += 1,
+                Outcome::Failed(_) => totals.failed += 1,
+                Outcome::Error(_) => totals.errors += 1,
+                Outcome::Skipped => totals.skipped += 1,
+            }
+        }
+        totals
+    }
+}
+
+/**
+ * One line per ROM with the result, the time it ran and why it did not pass,
+ * followed by the totals.
+ */
+pub(crate) fn format_summary(results: &[TestResult], duration: Duration) -> String {
+    let rom_width = results
+        .iter()
+        .map(|result| result.rom.display().to_string().len())
+        .max()
+        .unwrap_or_default()
+        .max("ROM".len());
+
+    let mut lines = vec![format!(
+        "{:<6} {:>8}  {:<rom_width$}  DETAIL",
+        "RESULT", "TIME", "ROM"
+    )];
+    for result in results {
+        let (label, detail) = match &result.outcome {
+            Outcome::Passed => ("pass", ""),
+            Outcome::Failed(message) => ("FAIL", message.as_str()),
+            Outcome::Error(message) => ("ERROR", message.as_str()),
+            Outcome::Skipped => ("skip", ""),
+        };
+        // only the last line, like the verdict at the end of the serial output,
+        // the JUnit report has all of it
+        let detail = detail
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or_default();
+        let line = format!(
+            "{:<6} {:>7.2}s  {:<rom_width$}  {}",
+            label,
+            result.duration.as_secs_f64(),
+            result.rom.display(),
+            detail
+        );
+        lines.push(line.trim_end().to_string());
+    }
+
+    let totals = Totals::of(results);
+    lines.push(format!(
+        "{} passed, {} failed, {} errors, {} skipped in {:.2}s",
+        totals.passed,
+        totals.failed,
+        totals.errors,
+        totals.skipped,
+        duration.as_secs_f64()
+    ));
+    lines.join("\n") + "\n"
+}
+
+/// Writes the results as a JUnit XML report with a single test suite named `suite`
+pub(crate) fn write_junit(
+    path: &Path,
+    suite: &str,
+    results: &[TestResult],
+    duration: Duration,
+) -> Result<(), String> {
+    let totals = Totals::of(results);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let attributes = format!(
+        "tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\"",
+        results.len(),
+        totals.failed,
+        totals.errors,
+        totals.skipped,
+        duration.as_secs_f64()
+    );
+    xml += &format!("<testsuites {}>\n", attributes);
+    xml += &format!(
+        "  <testsuite name=\"{}\" {}>\n",
+        escape_xml(suite),
+        attributes
+    );
+
+    for result in results {
+        let open = format!(
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+            escape_xml(&result.rom.display().to_string()),
+            escape_xml(suite),
+            result.duration.as_secs_f64()
+        );
+        xml += &match &result.outcome {
+            Outcome::Passed => format!("{}/>\n", open),
+            Outcome::Failed(message) => format!(
+                "{}>\n      <failure message=\"{}\"/>\n    </testcase>\n",
+                open,
+                escape_xml(message)
+            ),
+            Outcome::Error(message) => format!(
+                "{}>\n      <error message=\"{}\"/>\n    </testcase>\n",
+                open,
+                escape_xml(message)
+            ),
+            Outcome::Skipped => format!("{}>\n      <skipped/>\n    </testcase>\n", open),
+        };
+    }
+
+    xml += "  </testsuite>\n</testsuites>\n";
+    fs::write(path, xml)
+        .map_err(|e| format!("Failed to write the JUnit report {}: {}", path.display(), e))
+}
+
+/// Escapes text for element content and attribute values
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            // not allowed in XML 1.0 at all
+            c if c.is_control() && c != '\t' && c != '\r' => escaped.push('?'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,365 @@
+use std::{
+    any::Any,
+    fs, panic,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use mygbcartridge::cartridge::Cartridge;
+
+use crate::{
+    cpu::register_set::ByteRegister,
+    debugger::{history::ExecutionHistory, stepping::FRAME_CYCLES},
+    device::{device::Device, headless::SerialMatcher},
+    logging::log::SilentLogger,
+};
+
+use super::{
+    expectations::{Check, DEFAULT_FAIL_TEXT, DEFAULT_PASS_TEXT, Expectation},
+    screenshot::ScreenshotTest,
+};
+
+/// `LD B,B`, the software breakpoint mooneye tests end with
+const MOONEYE_BREAKPOINT: u8 = 0x40;
+const MOONEYE_SIGNATURE: [u8; 6] = [3, 5, 8, 13, 21, 34];
+/// The endless loops mooneye tests run after the `LD B,B`: `jr @`, or a `nop` and a `jr` back to it
+const MOONEYE_END_LOOPS: [&[u8]; 2] = [&[0x18, 0xfe], &[0x00, 0x18, 0xfd]];
+const MOONEYE_REGISTERS: [ByteRegister; 6] = [
+    ByteRegister::B,
+    ByteRegister::C,
+    ByteRegister::D,
+    ByteRegister::E,
+    ByteRegister::H,
+    ByteRegister::L,
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    /// The ROM could not be checked, it timed out or the emulation aborted
+    Error(String),
+    Skipped,
+}
+
+pub(crate) struct TestResult {
+    /// Relative to the test directory
+    pub(crate) rom: PathBuf,
+    pub(crate) outcome: Outcome,
+    pub(crate) duration: Duration,
+}
+
+/// The limits of every run, unless the expectation of a ROM overrides them
+#[derive(Clone, Copy)]
+pub(crate) struct Limits {
+    pub(crate) frames: u64,
+    pub(crate) timeout: Duration,
+    /// How much a color channel may differ from a reference screenshot
+    pub(crate) tolerance: u8,
+}
+
+/// The `.gb` and `.gbc` files in `directory` and below, relative to it and sorted
+pub(crate) fn find_roms(directory: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut roms = Vec::new();
+    let mut directories = vec![directory.to_path_buf()];
+
+    while let Some(current) = directories.pop() {
+        let entries = fs::read_dir(&current)
+            .map_err(|e| format!("Failed to read the directory {}: {}", current.display(), e))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|e| format!("Failed to read the directory {}: {}", current.display(), e))?
+                .path();
+            if path.is_dir() {
+                directories.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension == "gb" || extension == "gbc")
+            {
+                roms.push(path.strip_prefix(directory).unwrap().to_path_buf());
+            }
+        }
+    }
+
+    roms.sort();
+    Ok(roms)
+}
+
+/**
+ * Runs the `roms` in `directory` on `jobs` threads, each headless and unthrottled
+ * on a device of its own. The results are in the order of `roms`.
+ */
+pub(crate) fn run_tests(
+    directory: &Path,
+    roms: &[PathBuf],
+    expectation: impl Fn(&Path) -> Expectation + Sync,
+    limits: Limits,
+    jobs: usize,
+) -> Vec<TestResult> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(roms.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, roms.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(rom) = roms.get(index) else {
+                        break;
+                    };
+                    let result = run_test(directory, rom, &expectation(rom), limits);
+                    results.lock().unwrap().push((index, result));
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn run_test(directory: &Path, rom: &Path, expectation: &Expectation, limits: Limits) -> TestResult {
+    let started = Instant::now();
+    let outcome = match expectation.skip {
+        true => Outcome::Skipped,
+        false => panic::catch_unwind(|| check_rom(directory, rom, expectation, limits))
+            .unwrap_or_else(|payload| {
+                Outcome::Error(format!(
+                    "The emulation aborted: {}",
+                    panic_message(&payload)
+                ))
+            }),
+    };
+
+    TestResult {
+        rom: rom.to_path_buf(),
+        outcome,
+        duration: started.elapsed(),
+    }
+}
+
+fn check_rom(directory: &Path, rom: &Path, expectation: &Expectation, limits: Limits) -> Outcome {
+    let frames = expectation.frames.unwrap_or(limits.frames);
+    let timeout = expectation
+        .timeout
+        .map(Duration::from_secs)
+        .unwrap_or(limits.timeout);
+    let texts = match &expectation.check {
+        Check::Auto => vec![DEFAULT_PASS_TEXT.as_bytes(), DEFAULT_FAIL_TEXT.as_bytes()],
+        Check::Serial { pass, fail } => vec![pass.as_bytes(), fail.as_bytes()],
+        Check::Mooneye => Vec::new(),
+        Check::Screenshot(reference) => {
+            let test = ScreenshotTest {
+                rom: directory.join(rom),
+                reference: directory.join(reference),
+                frames,
+                tolerance: expectation.tolerance.unwrap_or(limits.tolerance),
+                timeout: Some(timeout),
+            };
+            return test.run();
+        }
+    };
+    let mut serial = SerialMatcher::new(texts, 0);
+
+    let mut device = match test_device(&directory.join(rom)) {
+        Ok(device) => device,
+        Err(e) => return Outcome::Error(e),
+    };
+
+    let started = Instant::now();
+    while device.cycle_count < frames * FRAME_CYCLES {
+        let opcode = device.mem_map.peek_byte(*device.cpu.register_set.pc());
+        device.step();
+
+        if opcode == MOONEYE_BREAKPOINT && ends_mooneye_test(&device, &expectation.check) {
+            return check_mooneye(&device);
+        }
+        match serial.find(&device.serial_buffer) {
+            Some(0) => return Outcome::Passed,
+            Some(_) => return Outcome::Failed(serial_output(&device)),
+            None => {}
+        }
+        if started.elapsed() >= timeout {
+            return timed_out(timeout);
+        }
+    }
+
+    Outcome::Failed(format!(
+        "No result after {} frames{}",
+        frames,
+        match device.serial_buffer.is_empty() {
+            true => String::new(),
+            false => format!(", the serial output is: {}", serial_output(&device)),
+        }
+    ))
+}
+
+pub(crate) fn timed_out(timeout: Duration) -> Outcome {
+    Outcome::Error(format!("Timed out after {} seconds", timeout.as_secs()))
+}
+
+/// A device for running `rom` as fast as possible, without logging
+pub(crate) fn test_device(rom: &Path) -> Result<Device, String> {
+    let data =
+        fs::read(rom).map_err(|e| format!("Failed to read the ROM {}: {}", rom.display(), e))?;
+    let mut device = Device::new(Cartridge { data });
+    device.logger = Box::new(SilentLogger);
+    device.history = ExecutionHistory::new(0);
+    device.throttle = false;
+    Ok(device)
+}
+
+fn mooneye_registers(device: &Device) -> [u8; 6] {
+    MOONEYE_REGISTERS.map(|register| *device.cpu.register_set.get_b(register))
+}
+
+/**
+ * Whether the `LD B,B` that just ran ends a mooneye test. blargg tests run it as well,
+ * so unless mooneye is expected, the registers must hold the signature
+ * or one of the loops mooneye tests end in must follow.
+ */
+fn ends_mooneye_test(device: &Device, check: &Check) -> bool {
+    let pc = *device.cpu.register_set.pc();
+    let at_end_loop = || {
+        MOONEYE_END_LOOPS.iter().any(|end_loop| {
+            end_loop.iter().enumerate().all(|(offset, byte)| {
+                device.mem_map.peek_byte(pc.wrapping_add(offset as u16)) == *byte
+            })
+        })
+    };
+
+    match check {
+        Check::Mooneye => true,
+        Check::Auto => mooneye_registers(device) == MOONEYE_SIGNATURE || at_end_loop(),
+        _ => false,
+    }
+}
+
+fn check_mooneye(device: &Device) -> Outcome {
+    let registers = mooneye_registers(device);
+    match registers == MOONEYE_SIGNATURE {
+        true => Outcome::Passed,
+        false => Outcome::Failed(format!(
+            "B, C, D, E, H, L are {:?} instead of {:?}",
+            registers, MOONEYE_SIGNATURE
+        )),
+    }
+}
+
+fn serial_output(device: &Device) -> String {
+    String::from_utf8_lossy(&device.serial_buffer)
+        .trim()
+        .to_string()
+}
+
+fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "unknown panic".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path, time::Duration};
+
+    use crate::testing::expectations::{Check, Expectation};
+
+    use super::{Limits, Outcome, check_rom};
+
+    const LIMITS: Limits = Limits {
+        frames: 60,
+        timeout: Duration::from_secs(10),
+        tolerance: 0,
+    };
+
+    /// Checks a ROM running `program` at its entry point
+    fn check(name: &str, program: &[u8], check: Check) -> Outcome {
+        let mut data = vec![0; 0x8000];
+        data[0x0100..0x0100 + program.len()].copy_from_slice(program);
+        data[0x0150..0x0157].copy_from_slice(b"Passed\0");
+
+        let directory = env::temp_dir();
+        let rom = format!("bricoboy-runner-{}-{}.gb", name, std::process::id());
+        fs::write(directory.join(&rom), data).unwrap();
+        let expectation = Expectation {
+            check,
+            ..Expectation::default()
+        };
+        let outcome = check_rom(&directory, Path::new(&rom), &expectation, LIMITS);
+        fs::remove_file(directory.join(rom)).unwrap();
+        outcome
+    }
+
+    /// Sets the mooneye registers to `registers` and runs `LD B,B` followed by `jr @`
+    fn mooneye(registers: [u8; 6]) -> Vec<u8> {
+        let [b, c, d, e, h, l] = registers;
+        vec![
+            0x06, b, 0x0e, c, 0x16, d, 0x1e, e, 0x26, h, 0x2e, l,    // ld b..l
+            0x40, // ld b, b
+            0x18, 0xfe, // jr @
+        ]
+    }
+
+    /// Runs `LD B,B` like blargg's "06-ld r,r", then prints the text at 0x0150
+    const BLARGG: [u8; 18] = [
+        0x40, // ld b, b
+        0x21, 0x50, 0x01, // ld hl, $0150
+        0x2a, // .print: ld a, [hl+]
+        0xb7, // or a
+        0x28, 0x08, // jr z, .end
+        0xe0, 0x01, // ldh [rSB], a
+        0x3e, 0x81, // ld a, $81
+        0xe0, 0x02, // ldh [rSC], a
+        0x18, 0xf4, // jr .print
+        0x18, 0xfe, // .end: jr @
+    ];
+
+    #[test]
+    fn auto_keeps_running_after_a_blargg_ld_b_b() {
+        assert_eq!(check("blargg", &BLARGG, Check::Auto), Outcome::Passed);
+        assert!(matches!(
+            check("blargg-mooneye", &BLARGG, Check::Mooneye),
+            Outcome::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn auto_recognizes_mooneye_results() {
+        let signature = [3, 5, 8, 13, 21, 34];
+        assert_eq!(
+            check("mooneye-pass", &mooneye(signature), Check::Auto),
+            Outcome::Passed
+        );
+        assert!(matches!(
+            check("mooneye-fail", &mooneye([0x42; 6]), Check::Auto),
+            Outcome::Failed(message) if message.contains("[66, 66, 66, 66, 66, 66]")
+        ));
+    }
+
+    #[test]
+    fn serial_checks_ignore_ld_b_b() {
+        let serial = Check::Serial {
+            pass: "Pass".to_string(),
+            fail: "Fail".to_string(),
+        };
+        assert_eq!(check("serial", &BLARGG, serial), Outcome::Passed);
+        assert!(matches!(
+            check("serial-none", &mooneye([3, 5, 8, 13, 21, 34]), Check::Serial {
+                pass: "Pass".to_string(),
+                fail: "Fail".to_string(),
+            }),
+            Outcome::Failed(message) if message.starts_with("No result after 60 frames")
+        ));
+    }
+}
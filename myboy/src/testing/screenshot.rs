@@ -0,0 +1,293 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    debugger::stepping::RunTarget,
+    device::{
+        device::Device,
+        headless::{StopConditions, StopReason},
+        screenshot::{load_screenshot, write_screenshot},
+    },
+};
+
+use super::runner::{Outcome, test_device, timed_out};
+
+/// The color of the differing pixels in diff images
+const DIFF_COLOR: [u8; 4] = [0xff, 0x00, 0x00, 0xff];
+
+/**
+ * How the LCD differs from a reference screenshot. Two pixels match if none of their color
+ * channels differ by more than the tolerance, the alpha channel is not drawn and ignored.
+ */
+pub(crate) struct ScreenComparison {
+    /// The pixels that do not match
+    pub(crate) differing: usize,
+    /// The largest difference of a color channel, also within the tolerance
+    pub(crate) max_difference: u8,
+    /// The reference faded to light gray, with the differing pixels in red
+    diff: Vec<u8>,
+}
+
+impl ScreenComparison {
+    pub(crate) fn new(screen: &[u8], reference: &[u8], tolerance: u8) -> ScreenComparison {
+        let mut comparison = ScreenComparison {
+            differing: 0,
+            max_difference: 0,
+            diff: Vec::with_capacity(reference.len()),
+        };
+
+        for (pixel, expected) in screen.chunks(4).zip(reference.chunks(4)) {
+            let difference = (0..3)
+                .map(|channel| pixel[channel].abs_diff(expected[channel]))
+                .max()
+                .unwrap_or_default();
+            comparison.max_difference = comparison.max_difference.max(difference);
+
+            if difference > tolerance {
+                comparison.differing += 1;
+                comparison.diff.extend_from_slice(&DIFF_COLOR);
+            } else {
+                let gray = (expected[0] as u16 + expected[1] as u16 + expected[2] as u16) / 3;
+                let faded = 0xc0 + (gray / 4) as u8;
+                comparison
+                    .diff
+                    .extend_from_slice(&[faded, faded, faded, 0xff]);
+            }
+        }
+
+        comparison
+    }
+
+    pub(crate) fn matches(&self) -> bool {
+        self.differing == 0
+    }
+
+    pub(crate) fn write_diff(&self, path: &Path) -> Result<(), String> {
+        write_screenshot(path, &self.diff)
+    }
+}
+
+/// Where the diff image for a reference is written, `dmg-acid2.diff.png` for `dmg-acid2.png`
+pub(crate) fn diff_path(reference: &Path) -> PathBuf {
+    reference.with_extension("diff.png")
+}
+
+/**
+ * Compares the screen with the reference read from `reference_path`. If they differ the diff
+ * image is written next to the reference, if they match an old one is removed.
+ */
+pub(crate) fn check_screen(
+    screen: &[u8],
+    reference: &[u8],
+    reference_path: &Path,
+    tolerance: u8,
+) -> Result<(), String> {
+    let comparison = ScreenComparison::new(screen, reference, tolerance);
+    let diff = diff_path(reference_path);
+    if comparison.matches() {
+        let _ = fs::remove_file(diff);
+        return Ok(());
+    }
+
+    comparison.write_diff(&diff)?;
+    Err(format!(
+        "{} of {} pixels differ from {} by up to {}, see {}",
+        comparison.differing,
+        screen.len() / 4,
+        reference_path.display(),
+        comparison.max_difference,
+        diff.display()
+    ))
+}
+
+/// Runs on until the PPU completed the frame it is drawing, so the screen is whole
+pub(crate) fn finish_frame(device: &mut Device) {
+    let target = RunTarget::next_frame(device);
+    while !target.is_reached(device) {
+        device.step();
+    }
+}
+
+/**
+ * A ROM run headless for a number of frames and compared with a reference PNG. The test
+ * command runs `screenshot=` checks with it, graphics regression tests in Rust can too:
+ *
+ * ```no_run
+ * use bricoboy::{Outcome, ScreenshotTest};
+ *
+ * let test = ScreenshotTest {
+ *     rom: "roms/dmg-acid2.gb".into(),
+ *     reference: "roms/dmg-acid2.png".into(),
+ *     frames: 10,
+ *     tolerance: 0,
+ *     timeout: None,
+ * };
+ * assert_eq!(test.run(), Outcome::Passed);
+ * ```
+ */
+pub struct ScreenshotTest {
+    pub rom: PathBuf,
+    pub reference: PathBuf,
+    pub frames: u64,
+    /// How much a color channel may differ, 0 for an exact match
+    pub tolerance: u8,
+    pub timeout: Option<Duration>,
+}
+
+impl ScreenshotTest {
+    pub fn run(&self) -> Outcome {
+        let reference = match load_screenshot(&self.reference) {
+            Ok(reference) => reference,
+            Err(e) => return Outcome::Error(e),
+        };
+        let mut device = match test_device(&self.rom) {
+            Ok(device) => device,
+            Err(e) => return Outcome::Error(e),
+        };
+
+        let conditions = StopConditions {
+            frames: Some(self.frames),
+            timeout: self.timeout,
+            ..StopConditions::default()
+        };
+        if let (Some(StopReason::Timeout), Some(timeout)) =
+            (device.run_headless(&conditions), self.timeout)
+        {
+            return timed_out(timeout);
+        }
+        finish_frame(&mut device);
+
+        match check_screen(&device.screen, &reference, &self.reference, self.tolerance) {
+            Ok(()) => Outcome::Passed,
+            Err(e) => Outcome::Failed(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use crate::device::screenshot::{load_screenshot, write_screenshot};
+
+    use super::{
+        DIFF_COLOR, Outcome, ScreenComparison, ScreenshotTest, StopConditions, diff_path,
+        finish_frame, test_device,
+    };
+
+    const PIXELS: usize = 160 * 144;
+
+    fn screen(color: [u8; 4]) -> Vec<u8> {
+        color.repeat(PIXELS)
+    }
+
+    /// A directory of its own for every test, as they run in parallel
+    fn temp_dir(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("bricoboy-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    /// A ROM that loops forever with the LCD as the boot ROM left it
+    fn write_idle_rom(path: &PathBuf) {
+        let mut rom = vec![0u8; 0x8000];
+        // nop, jp 0x0150
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+        // jr -2
+        rom[0x150..0x152].copy_from_slice(&[0x18, 0xfe]);
+        fs::write(path, rom).unwrap();
+    }
+
+    #[test]
+    fn identical_screens_match() {
+        let comparison = ScreenComparison::new(
+            &screen([0x12, 0x34, 0x56, 0xff]),
+            &screen([0x12, 0x34, 0x56, 0xff]),
+            0,
+        );
+        assert!(comparison.matches());
+        assert_eq!(comparison.max_difference, 0);
+    }
+
+    #[test]
+    fn differences_within_the_tolerance_match() {
+        let reference = screen([0x80, 0x80, 0x80, 0xff]);
+        let mut screen = reference.clone();
+        screen[4 * 10 + 1] = 0x83;
+
+        let exact = ScreenComparison::new(&screen, &reference, 0);
+        assert_eq!(exact.differing, 1);
+        assert_eq!(exact.max_difference, 3);
+        assert!(!ScreenComparison::new(&screen, &reference, 2).matches());
+        assert!(ScreenComparison::new(&screen, &reference, 3).matches());
+    }
+
+    #[test]
+    fn alpha_is_ignored() {
+        let comparison = ScreenComparison::new(
+            &screen([0x00, 0x00, 0x00, 0x00]),
+            &screen([0x00, 0x00, 0x00, 0xff]),
+            0,
+        );
+        assert!(comparison.matches());
+    }
+
+    #[test]
+    fn diff_marks_differing_pixels() {
+        let reference = screen([0xff, 0xff, 0xff, 0xff]);
+        let mut screen = reference.clone();
+        screen[4 * 7..4 * 8].copy_from_slice(&[0x00, 0x00, 0x00, 0xff]);
+
+        let comparison = ScreenComparison::new(&screen, &reference, 0);
+        assert_eq!(comparison.differing, 1);
+        let diff_pixels = comparison.diff.chunks(4).collect::<Vec<&[u8]>>();
+        assert_eq!(diff_pixels[7], DIFF_COLOR);
+        let red = diff_pixels.iter().filter(|pixel| **pixel == DIFF_COLOR);
+        assert_eq!(red.count(), 1);
+    }
+
+    #[test]
+    fn screenshot_test_compares_with_the_reference() {
+        let directory = temp_dir("screenshot-test");
+        let rom = directory.join("idle.gb");
+        let reference = directory.join("idle.png");
+        write_idle_rom(&rom);
+
+        // the screen of the first run is the reference of the second
+        let mut device = test_device(&rom).unwrap();
+        device.run_headless(&StopConditions {
+            frames: Some(3),
+            ..StopConditions::default()
+        });
+        finish_frame(&mut device);
+        device.save_screenshot(&reference).unwrap();
+
+        let test = ScreenshotTest {
+            rom: rom.clone(),
+            reference: reference.clone(),
+            frames: 3,
+            tolerance: 0,
+            timeout: None,
+        };
+        assert_eq!(test.run(), Outcome::Passed);
+        assert!(!diff_path(&reference).exists());
+
+        let mut changed = load_screenshot(&reference).unwrap();
+        changed[0] ^= 0xff;
+        write_screenshot(&reference, &changed).unwrap();
+        let Outcome::Failed(message) = test.run() else {
+            panic!("The changed reference matched");
+        };
+        assert!(
+            message.starts_with("1 of 23040 pixels differ"),
+            "{}",
+            message
+        );
+        assert!(diff_path(&reference).exists());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,44 +1,53 @@
-// use crate::ui::emulator_view::EmulatorView;
-// use egui::CentralPanel;
+use crate::ui::emulator_view::EmulatorView;
+use egui::CentralPanel;
 
 use crate::Device;
 
-#[expect(dead_code)]
 pub struct AppTemplate {
-    device: Device<'static>,
+    /// `None` once the app exited
+    view: Option<EmulatorView>,
 }
 
-#[expect(dead_code)]
 impl AppTemplate {
-    pub fn new(device: Device<'static>) -> AppTemplate {
-        AppTemplate { device }
+    /// Starts running the device
+    pub fn new(device: Device) -> AppTemplate {
+        AppTemplate {
+            view: Some(EmulatorView::new(device)),
+        }
     }
 }
 
 impl eframe::App for AppTemplate {
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // the emulator thread publishes new snapshots while running, so keep repainting
         ctx.request_repaint_after_secs(0.04);
-        // Put your widgets into a `SidePanel`, `TopBottomPanel`, `CentralPanel`, `Window` or `Area`.
-        // For inspiration and more examples, go to https://emilk.github.io/egui
-        // CentralPanel::default().show(ctx, |ui| {
-        //     let emulator_view = EmulatorView::new(Box::new(device));
-        //     egui::menu::bar(ui, |ui| {
-        //         // NOTE: no File->Quit on web pages!
-        //         let is_web = cfg!(target_arch = "wasm32");
-        //         if !is_web {
-        //             ui.menu_button("File", |ui| {
-        //                 if ui.button("Quit").clicked() {
-        //                     ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
-        //                 }
-        //             });
-        //             ui.add_space(16.0);
-        //         }
-
-        //         egui::widgets::global_theme_preference_buttons(ui);
-        //     });
-
-        //     ui.add(emulator_view);
-        // });
+
+        CentralPanel::default().show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                // NOTE: no File->Quit on web pages!
+                let is_web = cfg!(target_arch = "wasm32");
+                if !is_web {
+                    ui.menu_button("File", |ui| {
+                        if ui.button("Quit").clicked() {
+                            ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
+                        }
+                    });
+                    ui.add_space(16.0);
+                }
+
+                egui::widgets::global_theme_preference_buttons(ui);
+            });
+
+            if let Some(view) = &mut self.view {
+                ui.add(view);
+            }
+        });
+    }
+
+    fn on_exit(&mut self) {
+        if let Some(mut device) = self.view.take().and_then(EmulatorView::stop) {
+            device.logger.flush();
+        }
     }
 }
@@ -6,11 +6,27 @@ use egui_extras::{Column, Size, StripBuilder, TableBuilder};
 use crate::{
     Device,
     cpu::{addressing_mode::AddressingMode, instruction::Instruction, register_set::RegisterSet},
-    device::mem_map::MemMap,
+    device::{
+        emulator::{Command, Emulator},
+        mem_map::MemMap,
+    },
+    disasm::disassembler::disassemble,
 };
 
+fn scroll_target_id() -> egui::Id {
+    egui::Id::new("asm_text_scroll_target")
+}
+
+/**
+ * Makes the next `AsmTextTable` shown scroll to `address`.
+ */
+pub fn scroll_to_address(ui: &egui::Ui, address: u16) {
+    ui.data_mut(|data| data.insert_temp(scroll_target_id(), address));
+}
+
 pub struct AsmTextTable<'a> {
-    device: &'a mut Device<'static>,
+    device: &'a Device,
+    emulator: &'a Emulator,
     autoscroll: bool,
 }
 
@@ -35,19 +51,26 @@ impl<'a> Widget for AsmTextTable<'a> {
 }
 
 impl<'a> AsmTextTable<'a> {
-    pub fn new(device: &'a mut Device<'static>, autoscroll: bool) -> AsmTextTable<'a> {
-        AsmTextTable { device, autoscroll }
+    pub fn new(device: &'a Device, emulator: &'a Emulator, autoscroll: bool) -> AsmTextTable<'a> {
+        AsmTextTable {
+            device,
+            emulator,
+            autoscroll,
+        }
     }
 
     fn asm_text_table(&mut self, ui: &mut egui::Ui) {
         let body_text_size = TextStyle::Body.resolve(ui.style()).size;
 
         let current_address = *self.device.cpu.register_set.pc();
+        let mapped_bank = self.device.mem_map.rom_bank_for_address(0x4000);
+
+        let scroll_target = ui.data_mut(|data| data.remove_temp::<u16>(scroll_target_id()));
 
         let mut table = TableBuilder::new(ui)
             .striped(true)
             .resizable(false)
-            .column(Column::auto().at_least(40.0).at_most(60.0).clip(true))
+            .column(Column::auto().at_least(40.0).at_most(160.0).clip(true))
             .column(
                 Column::remainder()
                     .at_least(200.0)
@@ -57,9 +80,10 @@ impl<'a> AsmTextTable<'a> {
             )
             .column(Column::auto().at_least(100.0).resizable(true));
 
-        table = match self.autoscroll {
-            true => table.scroll_to_row(current_address as usize, None),
-            false => table,
+        table = match (scroll_target, self.autoscroll) {
+            (Some(address), _) => table.scroll_to_row(address as usize, Some(Align::Center)),
+            (None, true) => table.scroll_to_row(current_address as usize, None),
+            (None, false) => table,
         };
 
         table
@@ -83,24 +107,38 @@ impl<'a> AsmTextTable<'a> {
                         if *self.device.cpu.register_set.pc() == instruction.address {
                             row.set_selected(true);
                         }
-                        if let Some(ref addr) = self.device.breakpoint {
-                            if *addr == instruction.address {
-                                row.set_selected(true);
-                            }
+                        let is_breakpoint = self
+                            .device
+                            .breakpoints
+                            .execute_breakpoint_at(instruction.address)
+                            .is_some();
+                        if is_breakpoint {
+                            row.set_selected(true);
                         }
-                        let is_breakpoint = self.device.breakpoint == Some(instruction.address);
+
+                        let symbols = self.device.symbols.clone();
+                        let jump_label = disassemble(&self.device.mem_map, 0, instruction.address)
+                            .jump_target
+                            .and_then(|target| symbols.label(mapped_bank, target));
 
                         row.col(|ui| {
+                            let address_text = match symbols.label(mapped_bank, instruction.address)
+                            {
+                                Some(name) => format!("{}:", name),
+                                None => format!("0x{:04X}", instruction.address),
+                            };
                             let label = match is_breakpoint {
                                 true => ui.colored_label(
                                     Color32::from_rgb_additive(255, 24, 25),
-                                    format!("0x{:04X}", instruction.address),
+                                    address_text,
                                 ),
-                                false => ui.label(format!("0x{:04X}", instruction.address)),
-                            };
+                                false => ui.label(address_text),
+                            }
+                            .on_hover_text(format!("0x{:04X}", instruction.address));
 
                             if label.clicked() {
-                                self.device.toggle_breakpoint(instruction.address);
+                                self.emulator
+                                    .send(Command::ToggleBreakpoint(instruction.address));
                             }
                         });
                         row.col(|ui| {
@@ -112,13 +150,18 @@ impl<'a> AsmTextTable<'a> {
                                 }
 
                                 if let Some(target) = &instruction.target {
-                                    AsmTextTable::render_source_or_target(
-                                        ui,
-                                        target,
-                                        instruction.address,
-                                        &self.device.mem_map,
-                                        &self.device.cpu.register_set,
-                                    );
+                                    match jump_label {
+                                        Some(name) => {
+                                            ui.label(AsmTextTable::label_text(name));
+                                        }
+                                        None => AsmTextTable::render_source_or_target(
+                                            ui,
+                                            target,
+                                            instruction.address,
+                                            &self.device.mem_map,
+                                            &self.device.cpu.register_set,
+                                        ),
+                                    }
                                 }
                                 if let Some(source) = &instruction.source {
                                     AsmTextTable::render_source_or_target(
@@ -142,6 +185,22 @@ impl<'a> AsmTextTable<'a> {
                                 }
                             });
                         });
+
+                        row.response().context_menu(|ui| {
+                            let running = self.device.running;
+                            if ui
+                                .add_enabled(!running, egui::Button::new("Run to here"))
+                                .clicked()
+                            {
+                                self.emulator.send(Command::RunTo(instruction.address));
+                                ui.close_menu();
+                            }
+                            if ui.button("Toggle breakpoint").clicked() {
+                                self.emulator
+                                    .send(Command::ToggleBreakpoint(instruction.address));
+                                ui.close_menu();
+                            }
+                        });
                     } else {
                         row.col(|ui| {
                             ui.label(format!("0x{:04X}", rowid));
@@ -285,6 +344,12 @@ impl<'a> AsmTextTable<'a> {
             .monospace()
     }
 
+    fn label_text(name: &str) -> RichText {
+        RichText::new(name)
+            .color(Color32::from_rgb(25, 255, 0))
+            .monospace()
+    }
+
     fn register_text<T: Display>(register: T) -> RichText {
         RichText::new(format!("{}", register))
             .color(Color32::from_rgb(0, 255, 25))
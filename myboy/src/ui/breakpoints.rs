@@ -0,0 +1,90 @@
+use egui::{Color32, Id, RichText, TextEdit};
+
+use crate::{
+    Device,
+    debugger::breakpoints::{Breakpoint, BreakpointKind},
+    device::emulator::{Command, Emulator},
+};
+
+/**
+ * Lists the breakpoints with their hit counts, lets them be enabled, disabled and removed,
+ * and adds new ones in the same syntax as the `--breakpoint` option.
+ */
+pub struct BreakpointView<'a> {
+    pub device: &'a Device,
+    pub emulator: &'a Emulator,
+}
+
+impl egui::Widget for BreakpointView<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let input_id = Id::new("breakpoint_input");
+        let error_id = Id::new("breakpoint_error");
+
+        ui.vertical(|ui| {
+            let mut toggled = Vec::new();
+            let mut removed = Vec::new();
+
+            for breakpoint in self.device.breakpoints.breakpoints() {
+                ui.horizontal(|ui| {
+                    let mut enabled = breakpoint.enabled;
+                    if ui.checkbox(&mut enabled, "").changed() {
+                        toggled.push((breakpoint.id, enabled));
+                    }
+
+                    let mut text = format!("#{} {}", breakpoint.id, breakpoint.kind);
+                    if let BreakpointKind::Execute { address, bank } = breakpoint.kind
+                        && let Some(label) = self.device.symbols.label(bank, address)
+                    {
+                        text.push_str(&format!(" ({})", label));
+                    }
+                    if let Some(condition) = &breakpoint.condition {
+                        text.push_str(&format!(" if {}", condition));
+                    }
+                    ui.label(RichText::new(text).monospace());
+                    ui.label(format!("{} hits", breakpoint.hit_count));
+
+                    if ui.small_button("x").clicked() {
+                        removed.push(breakpoint.id);
+                    }
+                });
+            }
+
+            for (id, enabled) in toggled {
+                self.emulator
+                    .send(Command::SetBreakpointEnabled(id, enabled));
+            }
+            for id in removed {
+                self.emulator.send(Command::RemoveBreakpoint(id));
+            }
+
+            let mut input =
+                ui.data_mut(|data| data.get_temp::<String>(input_id).unwrap_or_default());
+            ui.horizontal(|ui| {
+                let response = ui.add(
+                    TextEdit::singleline(&mut input)
+                        .hint_text("0150 if A == 0x3F")
+                        .desired_width(140.0),
+                );
+                let submitted =
+                    response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter));
+
+                if ui.button("Add").clicked() || submitted {
+                    match Breakpoint::parse(&input, &self.device.symbols) {
+                        Ok(breakpoint) => {
+                            self.emulator.send(Command::AddBreakpoint(breakpoint));
+                            input.clear();
+                            ui.data_mut(|data| data.remove::<String>(error_id));
+                        }
+                        Err(e) => ui.data_mut(|data| data.insert_temp(error_id, e)),
+                    }
+                }
+            });
+            ui.data_mut(|data| data.insert_temp(input_id, input));
+
+            if let Some(error) = ui.data(|data| data.get_temp::<String>(error_id)) {
+                ui.colored_label(Color32::from_rgb(255, 25, 0), error);
+            }
+        })
+        .response
+    }
+}
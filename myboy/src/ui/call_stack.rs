@@ -0,0 +1,64 @@
+use egui::RichText;
+
+use crate::{debugger::call_stack::CallStack, disasm::symbols::SymbolTable};
+
+use super::asm_text::scroll_to_address;
+
+/**
+ * The shadow call stack, innermost frame first.
+ * Clicking an address shows it in the program view.
+ */
+pub struct CallStackView<'a> {
+    pub call_stack: &'a CallStack,
+    pub pc: u16,
+    pub symbols: &'a SymbolTable,
+}
+
+impl egui::Widget for CallStackView<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("#0  ").monospace());
+                if ui
+                    .link(self.symbols.format_address(None, self.pc))
+                    .clicked()
+                {
+                    scroll_to_address(ui, self.pc);
+                }
+            });
+
+            for (index, frame) in self.call_stack.frames().iter().rev().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(format!("#{:<3}", index + 1)).monospace());
+                    if ui
+                        .link(
+                            self.symbols
+                                .format_address(frame.call_site_bank, frame.call_site),
+                        )
+                        .clicked()
+                    {
+                        scroll_to_address(ui, frame.call_site);
+                    }
+                    ui.label(format!("{} to", frame.kind));
+                    if ui
+                        .link(self.symbols.format_address(frame.target_bank, frame.target))
+                        .clicked()
+                    {
+                        scroll_to_address(ui, frame.target);
+                    }
+                });
+            }
+
+            if self.call_stack.discarded_frames() > 0 {
+                ui.label(
+                    RichText::new(format!(
+                        "{} frames discarded by moving SP",
+                        self.call_stack.discarded_frames()
+                    ))
+                    .size(10.0),
+                );
+            }
+        })
+        .response
+    }
+}
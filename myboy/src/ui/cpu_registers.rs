@@ -1,78 +1,137 @@
-use crate::cpu::{
-    cpu::CPU,
-    register_set::{ByteRegister, Flag, WordRegister},
+use egui::DragValue;
+
+use crate::{
+    cpu::{
+        cpu::CPU,
+        register_set::{ByteRegister, Flag, WordRegister},
+    },
+    device::emulator::{Command, Emulator},
 };
 
+/**
+ * The CPU registers and flags. While the emulator is paused they can be edited,
+ * registers by dragging or typing a hex value, flags by toggling them.
+ */
 pub struct CPURegisterView<'a> {
     pub cpu: &'a CPU,
+    pub emulator: &'a Emulator,
+    pub editable: bool,
 }
 
 impl egui::Widget for CPURegisterView<'_> {
     fn ui(self, ui: &mut egui::Ui) -> egui::Response {
-        let regset = self.cpu.register_set;
+        let CPURegisterView {
+            cpu,
+            emulator,
+            editable,
+        } = self;
         ui.vertical(|ui| {
             ui.horizontal(|ui| {
                 ui.label("AF:");
-                ui.horizontal(|ui| {
-                    ui.label(format!("${:02X}", regset.get_b(ByteRegister::A),));
-                    ui.label(format!("${:02X}", regset.get_b(ByteRegister::F),))
-                        .on_hover_ui(|ui| {
-                            ui.vertical(|ui| {
-                                ui.label("Flags:");
-                                ui.columns(2, |columns| {
-                                    columns[0].label("Z (Zero) : Set if result is zero");
-                                    columns[1].label(format!("{}", regset.get_flag(Flag::Zero)));
-                                });
-                                ui.columns(2, |columns| {
-                                    columns[0].label("N (Subtract): Set if subtraction");
-                                    columns[1]
-                                        .label(format!("{}", regset.get_flag(Flag::Subtract)));
-                                });
-                                ui.columns(2, |columns| {
-                                    columns[0]
-                                        .label("H (Half Carry): Set if carry from bit 3 to 4");
-                                    columns[1]
-                                        .label(format!("{}", regset.get_flag(Flag::HalfCarry)));
-                                });
-                                ui.columns(2, |columns| {
-                                    columns[0].label("C (Carry): Set if carry from bit 7");
-                                    columns[1].label(format!("{}", regset.get_flag(Flag::Carry)));
-                                });
-                            });
-                        });
-                });
+                byte_register(ui, cpu, emulator, editable, ByteRegister::A);
+                byte_register(ui, cpu, emulator, editable, ByteRegister::F);
 
                 ui.label("BC:");
-                ui.horizontal(|ui| {
-                    ui.label(format!("${:02X}", regset.get_b(ByteRegister::B),));
-                    ui.label(format!("${:02X}", regset.get_b(ByteRegister::C),));
-                });
+                byte_register(ui, cpu, emulator, editable, ByteRegister::B);
+                byte_register(ui, cpu, emulator, editable, ByteRegister::C);
             });
 
             ui.horizontal(|ui| {
                 ui.label("DE:");
-                ui.label(format!(
-                    "0x{:02X}{:02X}",
-                    regset.get_b(ByteRegister::D),
-                    regset.get_b(ByteRegister::E)
-                ));
+                byte_register(ui, cpu, emulator, editable, ByteRegister::D);
+                byte_register(ui, cpu, emulator, editable, ByteRegister::E);
 
                 ui.label("HL:");
-                ui.label(format!(
-                    "0x{:02X}{:02X}",
-                    regset.get_b(ByteRegister::H),
-                    regset.get_b(ByteRegister::L)
-                ));
+                byte_register(ui, cpu, emulator, editable, ByteRegister::H);
+                byte_register(ui, cpu, emulator, editable, ByteRegister::L);
             });
 
             ui.horizontal(|ui| {
                 ui.label("SP:");
-                ui.label(format!("0x{:04X}", regset.get_w(WordRegister::SP)));
+                word_register(ui, cpu, emulator, editable, WordRegister::SP);
 
                 ui.label("PC:");
-                ui.label(format!("0x{:04X}", regset.get_w(WordRegister::PC)));
+                word_register(ui, cpu, emulator, editable, WordRegister::PC);
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Flags:");
+                let flags = [
+                    (Flag::Zero, "Z", "Zero: Set if result is zero"),
+                    (Flag::Subtract, "N", "Subtract: Set if subtraction"),
+                    (
+                        Flag::HalfCarry,
+                        "H",
+                        "Half Carry: Set if carry from bit 3 to 4",
+                    ),
+                    (Flag::Carry, "C", "Carry: Set if carry from bit 7"),
+                ];
+                for (flag, name, description) in flags {
+                    let mut value = cpu.register_set.get_flag(flag);
+                    let changed = ui
+                        .add_enabled(editable, egui::Checkbox::new(&mut value, name))
+                        .on_hover_text(description)
+                        .changed();
+                    if changed {
+                        emulator.send(Command::SetFlag(flag, value));
+                    }
+                }
             });
         })
         .response
     }
 }
+
+fn byte_register(
+    ui: &mut egui::Ui,
+    cpu: &CPU,
+    emulator: &Emulator,
+    editable: bool,
+    register: ByteRegister,
+) {
+    let mut value = *cpu.register_set.get_b(register);
+    if !editable {
+        ui.label(format!("${:02X}", value));
+        return;
+    }
+
+    if ui
+        .add(
+            DragValue::new(&mut value)
+                .hexadecimal(2, false, true)
+                .prefix("$"),
+        )
+        .changed()
+    {
+        // the lower nibble of F is always zero
+        if let ByteRegister::F = register {
+            value &= 0xf0;
+        }
+        emulator.send(Command::SetRegister(register, value));
+    }
+}
+
+fn word_register(
+    ui: &mut egui::Ui,
+    cpu: &CPU,
+    emulator: &Emulator,
+    editable: bool,
+    register: WordRegister,
+) {
+    let mut value = cpu.register_set.get_w(register);
+    if !editable {
+        ui.label(format!("0x{:04X}", value));
+        return;
+    }
+
+    if ui
+        .add(
+            DragValue::new(&mut value)
+                .hexadecimal(4, false, true)
+                .prefix("0x"),
+        )
+        .changed()
+    {
+        emulator.send(Command::SetWordRegister(register, value));
+    }
+}
@@ -1,50 +1,126 @@
-use std::thread::{self, JoinHandle};
+use egui::{
+    CentralPanel, CollapsingHeader, Color32, ColorImage, Response, RichText, SidePanel,
+    TextureHandle, TextureOptions, Widget,
+};
 
-use egui::{CentralPanel, CollapsingHeader, Response, RichText, SidePanel, Widget};
+use crate::{
+    device::{
+        device::Device,
+        emulator::{Command, Emulator, Panels},
+    },
+    screen::{HEIGHT, WIDTH},
+};
 
-use crate::{device::device::Device, io::if_register::InterruptType};
-
-use super::{asm_text::AsmTextTable, cpu_registers::CPURegisterView, io_registers::IORegisterView};
+use super::{
+    asm_text::AsmTextTable,
+    breakpoints::BreakpointView,
+    call_stack::CallStackView,
+    cpu_registers::CPURegisterView,
+    history::HistoryView,
+    io_registers::{IORegisterView, InterruptView},
+    memory_view::MemoryView,
+    video_view::VideoView,
+};
 
 enum MainView {
     Program,
     Memory,
     Serial,
+    History,
+    Video,
 }
 
 pub struct EmulatorView {
-    device: Box<Device<'static>>,
+    /// Runs the device
+    emulator: Emulator,
+    /// The device as of the last snapshot of the emulator, only shown, edits are sent as commands
+    device: Box<Device>,
+    /// Whether the emulator can go back in time
+    reversible: bool,
     active_view: MainView,
     scrollfollowing: bool,
     perm_scrollfollowing: bool,
+    /// The LCD output, uploaded again on every repaint
+    screen_texture: Option<TextureHandle>,
+    /// Why the last command failed, e.g. going back in time, or the snapshot could not be shown
+    command_error: Option<String>,
 }
 
+/// The LCD is shown at twice its size
+const SCREEN_SCALE: f32 = 2.0;
+
 impl EmulatorView {
-    pub fn new(device: Box<Device<'static>>) -> EmulatorView {
+    /// Starts running the device on the emulator thread
+    pub(crate) fn new(device: Device) -> EmulatorView {
+        let shown = Box::new(device.mirror());
+        let emulator = Emulator::start(device);
+        emulator.send(Command::Run);
+
         EmulatorView {
-            device,
+            emulator,
+            device: shown,
+            reversible: false,
             active_view: MainView::Program,
             scrollfollowing: false,
             perm_scrollfollowing: false,
+            screen_texture: None,
+            command_error: None,
         }
     }
-}
 
-pub fn run_emulator(device: &mut Device) -> Result<JoinHandle<()>, String> {
-    if device.running {
-        return Err("Emulator is already running".to_string());
+    /// Stops the emulator thread, `None` if the emulation aborted
+    pub(crate) fn stop(self) -> Option<Device> {
+        self.emulator.stop()
     }
-    unsafe {
-        let raw_device_pointer = device as *mut Device as usize;
-        Ok(thread::spawn(move || {
-            let raw_device = raw_device_pointer as *mut Device;
-            let _ = <*mut Device>::as_mut(raw_device).unwrap().run();
-        }))
+
+    /// Shows the snapshot the emulator published since the last repaint, if any
+    fn show_snapshot(&mut self) {
+        let Some(snapshot) = self.emulator.take_snapshot() else {
+            return;
+        };
+        self.reversible = snapshot.reversible;
+        self.command_error = snapshot.error.clone();
+        if let Err(e) = self.device.show_snapshot(snapshot) {
+            self.command_error = Some(format!("Could not show the device, {}", e));
+        }
+    }
+
+    /// Asks for a snapshot with what the panels shown now need, it comes by the next repaint
+    fn request_snapshot(&self) {
+        self.emulator.send(Command::Inspect(Panels {
+            history: matches!(self.active_view, MainView::History),
+            writes: matches!(self.active_view, MainView::Memory),
+        }));
+    }
+
+    fn screen_ui(&mut self, ui: &mut egui::Ui) {
+        let image = ColorImage::from_rgba_unmultiplied(
+            [WIDTH as usize, HEIGHT as usize],
+            &self.device.screen,
+        );
+        let texture = match &mut self.screen_texture {
+            Some(texture) => {
+                texture.set(image, TextureOptions::NEAREST);
+                texture
+            }
+            None => self.screen_texture.insert(ui.ctx().load_texture(
+                "lcd",
+                image,
+                TextureOptions::NEAREST,
+            )),
+        };
+
+        ui.image((
+            texture.id(),
+            egui::vec2(WIDTH as f32, HEIGHT as f32) * SCREEN_SCALE,
+        ));
     }
 }
 
-impl Widget for EmulatorView {
-    fn ui(mut self, ui: &mut egui::Ui) -> Response {
+impl Widget for &mut EmulatorView {
+    fn ui(self, ui: &mut egui::Ui) -> Response {
+        self.show_snapshot();
+        self.request_snapshot();
         if !self.perm_scrollfollowing {
             self.scrollfollowing = false
         } else {
@@ -55,15 +131,46 @@ impl Widget for EmulatorView {
                 ui.horizontal(|ui| {
                     if self.device.running {
                         if ui.button("Pause").clicked() {
-                            self.device.running = false;
+                            self.emulator.send(Command::Pause);
                         }
                     } else {
                         if ui.button("Run").clicked() {
-                            let _ = run_emulator(&mut self.device);
+                            self.emulator.send(Command::Run);
                         }
                         if ui.button("> Step").clicked() {
-                            self.device.running = false;
-                            self.device.step();
+                            self.emulator.send(Command::Step);
+                            self.scrollfollowing = true
+                        }
+                        if ui.button("Step over").clicked() {
+                            self.emulator.send(Command::StepOver);
+                            self.scrollfollowing = true
+                        }
+                        let in_routine = self.device.cpu.call_stack.depth() > 0;
+                        if ui
+                            .add_enabled(in_routine, egui::Button::new("Step out"))
+                            .clicked()
+                        {
+                            self.emulator.send(Command::StepOut);
+                            self.scrollfollowing = true
+                        }
+                        if ui.button("Frame").clicked() {
+                            self.emulator.send(Command::Frame);
+                        }
+
+                        if ui
+                            .add_enabled(self.reversible, egui::Button::new("< Step back"))
+                            .on_hover_text("Go back to before the last instruction")
+                            .clicked()
+                        {
+                            self.emulator.send(Command::StepBack);
+                            self.scrollfollowing = true
+                        }
+                        if ui
+                            .add_enabled(self.reversible, egui::Button::new("Reverse"))
+                            .on_hover_text("Go back to the last breakpoint hit")
+                            .clicked()
+                        {
+                            self.emulator.send(Command::ReverseContinue);
                             self.scrollfollowing = true
                         }
                     }
@@ -75,15 +182,22 @@ impl Widget for EmulatorView {
                             ui.add_enabled_ui(self.device.speed_multiplier > 0.059, |ui| {
                                 if ui.button("-").clicked() {
                                     self.device.speed_multiplier -= 0.05;
+                                    self.emulator
+                                        .send(Command::SetSpeed(self.device.speed_multiplier));
                                 }
                             });
                             ui.label(format!("{:.2}x", self.device.speed_multiplier));
                             if ui.button("+").clicked() {
                                 self.device.speed_multiplier += 0.05;
+                                self.emulator
+                                    .send(Command::SetSpeed(self.device.speed_multiplier));
                             }
                         });
                     });
                 });
+                if let Some(error) = &self.command_error {
+                    ui.colored_label(Color32::from_rgb(255, 25, 0), error);
+                }
 
                 ui.separator();
 
@@ -91,7 +205,9 @@ impl Widget for EmulatorView {
                     .default_open(true)
                     .show(ui, |ui| {
                         CPURegisterView {
+                            editable: !self.device.running,
                             cpu: &self.device.cpu,
+                            emulator: &self.emulator,
                         }
                         .ui(ui)
                     });
@@ -100,7 +216,9 @@ impl Widget for EmulatorView {
                     .default_open(true)
                     .show(ui, |ui| {
                         IORegisterView {
-                            registers: &self.device.mem_map.io_registers,
+                            editable: !self.device.running,
+                            mem_map: &self.device.mem_map,
+                            emulator: &self.emulator,
                         }
                         .ui(ui)
                     });
@@ -113,108 +231,36 @@ impl Widget for EmulatorView {
                                 "IME: {:?}",
                                 &self.device.cpu.interrupt_master_enable
                             ));
-                            ui.label(format!(
-                                "IE: {:#02X}",
-                                &self.device.mem_map.io().ie_register.0
-                            ));
-                            ui.label(format!(
-                                "IF: {:#02X}",
-                                &self.device.mem_map.io().if_register.0
-                            ));
-                            ui.columns(3, |columns| {
-                                columns[0].label("");
-                                columns[1].label(RichText::new("enabled?").size(10.0));
-                                columns[2].label(RichText::new("requested?").size(10.0));
-
-                                columns[0].label("V-Blank: ");
-                                columns[1].label(format!(
-                                    "{}",
-                                    self.device
-                                        .mem_map
-                                        .io()
-                                        .ie_register
-                                        .is_vblank_handler_enabled()
-                                ));
-                                columns[2].label(format!(
-                                    "{}",
-                                    self.device
-                                        .mem_map
-                                        .io()
-                                        .if_register
-                                        .is_requested(InterruptType::VBlank)
-                                ));
-                                columns[0].label("LCD Stat: ");
-                                columns[1].label(format!(
-                                    "{}",
-                                    self.device
-                                        .mem_map
-                                        .io()
-                                        .ie_register
-                                        .is_lcd_handler_enabled()
-                                ));
-                                columns[2].label(format!(
-                                    "{}",
-                                    self.device
-                                        .mem_map
-                                        .io()
-                                        .if_register
-                                        .is_requested(InterruptType::LCDStat)
-                                ));
-                                columns[0].label("Timer: ");
-                                columns[1].label(format!(
-                                    "{}",
-                                    self.device
-                                        .mem_map
-                                        .io()
-                                        .ie_register
-                                        .is_timer_handler_enabled()
-                                ));
-                                columns[2].label(format!(
-                                    "{}",
-                                    self.device
-                                        .mem_map
-                                        .io()
-                                        .if_register
-                                        .is_requested(InterruptType::Timer)
-                                ));
-                                columns[0].label("Serial: ");
-                                columns[1].label(format!(
-                                    "{}",
-                                    self.device
-                                        .mem_map
-                                        .io()
-                                        .ie_register
-                                        .is_serial_handler_enabled()
-                                ));
-                                columns[2].label(format!(
-                                    "{}",
-                                    self.device
-                                        .mem_map
-                                        .io()
-                                        .if_register
-                                        .is_requested(InterruptType::Serial)
-                                ));
-                                columns[0].label("Joypad: ");
-                                columns[1].label(format!(
-                                    "{}",
-                                    self.device
-                                        .mem_map
-                                        .io()
-                                        .ie_register
-                                        .is_joypad_handler_enabled()
-                                ));
-                                columns[2].label(format!(
-                                    "{}",
-                                    self.device
-                                        .mem_map
-                                        .io()
-                                        .if_register
-                                        .is_requested(InterruptType::Joypad)
-                                ));
-                            });
+                            InterruptView {
+                                editable: !self.device.running,
+                                mem_map: &self.device.mem_map,
+                                emulator: &self.emulator,
+                            }
+                            .ui(ui);
                         });
                     });
 
+                CollapsingHeader::new("Call Stack")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        CallStackView {
+                            call_stack: &self.device.cpu.call_stack,
+                            pc: *self.device.cpu.register_set.pc(),
+                            symbols: &self.device.symbols,
+                        }
+                        .ui(ui)
+                    });
+
+                CollapsingHeader::new("Breakpoints")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        BreakpointView {
+                            device: &self.device,
+                            emulator: &self.emulator,
+                        }
+                        .ui(ui)
+                    });
+
                 ui.separator();
 
                 ui.checkbox(
@@ -223,6 +269,17 @@ impl Widget for EmulatorView {
                 );
             });
 
+            SidePanel::right("side_panel_r").show_inside(ui, |ui| {
+                self.screen_ui(ui);
+
+                ui.separator();
+                ui.label("Serial Output");
+                // ui.add_sized(
+                //     ui.available_size().min(egui::Vec2 { x: 400.0, y: 600.0 }),
+                //     SerialOutputView::new(&mut self.device),
+                // );
+            });
+
             CentralPanel::default().show_inside(ui, |ui| {
                 let cartridge = &self.device.cartridge;
                 ui.heading(cartridge.get_title());
@@ -245,6 +302,8 @@ impl Widget for EmulatorView {
                     let program_button = ui.button("program");
                     let memory_button = ui.button("memory");
                     let serial_button = ui.button("serial");
+                    let history_button = ui.button("history");
+                    let video_button = ui.button("video");
                     match self.active_view {
                         MainView::Program => {
                             program_button.enabled();
@@ -255,6 +314,12 @@ impl Widget for EmulatorView {
                         MainView::Serial => {
                             serial_button.enabled();
                         }
+                        MainView::History => {
+                            history_button.enabled();
+                        }
+                        MainView::Video => {
+                            video_button.enabled();
+                        }
                     };
                     if program_button.clicked() {
                         self.active_view = MainView::Program;
@@ -265,9 +330,32 @@ impl Widget for EmulatorView {
                     if serial_button.clicked() {
                         self.active_view = MainView::Serial;
                     }
+                    if history_button.clicked() {
+                        self.active_view = MainView::History;
+                    }
+                    if video_button.clicked() {
+                        self.active_view = MainView::Video;
+                    }
                 });
 
-                ui.add(AsmTextTable::new(&mut self.device, self.scrollfollowing));
+                match self.active_view {
+                    MainView::Memory => ui.add(MemoryView {
+                        device: &self.device,
+                        emulator: &self.emulator,
+                    }),
+                    MainView::History => ui.add(HistoryView {
+                        history: &self.device.history,
+                        symbols: &self.device.symbols,
+                    }),
+                    MainView::Video => ui.add(VideoView {
+                        device: &self.device,
+                    }),
+                    _ => ui.add(AsmTextTable::new(
+                        &self.device,
+                        &self.emulator,
+                        self.scrollfollowing,
+                    )),
+                };
 
                 // match self.active_view {
                 //     MainView::Program => {
@@ -288,14 +376,6 @@ impl Widget for EmulatorView {
                 //     }
                 // };
             });
-
-            SidePanel::right("side_panel_r").show_inside(ui, move |ui| {
-                ui.label("Serial Output");
-                // ui.add_sized(
-                //     ui.available_size().min(egui::Vec2 { x: 400.0, y: 600.0 }),
-                //     SerialOutputView::new(&mut self.device),
-                // );
-            });
         })
         .response
     }
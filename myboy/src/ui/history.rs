@@ -0,0 +1,42 @@
+use egui::{RichText, ScrollArea, TextStyle};
+
+use crate::{debugger::history::ExecutionHistory, disasm::symbols::SymbolTable};
+
+use super::asm_text::scroll_to_address;
+
+/**
+ * The last executed instructions, newest at the bottom.
+ * Clicking an entry shows its address in the program view.
+ */
+pub struct HistoryView<'a> {
+    pub history: &'a ExecutionHistory,
+    pub symbols: &'a SymbolTable,
+}
+
+impl egui::Widget for HistoryView<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let row_height = TextStyle::Monospace.resolve(ui.style()).size + 4.0;
+        let entries = self.history.entries();
+
+        ui.vertical(|ui| {
+            ui.label(format!("Last {} instructions", entries.len()));
+
+            ScrollArea::vertical().stick_to_bottom(true).show_rows(
+                ui,
+                row_height,
+                entries.len(),
+                |ui, rows| {
+                    for state in entries.range(rows) {
+                        let text =
+                            RichText::new(ExecutionHistory::format_entry(state, self.symbols))
+                                .monospace();
+                        if ui.link(text).clicked() {
+                            scroll_to_address(ui, *state.register_set.pc());
+                        }
+                    }
+                },
+            );
+        })
+        .response
+    }
+}
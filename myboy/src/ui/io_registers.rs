@@ -1,96 +1,306 @@
-use egui::RichText;
+use egui::{Checkbox, DragValue, RichText};
 
-use crate::io::io_registers::IORegisters;
+use crate::{
+    device::{
+        emulator::{Command, Emulator},
+        mem_map::MemMap,
+    },
+    io::if_register::InterruptType,
+};
 
+const TIMER_CLOCKS: [&str; 4] = ["4096 Hz", "262144 Hz", "65536 Hz", "16384 Hz"];
+
+/**
+ * The IO registers. While the emulator is paused they can be edited, the decoded
+ * bits of LCDC, STAT and TAC with toggles. Values are written through the memory map
+ * of the emulator, so a write has the same side effects as one by the CPU,
+ * e.g. writing DIV resets it.
+ */
 pub struct IORegisterView<'a> {
-    pub registers: &'a IORegisters,
+    pub mem_map: &'a MemMap,
+    pub emulator: &'a Emulator,
+    pub editable: bool,
 }
 
 impl egui::Widget for IORegisterView<'_> {
     fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let IORegisterView {
+            mem_map,
+            emulator,
+            editable,
+        } = self;
+        let edits = editable.then_some(emulator);
         ui.vertical(|ui| {
             ui.label(RichText::new("Serial Data").underline());
             ui.horizontal(|ui| {
                 ui.label("SB (Serial transfer #FF01):");
-                ui.label(format!("0x{:02X}", self.registers.read_byte(0xff01)));
+                byte_field(ui, mem_map, edits, 0xff01);
             });
             ui.horizontal(|ui| {
                 ui.label("SC (Serial control #FF02):");
-                ui.label(format!("0x{:02X}", self.registers.read_byte(0xff02)));
+                byte_field(ui, mem_map, edits, 0xff02);
             });
 
             ui.label(RichText::new("Timers").underline());
             ui.horizontal(|ui| {
                 ui.label("DIV (Divider #FF04):");
-                ui.label(format!("0x{:02X}", self.registers.read_byte(0xff04)))
-                    .on_hover_text(format!("System Counter: {:#X}", self.registers.timers.sys));
+                byte_field(ui, mem_map, edits, 0xff04).on_hover_text(format!(
+                    "System Counter: {:#X}, any write resets it",
+                    mem_map.io().timers.sys
+                ));
             });
             ui.horizontal(|ui| {
                 ui.label("TIMA (Timer #FF05):");
-                ui.label(format!("0x{:02X}", self.registers.read_byte(0xff05)));
+                byte_field(ui, mem_map, edits, 0xff05);
             });
             ui.horizontal(|ui| {
                 ui.label("TMA (Timer Modulo #FF06):");
-                ui.label(format!("0x{:02X}", self.registers.read_byte(0xff06)));
+                byte_field(ui, mem_map, edits, 0xff06);
             });
             ui.horizontal(|ui| {
                 ui.label("TAC (Timer Control #FF07):");
-                ui.label(format!("0x{:02X}", self.registers.read_byte(0xff07)));
-            });
-
-            let lcdc_reg = self.registers.get_lcdc_register();
-            ui.label(RichText::new("LCD-Control Register (#FF40)").underline());
-            ui.horizontal(|ui| {
-                ui.label("LCD Enabled:");
-                ui.label(format!("{}", lcdc_reg.lcd_enabled()));
-            });
-            ui.horizontal(|ui| {
-                ui.label("Window Tile Map Display Select:");
-                ui.label(format!("{}", lcdc_reg.window_tile_map_bank()));
-            });
-            ui.horizontal(|ui| {
-                ui.label("Window Display Enabled:");
-                ui.label(format!("{}", lcdc_reg.window_enabled()));
-            });
-            ui.horizontal(|ui| {
-                ui.label("BG & Window Tile Data Select:");
-                ui.label(format!("{}", lcdc_reg.bgwin_tile_data_area()));
+                byte_field(ui, mem_map, edits, 0xff07);
             });
+            let tac = mem_map.io().read_byte(0xff07);
             ui.horizontal(|ui| {
-                ui.label("BG Tile Map Display Select:");
-                ui.label(format!("{}", lcdc_reg.bg_tile_map_bank()));
-            });
-            ui.horizontal(|ui| {
-                ui.label("Sprite Size:");
-                ui.label(format!("{}", lcdc_reg.obj_size()));
-            });
-            ui.horizontal(|ui| {
-                ui.label("Sprite Display Enabled:");
-                ui.label(format!("{}", lcdc_reg.obj_enabled()));
-            });
-            ui.horizontal(|ui| {
-                ui.label("BG Display:");
-                ui.label(format!("{}", lcdc_reg.bgwin_enabled()));
+                bit_toggle(ui, mem_map, edits, 0xff07, 2, "Timer Enabled");
+                ui.add_enabled_ui(editable, |ui| {
+                    egui::ComboBox::from_id_salt("tac_clock")
+                        .selected_text(TIMER_CLOCKS[(tac & 0b11) as usize])
+                        .show_ui(ui, |ui| {
+                            for (clock, name) in TIMER_CLOCKS.iter().enumerate() {
+                                let selected = (tac & 0b11) as usize == clock;
+                                if ui.selectable_label(selected, *name).clicked() {
+                                    let value = tac & !0b11 | clock as u8;
+                                    emulator.send(Command::WriteByte(0xff07, value));
+                                }
+                            }
+                        });
+                });
             });
 
+            let lcdc_reg = mem_map.io().get_lcdc_register();
+            ui.label(RichText::new("LCD-Control Register (#FF40)").underline());
+            let area = |bank: u8| match bank {
+                0 => "9800-9BFF",
+                _ => "9C00-9FFF",
+            };
+            let lcdc_bits = [
+                (7, lcdc_reg.lcd_enabled(), "LCD Enabled".to_string()),
+                (
+                    6,
+                    lcdc_reg.window_tile_map_bank() == 1,
+                    format!(
+                        "Window Tile Map Display Select: {}",
+                        area(lcdc_reg.window_tile_map_bank())
+                    ),
+                ),
+                (
+                    5,
+                    lcdc_reg.window_enabled(),
+                    "Window Display Enabled".to_string(),
+                ),
+                (
+                    4,
+                    lcdc_reg.bgwin_tile_data_area() == 1,
+                    format!(
+                        "BG & Window Tile Data Select: {}",
+                        match lcdc_reg.bgwin_tile_data_area() {
+                            0 => "8800-97FF",
+                            _ => "8000-8FFF",
+                        }
+                    ),
+                ),
+                (
+                    3,
+                    lcdc_reg.bg_tile_map_bank() == 1,
+                    format!(
+                        "BG Tile Map Display Select: {}",
+                        area(lcdc_reg.bg_tile_map_bank())
+                    ),
+                ),
+                (
+                    2,
+                    lcdc_reg.obj_size() == 1,
+                    format!(
+                        "Sprite Size: {}",
+                        match lcdc_reg.obj_size() {
+                            0 => "8x8",
+                            _ => "8x16",
+                        }
+                    ),
+                ),
+                (
+                    1,
+                    lcdc_reg.obj_enabled(),
+                    "Sprite Display Enabled".to_string(),
+                ),
+                (0, lcdc_reg.bgwin_enabled(), "BG Display".to_string()),
+            ];
+            for (bit, value, text) in lcdc_bits {
+                toggle(ui, mem_map, edits, 0xff40, bit, value, &text);
+            }
+
             ui.separator();
 
             ui.label(RichText::new("LCD").underline());
             ui.horizontal(|ui| {
                 ui.label("LCDSTAT (Status #FF41):");
-                ui.label(format!("0x{:02X}", self.registers.get_lcdstat()));
+                byte_field(ui, mem_map, edits, 0xff41);
             });
+            let stat = mem_map.io().get_lcdstat();
+            for (bit, text) in [
+                (6, "LYC=LY Interrupt"),
+                (5, "Mode 2 (OAM) Interrupt"),
+                (4, "Mode 1 (VBlank) Interrupt"),
+                (3, "Mode 0 (HBlank) Interrupt"),
+            ] {
+                bit_toggle(ui, mem_map, edits, 0xff41, bit, text);
+            }
+            // kept up to date by the PPU
+            ui.label(format!(
+                "LYC=LY: {}, Mode: {}",
+                stat & 0b100 != 0,
+                stat & 0b11
+            ));
             ui.horizontal(|ui| {
                 ui.label("LY (Line Register #FF44):");
-                ui.label(format!("{}", self.registers.get_lcd_ly()));
+                ui.label(format!("{}", mem_map.io().get_lcd_ly()));
             });
 
             ui.label(RichText::new("General IO Registers (#FF00)").underline());
             ui.horizontal(|ui| {
                 ui.label("#FF00:");
-                ui.label(format!("0x{:02X}", self.registers.read_byte(0xff00)));
+                byte_field(ui, mem_map, edits, 0xff00);
             });
         })
         .response
     }
 }
+
+/**
+ * The enabled (IE) and requested (IF) interrupts, toggled bit by bit while paused.
+ */
+pub struct InterruptView<'a> {
+    pub mem_map: &'a MemMap,
+    pub emulator: &'a Emulator,
+    pub editable: bool,
+}
+
+impl egui::Widget for InterruptView<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let InterruptView {
+            mem_map,
+            emulator,
+            editable,
+        } = self;
+        let edits = editable.then_some(emulator);
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                ui.label("IE:");
+                byte_field(ui, mem_map, edits, 0xffff);
+                ui.label("IF:");
+                byte_field(ui, mem_map, edits, 0xff0f);
+            });
+
+            egui::Grid::new("interrupts").show(ui, |ui| {
+                ui.label("");
+                ui.label(RichText::new("enabled?").size(10.0));
+                ui.label(RichText::new("requested?").size(10.0));
+                ui.end_row();
+
+                let ie = &mem_map.io().ie_register;
+                let interrupts = [
+                    (
+                        InterruptType::VBlank,
+                        "V-Blank: ",
+                        ie.is_vblank_handler_enabled(),
+                    ),
+                    (
+                        InterruptType::LCDStat,
+                        "LCD Stat: ",
+                        ie.is_lcd_handler_enabled(),
+                    ),
+                    (
+                        InterruptType::Timer,
+                        "Timer: ",
+                        ie.is_timer_handler_enabled(),
+                    ),
+                    (
+                        InterruptType::Serial,
+                        "Serial: ",
+                        ie.is_serial_handler_enabled(),
+                    ),
+                    (
+                        InterruptType::Joypad,
+                        "Joypad: ",
+                        ie.is_joypad_handler_enabled(),
+                    ),
+                ];
+                for (interrupt, name, enabled) in interrupts {
+                    let requested = mem_map.io().if_register.is_requested(interrupt);
+                    let bit = (interrupt as u8).trailing_zeros() as u8;
+
+                    ui.label(name);
+                    toggle(ui, mem_map, edits, 0xffff, bit, enabled, "");
+                    toggle(ui, mem_map, edits, 0xff0f, bit, requested, "");
+                    ui.end_row();
+                }
+            });
+        })
+        .response
+    }
+}
+
+/// A hex byte, sent to `edits` to be written through its memory map, `None` while running
+fn byte_field(
+    ui: &mut egui::Ui,
+    mem_map: &MemMap,
+    edits: Option<&Emulator>,
+    address: u16,
+) -> egui::Response {
+    let mut value = mem_map.peek_byte(address);
+    let Some(emulator) = edits else {
+        return ui.label(format!("0x{:02X}", value));
+    };
+
+    let response = ui.add(
+        DragValue::new(&mut value)
+            .hexadecimal(2, false, true)
+            .prefix("0x"),
+    );
+    if response.changed() {
+        emulator.send(Command::WriteByte(address, value));
+    }
+    response
+}
+
+/// A checkbox for one bit of the register at `address`
+fn bit_toggle(
+    ui: &mut egui::Ui,
+    mem_map: &MemMap,
+    edits: Option<&Emulator>,
+    address: u16,
+    bit: u8,
+    text: &str,
+) {
+    let value = mem_map.peek_byte(address) & (1 << bit) != 0;
+    toggle(ui, mem_map, edits, address, bit, value, text);
+}
+
+/// Like `bit_toggle`, with the current value of the bit taken from a decoded accessor
+fn toggle(
+    ui: &mut egui::Ui,
+    mem_map: &MemMap,
+    edits: Option<&Emulator>,
+    address: u16,
+    bit: u8,
+    mut value: bool,
+    text: &str,
+) {
+    let response = ui.add_enabled(edits.is_some(), Checkbox::new(&mut value, text));
+    if let Some(emulator) = edits.filter(|_| response.changed()) {
+        let byte = mem_map.peek_byte(address);
+        emulator.send(Command::WriteByte(address, byte ^ (1 << bit)));
+    }
+}
@@ -0,0 +1,329 @@
+use egui::{Color32, Id, Key, Label, RichText, ScrollArea, Sense, TextEdit, TextStyle};
+
+use crate::{
+    Device,
+    debugger::{breakpoints::parse_address, stepping::FRAME_CYCLES},
+    device::{
+        emulator::{Command, Emulator},
+        mem_map::{is_editable, region_name},
+    },
+};
+
+const BYTES_PER_ROW: u16 = 16;
+const BANK_SIZE: u32 = 0x4000;
+/// Writes are highlighted for this many cycles, fading out
+const RECENT_WRITE_CYCLES: u64 = 30 * FRAME_CYCLES;
+
+/**
+ * The memory being shown: the CPU address space or a single ROM bank as stored on the cartridge.
+ */
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MemorySource {
+    AddressSpace,
+    RomBank(u16),
+}
+
+impl MemorySource {
+    fn start(&self) -> u16 {
+        match self {
+            MemorySource::AddressSpace | MemorySource::RomBank(0) => 0x0000,
+            MemorySource::RomBank(_) => 0x4000,
+        }
+    }
+
+    fn length(&self) -> u32 {
+        match self {
+            MemorySource::AddressSpace => 0x10000,
+            MemorySource::RomBank(_) => BANK_SIZE,
+        }
+    }
+
+    fn contains(&self, address: u16) -> bool {
+        let offset = address.wrapping_sub(self.start()) as u32;
+        offset < self.length()
+    }
+
+    fn read(&self, device: &Device, address: u16) -> u8 {
+        match self {
+            MemorySource::AddressSpace => device.mem_map.peek_byte(address),
+            MemorySource::RomBank(bank) => device.cartridge.read_banked_byte(*bank, address),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct MemoryViewState {
+    source: MemorySource,
+    goto_input: String,
+    search_input: String,
+    /// The last match of the search, highlighted until the next search
+    found: Option<(u16, usize)>,
+    /// The address being edited, with the text entered so far
+    editing: Option<(u16, String)>,
+    scroll_target: Option<u16>,
+    message: Option<String>,
+}
+
+impl Default for MemoryViewState {
+    fn default() -> Self {
+        MemoryViewState {
+            source: MemorySource::AddressSpace,
+            goto_input: String::new(),
+            search_input: String::new(),
+            found: None,
+            editing: None,
+            scroll_target: None,
+            message: None,
+        }
+    }
+}
+
+/**
+ * A hex view of the address space or a ROM bank, 16 bytes per row, with the memory area
+ * of every row. Recently written bytes are highlighted.
+ *
+ * Double clicking a byte edits it, the new value is written through the memory map,
+ * so only areas the memory map can write are editable.
+ */
+pub struct MemoryView<'a> {
+    pub device: &'a Device,
+    pub emulator: &'a Emulator,
+}
+
+impl egui::Widget for MemoryView<'_> {
+    fn ui(mut self, ui: &mut egui::Ui) -> egui::Response {
+        let state_id = Id::new("memory_view_state");
+        let mut state = ui.data_mut(|data| {
+            data.get_temp::<MemoryViewState>(state_id)
+                .unwrap_or_default()
+        });
+
+        let response = ui
+            .vertical(|ui| {
+                self.toolbar(ui, &mut state);
+                if let Some(message) = &state.message {
+                    ui.colored_label(Color32::from_rgb(255, 25, 0), message);
+                }
+                ui.separator();
+                self.rows(ui, &mut state);
+            })
+            .response;
+
+        ui.data_mut(|data| data.insert_temp(state_id, state));
+        response
+    }
+}
+
+impl MemoryView<'_> {
+    fn toolbar(&mut self, ui: &mut egui::Ui, state: &mut MemoryViewState) {
+        ui.horizontal(|ui| {
+            let source_text = |source: MemorySource| match source {
+                MemorySource::AddressSpace => "Address space".to_string(),
+                MemorySource::RomBank(bank) => format!("ROM bank {:02x}", bank),
+            };
+            egui::ComboBox::from_id_salt("memory_source")
+                .selected_text(source_text(state.source))
+                .show_ui(ui, |ui| {
+                    let banks = self.device.cartridge.get_rom_bank_count();
+                    let sources = std::iter::once(MemorySource::AddressSpace)
+                        .chain((0..banks).map(MemorySource::RomBank));
+                    for source in sources {
+                        if ui
+                            .selectable_label(state.source == source, source_text(source))
+                            .clicked()
+                        {
+                            state.source = source;
+                            state.found = None;
+                            state.editing = None;
+                            state.scroll_target = Some(source.start());
+                        }
+                    }
+                });
+
+            let goto = ui.add(
+                TextEdit::singleline(&mut state.goto_input)
+                    .hint_text("c000 or label")
+                    .desired_width(100.0),
+            );
+            let goto_submitted =
+                goto.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter));
+            if ui.button("Go to").clicked() || goto_submitted {
+                state.message = None;
+                match parse_address(&state.goto_input, &self.device.symbols) {
+                    Ok(address) if state.source.contains(address) => {
+                        state.scroll_target = Some(address)
+                    }
+                    Ok(address) => {
+                        state.message = Some(format!("0x{:04x} is not in this bank", address))
+                    }
+                    Err(e) => state.message = Some(e),
+                }
+            }
+
+            let search = ui.add(
+                TextEdit::singleline(&mut state.search_input)
+                    .hint_text("3e 01 ?? e0")
+                    .desired_width(140.0),
+            );
+            let search_submitted =
+                search.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter));
+            if ui.button("Find next").clicked() || search_submitted {
+                state.message = None;
+                match parse_pattern(&state.search_input) {
+                    Ok(pattern) => self.find_next(state, &pattern),
+                    Err(e) => state.message = Some(e),
+                }
+            }
+        });
+    }
+
+    /**
+     * Looks for `pattern` after the last match, wrapping around at the end.
+     */
+    fn find_next(&self, state: &mut MemoryViewState, pattern: &[Option<u8>]) {
+        let source = state.source;
+        let length = source.length();
+        let first = match state.found {
+            Some((address, _)) => address.wrapping_sub(source.start()) as u32 + 1,
+            None => 0,
+        };
+
+        let matches_at = |offset: u32| {
+            pattern.iter().enumerate().all(|(index, byte)| {
+                let address = source.start().wrapping_add((offset + index as u32) as u16);
+                byte.is_none_or(|byte| source.read(self.device, address) == byte)
+            })
+        };
+
+        let found = (0..length)
+            .map(|index| (first + index) % length)
+            .filter(|offset| offset + pattern.len() as u32 <= length)
+            .find(|offset| matches_at(*offset));
+
+        match found {
+            Some(offset) => {
+                let address = source.start().wrapping_add(offset as u16);
+                state.found = Some((address, pattern.len()));
+                state.scroll_target = Some(address);
+            }
+            None => {
+                state.found = None;
+                state.message = Some("Pattern not found".to_string());
+            }
+        }
+    }
+
+    fn rows(&mut self, ui: &mut egui::Ui, state: &mut MemoryViewState) {
+        let row_height = TextStyle::Monospace.resolve(ui.style()).size + 2.0;
+        let row_count = (state.source.length() / BYTES_PER_ROW as u32) as usize;
+
+        let mut scroll_area = ScrollArea::vertical().auto_shrink(false);
+        if let Some(address) = state.scroll_target.take() {
+            let row = address.wrapping_sub(state.source.start()) / BYTES_PER_ROW;
+            let spacing = ui.spacing().item_spacing.y;
+            scroll_area = scroll_area.vertical_scroll_offset(row as f32 * (row_height + spacing));
+        }
+
+        scroll_area.show_rows(ui, row_height, row_count, |ui, rows| {
+            for row in rows {
+                let row_address = state
+                    .source
+                    .start()
+                    .wrapping_add(row as u16 * BYTES_PER_ROW);
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(format!("{:04x}", row_address)).monospace());
+                    ui.add_space(8.0);
+
+                    for column in 0..BYTES_PER_ROW {
+                        self.byte_cell(ui, state, row_address + column);
+                    }
+                    ui.add_space(8.0);
+
+                    let text = (0..BYTES_PER_ROW)
+                        .map(|column| state.source.read(self.device, row_address + column))
+                        .map(|byte| match byte {
+                            0x20..=0x7e => byte as char,
+                            _ => '.',
+                        })
+                        .collect::<String>();
+                    ui.label(RichText::new(text).monospace());
+                    ui.add_space(8.0);
+
+                    let region = match state.source {
+                        MemorySource::AddressSpace => region_name(row_address),
+                        MemorySource::RomBank(_) => "ROM",
+                    };
+                    ui.label(RichText::new(region).size(10.0).weak());
+                });
+            }
+        });
+    }
+
+    fn byte_cell(&mut self, ui: &mut egui::Ui, state: &mut MemoryViewState, address: u16) {
+        if let Some((editing_address, text)) = &mut state.editing
+            && *editing_address == address
+        {
+            let response = ui.add(
+                TextEdit::singleline(text)
+                    .font(TextStyle::Monospace)
+                    .desired_width(16.0)
+                    .char_limit(2),
+            );
+            response.request_focus();
+
+            if ui.input(|input| input.key_pressed(Key::Escape)) {
+                state.editing = None;
+            } else if response.lost_focus() {
+                match u8::from_str_radix(text.trim(), 16) {
+                    Ok(value) => self.emulator.send(Command::WriteByte(address, value)),
+                    Err(_) => state.message = Some(format!("Invalid byte '{}'", text)),
+                }
+                state.editing = None;
+            }
+            return;
+        }
+
+        let value = state.source.read(self.device, address);
+        let mut text = RichText::new(format!("{:02x}", value)).monospace();
+
+        let age = match state.source {
+            MemorySource::AddressSpace => self.device.mem_map.writes.age(address),
+            MemorySource::RomBank(_) => None,
+        };
+        if let Some(age) = age.filter(|age| *age < RECENT_WRITE_CYCLES) {
+            let fade = 1.0 - age as f32 / RECENT_WRITE_CYCLES as f32;
+            text = text.color(Color32::from_rgb(255, 25, 0).gamma_multiply(0.3 + 0.7 * fade));
+        }
+        let is_found = state
+            .found
+            .is_some_and(|(start, length)| (address.wrapping_sub(start) as usize) < length);
+        if is_found {
+            text = text.background_color(Color32::from_rgb(70, 70, 0));
+        }
+
+        let editable = state.source == MemorySource::AddressSpace && is_editable(address);
+        let response = ui.add(Label::new(text).sense(Sense::click()));
+        if editable && response.double_clicked() {
+            state.editing = Some((address, format!("{:02x}", value)));
+        }
+        response.on_hover_text(format!("0x{:04x}", address));
+    }
+}
+
+/// Hex bytes separated by spaces, `??` matches any byte
+fn parse_pattern(pattern: &str) -> Result<Vec<Option<u8>>, String> {
+    let bytes = pattern
+        .split_whitespace()
+        .map(|byte| match byte {
+            "??" => Ok(None),
+            _ => u8::from_str_radix(byte, 16)
+                .map(Some)
+                .map_err(|_| format!("Invalid byte '{}' in the pattern", byte)),
+        })
+        .collect::<Result<Vec<Option<u8>>, String>>()?;
+
+    match bytes.is_empty() {
+        true => Err("The pattern is empty".to_string()),
+        false => Ok(bytes),
+    }
+}
@@ -1,6 +1,11 @@
 pub mod app;
 pub mod asm_text;
+pub mod breakpoints;
+pub mod call_stack;
 pub mod cpu_registers;
 pub mod emulator_view;
+pub mod history;
 pub mod io_registers;
+pub mod memory_view;
 pub mod serial_output;
+pub mod video_view;
@@ -3,7 +3,7 @@ use egui::RichText;
 use crate::Device;
 
 pub struct SerialOutputView<'a> {
-    pub device: &'a Device<'static>,
+    pub device: &'a Device,
 }
 
 impl egui::Widget for SerialOutputView<'_> {
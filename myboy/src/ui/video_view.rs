@@ -0,0 +1,335 @@
+use egui::{
+    CollapsingHeader, Color32, ColorImage, Grid, Id, Image, Pos2, Rect, RichText, Stroke,
+    StrokeKind, TextureHandle, TextureOptions, Vec2, pos2, vec2,
+};
+
+use crate::{
+    Device,
+    io::lcdc::LCDCRegister,
+    ppu::{oam::OAMEntryFlags, ppu::PPU},
+};
+
+const TILE_COUNT: usize = 384;
+const TILES_PER_ROW: usize = 16;
+const SPRITE_COUNT: usize = 40;
+/// Tiles and maps are shown at twice their size
+const SCALE: f32 = 2.0;
+
+const BGP: u16 = 0xff47;
+const OBP0: u16 = 0xff48;
+const OBP1: u16 = 0xff49;
+
+/**
+ * The PPU viewers: the tile data, both background maps with the visible area,
+ * the sprites in OAM and the palettes. They show the live VRAM on every repaint.
+ */
+pub struct VideoView<'a> {
+    pub device: &'a Device,
+}
+
+impl egui::Widget for VideoView<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        ui.vertical(|ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                CollapsingHeader::new("Tiles")
+                    .default_open(true)
+                    .show(ui, |ui| self.tiles_ui(ui));
+                CollapsingHeader::new("Background maps")
+                    .default_open(true)
+                    .show(ui, |ui| self.tile_maps_ui(ui));
+                CollapsingHeader::new("OAM")
+                    .default_open(true)
+                    .show(ui, |ui| self.oam_ui(ui));
+                CollapsingHeader::new("Palettes")
+                    .default_open(true)
+                    .show(ui, |ui| self.palettes_ui(ui));
+            });
+        })
+        .response
+    }
+}
+
+impl VideoView<'_> {
+    fn vram(&self, address: u16) -> u8 {
+        self.device.mem_map.video_ram.data[(address - 0x8000) as usize]
+    }
+
+    fn palette(&self, register: u16) -> u8 {
+        self.device.mem_map.io_registers.read_byte(register)
+    }
+
+    /// The 8 color indices of a row of the tile at `tile_address`
+    fn tile_row(&self, tile_address: u16, row: u16) -> [u8; 8] {
+        let address = tile_address + row * 2;
+        PPU::bpp_to_pixelrow(u16::from_le_bytes([
+            self.vram(address),
+            self.vram(address + 1),
+        ]))
+    }
+
+    fn tiles_ui(&self, ui: &mut egui::Ui) {
+        let width = TILES_PER_ROW * 8;
+        let height = TILE_COUNT / TILES_PER_ROW * 8;
+        let bgp = self.palette(BGP);
+
+        let mut image = ColorImage::new([width, height], Color32::BLACK);
+        for tile in 0..TILE_COUNT {
+            let tile_address = 0x8000 + tile as u16 * 16;
+            let (tile_x, tile_y) = (tile % TILES_PER_ROW * 8, tile / TILES_PER_ROW * 8);
+            for row in 0..8 {
+                for (x, color) in self.tile_row(tile_address, row).iter().enumerate() {
+                    image[(tile_x + x, tile_y + row as usize)] = shade(bgp, *color);
+                }
+            }
+        }
+
+        let response = show_image(ui, "vram_tiles", image);
+        if let Some(position) = response.hover_pos() {
+            let offset = (position - response.rect.min) / SCALE;
+            let tile = offset.y as usize / 8 * TILES_PER_ROW + offset.x as usize / 8;
+            if tile < TILE_COUNT {
+                response.on_hover_text(format!("Tile {} at 0x{:04x}", tile, 0x8000 + tile * 16));
+            }
+        }
+        ui.label(
+            RichText::new("VRAM bank 1 only exists in CGB mode, which is not emulated").weak(),
+        );
+    }
+
+    /// The address of a tile's data as the background and window see it
+    fn tile_data_address(lcdc: &LCDCRegister, tile_number: u8) -> u16 {
+        match lcdc.bgwin_tile_data_area() {
+            1 => 0x8000 + tile_number as u16 * 16,
+            _ => 0x9000u16.wrapping_add_signed(tile_number as i8 as i16 * 16),
+        }
+    }
+
+    fn tile_maps_ui(&self, ui: &mut egui::Ui) {
+        let io = &self.device.mem_map.io_registers;
+        let lcdc = io.get_lcdc_register();
+
+        ui.label(format!(
+            "SCX {}  SCY {}  WX {}  WY {}, tile data at {}",
+            io.get_scx(),
+            io.get_scy(),
+            io.get_wx(),
+            io.get_wy(),
+            match lcdc.bgwin_tile_data_area() {
+                1 => "8000-8FFF",
+                _ => "8800-97FF",
+            }
+        ));
+
+        ui.horizontal_top(|ui| {
+            for map in 0..2u8 {
+                ui.vertical(|ui| {
+                    let base: u16 = if map == 0 { 0x9800 } else { 0x9c00 };
+                    let mut roles = Vec::new();
+                    if lcdc.bg_tile_map_bank() == map {
+                        roles.push("background");
+                    }
+                    if lcdc.window_enabled() && lcdc.window_tile_map_bank() == map {
+                        roles.push("window");
+                    }
+                    ui.label(format!(
+                        "{:04X}-{:04X} {}",
+                        base,
+                        base + 0x3ff,
+                        roles.join(", ")
+                    ));
+
+                    let response = show_image(
+                        ui,
+                        &format!("vram_tile_map_{}", map),
+                        self.tile_map_image(&lcdc, base),
+                    );
+                    let painter = ui.painter_at(response.rect);
+                    let origin = response.rect.min;
+
+                    if lcdc.bg_tile_map_bank() == map {
+                        let (scx, scy) = (io.get_scx() as f32, io.get_scy() as f32);
+                        // the viewport wraps around the edges of the map
+                        for (dx, dy) in [(0.0, 0.0), (-256.0, 0.0), (0.0, -256.0), (-256.0, -256.0)]
+                        {
+                            let min = pos2(scx + dx, scy + dy);
+                            draw_rect(
+                                &painter,
+                                origin,
+                                Rect::from_min_size(min, vec2(160.0, 144.0)),
+                                Color32::RED,
+                            );
+                        }
+                    }
+                    if lcdc.window_enabled() && lcdc.window_tile_map_bank() == map {
+                        let wx = io.get_wx() as f32 - 7.0;
+                        let wy = io.get_wy() as f32;
+                        let size = vec2((160.0 - wx).max(0.0), (144.0 - wy).max(0.0));
+                        draw_rect(
+                            &painter,
+                            origin,
+                            Rect::from_min_size(Pos2::ZERO, size),
+                            Color32::from_rgb(0, 160, 255),
+                        );
+                    }
+                });
+            }
+        });
+    }
+
+    fn tile_map_image(&self, lcdc: &LCDCRegister, base: u16) -> ColorImage {
+        let bgp = self.palette(BGP);
+        let mut image = ColorImage::new([256, 256], Color32::BLACK);
+
+        for index in 0..1024u16 {
+            let tile_address = Self::tile_data_address(lcdc, self.vram(base + index));
+            let (tile_x, tile_y) = ((index % 32) as usize * 8, (index / 32) as usize * 8);
+            for row in 0..8 {
+                for (x, color) in self.tile_row(tile_address, row).iter().enumerate() {
+                    image[(tile_x + x, tile_y + row as usize)] = shade(bgp, *color);
+                }
+            }
+        }
+
+        image
+    }
+
+    fn oam_ui(&self, ui: &mut egui::Ui) {
+        let lcdc = self.device.mem_map.io_registers.get_lcdc_register();
+        let height = if lcdc.obj_size() == 1 { 16 } else { 8 };
+
+        // all sprites side by side in one texture, every row shows its part of it
+        let mut image = ColorImage::new([SPRITE_COUNT * 8, height], Color32::TRANSPARENT);
+        for index in 0..SPRITE_COUNT {
+            let sprite = self
+                .device
+                .mem_map
+                .object_attribute_memory
+                .get_sprite(index);
+            let palette = match sprite.flags.palette() {
+                true => self.palette(OBP1),
+                false => self.palette(OBP0),
+            };
+            let tile_index = match height {
+                16 => *sprite.tile_index & 0xfe,
+                _ => *sprite.tile_index,
+            };
+            let tile_address = 0x8000 + tile_index as u16 * 16;
+
+            for row in 0..height {
+                let source_row = if sprite.flags.y_flip() {
+                    height - 1 - row
+                } else {
+                    row
+                };
+                let pixels = self.tile_row(tile_address, source_row as u16);
+                for x in 0..8 {
+                    let source_x = if sprite.flags.x_flip() { 7 - x } else { x };
+                    let color = pixels[source_x];
+                    if color != 0 {
+                        image[(index * 8 + x, row)] = shade(palette, color);
+                    }
+                }
+            }
+        }
+        let texture = load_texture(ui, "oam_sprites", image);
+
+        Grid::new("oam_table").striped(true).show(ui, |ui| {
+            for header in ["#", "", "X", "Y", "Tile", "Priority", "Flip", "Palette"] {
+                ui.strong(header);
+            }
+            ui.end_row();
+
+            for index in 0..SPRITE_COUNT {
+                let sprite = self
+                    .device
+                    .mem_map
+                    .object_attribute_memory
+                    .get_sprite(index);
+                let flags = *sprite.flags;
+
+                ui.label(format!("{}", index));
+                let uv = Rect::from_min_max(
+                    pos2(index as f32 / SPRITE_COUNT as f32, 0.0),
+                    pos2((index + 1) as f32 / SPRITE_COUNT as f32, 1.0),
+                );
+                ui.add(
+                    Image::new((texture.id(), vec2(8.0, height as f32) * SCALE))
+                        .uv(uv)
+                        .bg_fill(Color32::from_gray(40)),
+                );
+                ui.label(format!("{}", sprite.x));
+                ui.label(format!("{}", sprite.y));
+                ui.label(format!("{:02x}", sprite.tile_index));
+                ui.label(match flags.bg_priority() {
+                    true => "behind BG",
+                    false => "above BG",
+                });
+                ui.label(match (flags.x_flip(), flags.y_flip()) {
+                    (true, true) => "X Y",
+                    (true, false) => "X",
+                    (false, true) => "Y",
+                    (false, false) => "-",
+                });
+                ui.label(match flags.palette() {
+                    true => "OBP1",
+                    false => "OBP0",
+                });
+                ui.end_row();
+            }
+        });
+    }
+
+    fn palettes_ui(&self, ui: &mut egui::Ui) {
+        Grid::new("palettes").show(ui, |ui| {
+            for (name, register) in [("BGP", BGP), ("OBP0", OBP0), ("OBP1", OBP1)] {
+                let palette = self.palette(register);
+                ui.label(format!("{} {:02x}", name, palette));
+                for color in 0..4 {
+                    let (response, painter) =
+                        ui.allocate_painter(Vec2::splat(16.0), egui::Sense::hover());
+                    painter.rect_filled(response.rect, 0.0, shade(palette, color));
+                    response.on_hover_text(format!(
+                        "Color {} is shade {}",
+                        color,
+                        (palette >> (color * 2)) & 0b11
+                    ));
+                }
+                ui.end_row();
+            }
+        });
+        ui.label(RichText::new("CGB palettes are not emulated").weak());
+    }
+}
+
+/// The screen color of a color index, mapped through a DMG palette register
+fn shade(palette: u8, color: u8) -> Color32 {
+    let (r, g, b, a) = PPU::bpp_to_rgba((palette >> (color * 2)) & 0b11);
+    Color32::from_rgba_unmultiplied(r, g, b, a)
+}
+
+/// Uploads `image` to the texture named `name`, reusing the texture of the last repaint
+fn load_texture(ui: &egui::Ui, name: &str, image: ColorImage) -> TextureHandle {
+    let id = Id::new(name);
+    match ui.data(|data| data.get_temp::<TextureHandle>(id)) {
+        Some(mut texture) => {
+            texture.set(image, TextureOptions::NEAREST);
+            texture
+        }
+        None => {
+            let texture = ui.ctx().load_texture(name, image, TextureOptions::NEAREST);
+            ui.data_mut(|data| data.insert_temp(id, texture.clone()));
+            texture
+        }
+    }
+}
+
+fn show_image(ui: &mut egui::Ui, name: &str, image: ColorImage) -> egui::Response {
+    let texture = load_texture(ui, name, image);
+    ui.add(Image::new((texture.id(), texture.size_vec2() * SCALE)).sense(egui::Sense::hover()))
+}
+
+/// Outlines `rect`, given in pixels of the image shown at `origin`
+fn draw_rect(painter: &egui::Painter, origin: Pos2, rect: Rect, color: Color32) {
+    let rect = Rect::from_min_size(origin + rect.min.to_vec2() * SCALE, rect.size() * SCALE);
+    painter.rect_stroke(rect, 0.0, Stroke::new(1.0, color), StrokeKind::Inside);
+}
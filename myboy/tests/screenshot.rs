@@ -0,0 +1,106 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use bricoboy::{Outcome, ScreenshotTest};
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 144;
+
+/// A directory of its own for every test, as they run in parallel
+fn temp_dir(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("bricoboy-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+/// A ROM looping at its entry point with `jr @`, which leaves the screen blank
+fn write_idle_rom(path: &Path) {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x100..0x102].copy_from_slice(&[0x18, 0xfe]);
+    fs::write(path, rom).unwrap();
+}
+
+/// An RGB reference, as image editors save them, filled with `color` but for `dots`
+fn write_reference(path: &Path, color: u8, dots: &[(usize, [u8; 3])]) {
+    let mut pixels = vec![color; (WIDTH * HEIGHT * 3) as usize];
+    for (index, dot) in dots {
+        pixels[index * 3..index * 3 + 3].copy_from_slice(dot);
+    }
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path).unwrap()), WIDTH, HEIGHT);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&pixels).unwrap();
+}
+
+fn screenshot_test(directory: &Path, tolerance: u8) -> ScreenshotTest {
+    let rom = directory.join("idle.gb");
+    write_idle_rom(&rom);
+    ScreenshotTest {
+        rom,
+        reference: directory.join("idle.png"),
+        frames: 3,
+        tolerance,
+        timeout: None,
+    }
+}
+
+#[test]
+fn matches_a_blank_screen() {
+    let directory = temp_dir("integration-blank");
+    let test = screenshot_test(&directory, 0);
+    write_reference(&test.reference, 0xff, &[]);
+
+    assert_eq!(test.run(), Outcome::Passed);
+    assert!(!directory.join("idle.diff.png").exists());
+
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn reports_the_differing_pixels() {
+    let directory = temp_dir("integration-differing");
+    let test = screenshot_test(&directory, 0);
+    write_reference(&test.reference, 0xff, &[(100, [0x00, 0x00, 0x00])]);
+
+    let Outcome::Failed(message) = test.run() else {
+        panic!("The reference with a black pixel matched");
+    };
+    assert!(
+        message.starts_with("1 of 23040 pixels differ"),
+        "{}",
+        message
+    );
+    assert!(directory.join("idle.diff.png").exists());
+
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn allows_differences_within_the_tolerance() {
+    let directory = temp_dir("integration-tolerance");
+    write_reference(&directory.join("idle.png"), 0xfc, &[]);
+
+    assert_eq!(screenshot_test(&directory, 3).run(), Outcome::Passed);
+    assert!(matches!(
+        screenshot_test(&directory, 2).run(),
+        Outcome::Failed(_)
+    ));
+
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn a_missing_reference_is_an_error() {
+    let directory = temp_dir("integration-missing");
+    let test = screenshot_test(&directory, 0);
+
+    assert!(matches!(test.run(), Outcome::Error(message) if message.contains("idle.png")));
+
+    fs::remove_dir_all(directory).unwrap();
+}
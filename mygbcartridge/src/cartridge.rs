@@ -29,6 +29,16 @@ impl Cartridge {
         u16::from_le_bytes([self.read_byte(address), self.read_byte(address + 1)])
     }
 
+    /**
+     * Reads a byte from the given ROM bank, where `address` is the address
+     * as seen by the CPU (0x0000-0x3FFF for bank 0, 0x4000-0x7FFF for the others).
+     * Reads past the end of the ROM return 0xFF.
+     */
+    pub fn read_banked_byte(&self, bank: u16, address: u16) -> u8 {
+        let offset = (bank as usize) * 0x4000 + (address as usize & 0x3fff);
+        self.data.get(offset).copied().unwrap_or(0xff)
+    }
+
     pub fn read_fixed_bytes<const L: usize>(&self, address: u16, length: usize) -> &[u8; L] {
         match self.data[(address as usize)..(address as usize) + length].try_into() {
             Ok(bytes) => bytes,